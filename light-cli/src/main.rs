@@ -1,25 +1,118 @@
 use light::{
+    error::LightError,
+    exr::{load_exr, save_exr},
     image::Image,
-    image_filters::{average_samples, gamma_correct},
+    image_filters::{denoise, display_transform, post_process, to_working_space, DenoiseSettings},
     importing::load_from_blender,
-    trace_ray,
+    render_statistics::{self, Phase},
+    render::{render_pass, RenderBuffers},
+    scene::Scene,
+    validation::Severity,
 };
-use rayon::prelude::*;
 use std::{
     io::{stdout, Write},
-    sync::{atomic::AtomicUsize, Arc, Mutex},
+    sync::{Arc, Mutex},
     thread,
     time::Instant,
 };
-use ultraviolet::Vec3;
 
 fn main() {
-    let scene = Arc::new(load_from_blender("/tmp/blender_export.toml").unwrap());
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(|x| x.as_str()) {
+        Some("compare") => compare(&args[2..]),
+        Some("check") => check(&args[2..]),
+        _ => render(),
+    }
+}
+
+fn load_image(filename: &str) -> Result<Image, LightError> {
+    if filename.ends_with(".exr") {
+        load_exr(filename)
+    } else {
+        Image::load_from_file(filename)
+    }
+}
+
+/// `light-cli compare <image> <reference> [heatmap.ppm]`: prints error metrics and optionally saves a FLIP heatmap.
+fn compare(args: &[String]) {
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("Usage: light-cli compare <image> <reference> [heatmap.ppm]");
+        std::process::exit(2);
+    }
+    let load = |filename: &str| {
+        load_image(filename).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        })
+    };
+    let image = load(&args[0]);
+    let reference = load(&args[1]);
+    if let Err(error) = print_metrics(&image, &reference, args.get(2)) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+fn print_metrics(image: &Image, reference: &Image, heatmap: Option<&String>) -> Result<(), LightError> {
+    println!("MSE:    {:.6}", image.mse(reference)?);
+    println!("RMSE:   {:.6}", image.rmse(reference)?);
+    println!("relMSE: {:.6}", image.relative_mse(reference)?);
+    println!("PSNR:   {:.2} dB", image.psnr(reference)?);
+    println!("SSIM:   {:.4}", image.ssim(reference)?);
+    println!("FLIP:   {:.4}", image.flip(reference)?);
+    if let Some(heatmap) = heatmap {
+        image.error_heatmap(reference)?.save_to_file(heatmap)?;
+    }
+    Ok(())
+}
+
+/// Prints the problems of the scene. Returns false if there are errors.
+fn report_diagnostics(scene: &Scene) -> bool {
+    let diagnostics = scene.validate();
+    for diagnostic in &diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    !diagnostics.iter().any(|x| x.severity == Severity::Error)
+}
+
+/// `light-cli check <scene.toml>`: loads and validates a scene without rendering it.
+fn check(args: &[String]) {
+    if args.len() != 1 {
+        eprintln!("Usage: light-cli check <scene.toml>");
+        std::process::exit(2);
+    }
+    let scene = load_from_blender(&args[0]).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+    let diagnostics = scene.validate();
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic);
+    }
+    let errors = diagnostics.iter().filter(|x| x.severity == Severity::Error).count();
+    println!("{} errors, {} warnings", errors, diagnostics.len() - errors);
+    if errors > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn render() {
+    let scene = Arc::new(load_from_blender("/tmp/blender_export.toml").unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }));
+    if !report_diagnostics(&scene) {
+        std::process::exit(1);
+    }
+    render_statistics::set_enabled(scene.settings.statistics);
 
-    let protected_image: Arc<Mutex<Image>> =
-        Arc::new(Mutex::new(Image::new(scene.width, scene.height)));
-    let samples_per_pixel: usize = 1000;
-    let max_depth: i32 = 50;
+    let buffers = Mutex::new(RenderBuffers::new(&scene, scene.settings.denoise));
+    let samples_per_pixel: usize = scene.settings.samples_per_pixel;
+    let max_depth: i32 = scene.settings.max_depth;
+    let is_spectral = scene.settings.spectral;
+    let exposure = scene.settings.exposure;
+    let tone_mapper = scene.settings.tone_mapper;
+    let working_space = scene.settings.working_space;
 
     println!(
         "Rendering {}x{} image @ {} spp; depth {}...",
@@ -30,9 +123,7 @@ fn main() {
     rayon::ThreadPoolBuilder::new().num_threads(1).build_global().unwrap();
 
     let mut saving_thread: Option<thread::JoinHandle<()>> = None;
-    let scanlines = (0..scene.height).collect::<Vec<u32>>();
     let rendering_start = Instant::now();
-    let next_sample: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(1));
 
     // let ctrl_c_next_sample = next_sample.clone();
     // let ctrl_c_image = protected_image.clone();
@@ -52,78 +143,87 @@ fn main() {
     // })
     // .expect("Error setting Ctrl-C handler");
 
-    if let Some(bvh) = &scene.bvh {
-        for sample in 0..samples_per_pixel {
-            print!(
-                "\r[{:>width$}/{:>width$}][{:>6.2}%]{} Rendering...{:>10}",
-                sample + 1,
-                samples_per_pixel,
-                (sample + 1) as f32 / samples_per_pixel as f32 * 100.0,
-                match saving_thread {
-                    Some(_) => "[Saving]",
-                    None => "",
-                },
-                "",
-                width = samples_per_pixel.to_string().len()
-            );
-            stdout().flush().unwrap();
-            scanlines.par_iter().for_each_with(
-                protected_image.clone(),
-                |protected_image, y: &u32| {
-                    let mut this_row: Vec<Vec3> =
-                        vec![Vec3::new(0.0, 0.0, 0.0); scene.width as usize];
-
-                    for x in 0..scene.width {
-                        let x_offset: f32 = rand::random();
-                        let y_offset: f32 = rand::random();
-
-                        let u = (x as f32 + x_offset) / scene.width as f32;
-                        let v = (*y as f32 + y_offset) / scene.height as f32;
-
-                        let ray = scene.camera.get_ray(u, v);
-                        this_row[x as usize] += trace_ray::trace_ray(ray, bvh, max_depth);
-                    }
-                    let mut image = protected_image.lock().unwrap();
-                    for x in 0..scene.width {
-                        image[(x, *y)] += this_row[x as usize];
-                    }
-                },
-            );
-            if sample % 40 == 0 {
-                let mut image_copy = protected_image.lock().unwrap().clone();
-                let samples_until_now = sample + 1;
-                saving_thread = Some(thread::spawn(move || {
-                    image_copy
-                        .apply_filter(|x| average_samples(samples_until_now, x))
-                        .apply_filter(|x| gamma_correct(2.0, x))
-                        .save_to_file("/tmp/test.ppm")
-                        .unwrap();
-                }));
+    for sample in 0..samples_per_pixel {
+        let converged_fraction = buffers.lock().unwrap().statistics.converged_fraction();
+        print!(
+            "\r[{:>width$}/{:>width$}][{:>6.2}%][{:>6.2}% converged]{} Rendering...{:>10}",
+            sample + 1,
+            samples_per_pixel,
+            (sample + 1) as f32 / samples_per_pixel as f32 * 100.0,
+            converged_fraction * 100.0,
+            match saving_thread {
+                Some(_) => "[Saving]",
+                None => "",
+            },
+            "",
+            width = samples_per_pixel.to_string().len()
+        );
+        stdout().flush().unwrap();
+        render_pass(&scene, sample as u32, &buffers);
+        if let Some(threshold) = scene.settings.noise_threshold {
+            let statistics = &mut buffers.lock().unwrap().statistics;
+            statistics.update_convergence(threshold, scene.settings.min_samples as u32);
+            if statistics.converged_fraction() == 1.0 {
+                break;
             }
-            match &saving_thread {
-                Some(thread) => {
-                    if thread.is_finished() {
-                        saving_thread = None;
-                    }
-                }
-                None => {}
-            };
-            next_sample.store(sample + 1, std::sync::atomic::Ordering::Relaxed);
         }
-    }
-    else{
-        panic!("Scene doesn't contain a BVH");
+        if scene.settings.time_limit.is_some_and(|limit| rendering_start.elapsed().as_secs_f32() > limit) {
+            break;
+        }
+        if sample % 40 == 0 {
+            let mut image_copy = buffers.lock().unwrap().film.image();
+            let post_process_settings = scene.settings.post_process.clone();
+            saving_thread = Some(thread::spawn(move || {
+                image_copy.apply_filter(|x| to_working_space(is_spectral, working_space, x));
+                post_process(&image_copy, &post_process_settings)
+                    .apply_filter(|x| display_transform(exposure, tone_mapper, working_space, x))
+                    .save_to_file("/tmp/test.ppm")
+                    .unwrap();
+            }));
+        }
+        match &saving_thread {
+            Some(thread) => {
+                if thread.is_finished() {
+                    saving_thread = None;
+                }
+            }
+            None => {}
+        };
     }
     println!();
     println!("Rendering took {:.2?}", rendering_start.elapsed());
+    render_statistics::add_time(Phase::Render, rendering_start.elapsed());
+    if scene.settings.statistics {
+        let report = render_statistics::report();
+        print!("{}", report);
+        report.save_json("/tmp/test_statistics.json").unwrap();
+    }
 
-    protected_image
-        .lock()
-        .unwrap()
-        .apply_filter(|x| {
-            average_samples(next_sample.load(std::sync::atomic::Ordering::Relaxed), x)
-        })
-        .apply_filter(|x| gamma_correct(2.0, x))
+    let buffers = buffers.into_inner().unwrap();
+    let statistics = &buffers.statistics;
+    let mut image = buffers.film.image();
+    image.apply_filter(|x| to_working_space(is_spectral, working_space, x));
+    if scene.settings.denoise {
+        println!("Denoising...");
+        let (albedo, normal) = buffers.guide_images(&scene.settings).unwrap();
+        image
+            .clone()
+            .apply_filter(|x| display_transform(exposure, tone_mapper, working_space, x))
+            .save_to_file("/tmp/test_noisy.ppm")
+            .unwrap();
+        image = denoise(&image, &albedo, &normal, &DenoiseSettings::default());
+    }
+    let aov_layers = buffers.aovs.layers(&scene.settings);
+    let mut exr_layers = vec![("", &image)];
+    exr_layers.extend(aov_layers.iter().map(|(name, layer)| (*name, layer)));
+    save_exr("/tmp/test.exr", &exr_layers, working_space).unwrap();
+    post_process(&image, &scene.settings.post_process)
+        .apply_filter(|x| display_transform(exposure, tone_mapper, working_space, x))
         .save_to_file("/tmp/test.ppm")
         .unwrap();
+    if let Some(threshold) = scene.settings.noise_threshold {
+        statistics.noise_image(threshold).save_to_file("/tmp/test_noise.ppm").unwrap();
+    }
+    statistics.sample_count_image(samples_per_pixel).save_to_file("/tmp/test_samples.ppm").unwrap();
+    buffers.aovs.save("/tmp/test", &scene.settings).unwrap();
 }
@@ -1,10 +1,12 @@
 use light::{
-    image::Image,
-    image_filters::{average_samples, gamma_correct},
+    exr::save_exr,
+    image_filters::{denoise, display_transform, post_process, to_working_space, DenoiseSettings},
     importing::load_from_blender,
-    trace_ray, hittable::Hittable
+    render_statistics::{self, Phase},
+    render::{render_pass, RenderBuffers},
+    validation::Severity,
+    hittable::Hittable, image::Image
 };
-use rayon::prelude::*;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
@@ -17,18 +19,31 @@ use std::{
     thread,
     time::{Duration, Instant},
 };
-use ultraviolet::{self, Vec3};
 
 fn main() {
     for frame in 1..=1{
-        let scene = load_from_blender(format!("/tmp/blender_export{}.toml", frame).as_str()).unwrap();
+        render_statistics::reset();
+        let scene = load_from_blender(format!("/tmp/blender_export{}.toml", frame).as_str()).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+        let diagnostics = scene.validate();
+        for diagnostic in &diagnostics {
+            eprintln!("{}", diagnostic);
+        }
+        if diagnostics.iter().any(|x| x.severity == Severity::Error) {
+            std::process::exit(1);
+        }
         println!("Min: {:?}", scene.get_min_bounds());
         println!("Max: {:?}", scene.get_max_bounds());
+        render_statistics::set_enabled(scene.settings.statistics);
 
-        let protected_image: Arc<Mutex<Image>> =
-            Arc::new(Mutex::new(Image::new(scene.width, scene.height)));
-        let samples_per_pixel: usize = 3000;
-        let max_depth: i32 = 10;
+        // denoising can be toggled while rendering, so the guides are always needed
+        let buffers: Arc<Mutex<RenderBuffers>> = Arc::new(Mutex::new(RenderBuffers::new(&scene, true)));
+        let samples_per_pixel: usize = scene.settings.samples_per_pixel;
+        let max_depth: i32 = scene.settings.max_depth;
+        let is_spectral = scene.settings.spectral;
+        let working_space = scene.settings.working_space;
 
         println!(
             "Rendering {}x{} image @ {} spp; depth {}...",
@@ -41,11 +56,19 @@ fn main() {
         // rayon::ThreadPoolBuilder::new().num_threads(1).build_global().unwrap();
 
         let should_end = Arc::new(AtomicBool::new(false));
-        let next_sample = Arc::new(AtomicUsize::new(1));
+        // toggled with D while rendering
+        let should_denoise = Arc::new(AtomicBool::new(scene.settings.denoise));
+        let finished_passes = Arc::new(AtomicUsize::new(0));
+        // exposure and tone mapper, changed with +/- and T while rendering
+        let display_settings = Arc::new(Mutex::new((scene.settings.exposure, scene.settings.tone_mapper)));
 
-        let display_thread_image = protected_image.clone();
+        let display_thread_buffers = buffers.clone();
         let display_thread_should_end = should_end.clone();
-        let display_thread_next_sample = next_sample.clone();
+        let display_thread_should_denoise = should_denoise.clone();
+        let display_thread_finished_passes = finished_passes.clone();
+        let display_thread_display_settings = display_settings.clone();
+        let display_thread_settings = scene.settings.clone();
+        let display_thread_post_process = scene.settings.post_process.clone();
         let display_thread = thread::spawn(move || {
             // setup SDL
             let sdl_context = sdl2::init().unwrap();
@@ -84,6 +107,8 @@ fn main() {
                 .unwrap();
 
             let mut event_pump = sdl_context.event_pump().unwrap();
+            // the denoised image and the number of passes it contains
+            let mut denoised: Option<(usize, Image)> = None;
             while !display_thread_should_end.load(std::sync::atomic::Ordering::Relaxed) {
                 canvas.clear();
                 for event in event_pump.poll_iter() {
@@ -95,23 +120,54 @@ fn main() {
                         } => {
                             display_thread_should_end.store(true, std::sync::atomic::Ordering::Relaxed);
                         }
+                        Event::KeyDown {
+                            keycode: Some(Keycode::D),
+                            ..
+                        } => {
+                            display_thread_should_denoise.fetch_xor(true, std::sync::atomic::Ordering::Relaxed);
+                        }
+                        Event::KeyDown {
+                            keycode: Some(keycode @ (Keycode::T | Keycode::Plus | Keycode::Equals | Keycode::KpPlus | Keycode::Minus | Keycode::KpMinus)),
+                            ..
+                        } => {
+                            let mut display_settings = display_thread_display_settings.lock().unwrap();
+                            match keycode {
+                                Keycode::T => display_settings.1 = display_settings.1.next(),
+                                Keycode::Minus | Keycode::KpMinus => display_settings.0 -= 0.5,
+                                _ => display_settings.0 += 0.5,
+                            }
+                            canvas
+                                .window_mut()
+                                .set_title(format!("Light rendering [Frame {}] [{} {:+.1} EV]", frame, display_settings.1.name(), display_settings.0).as_str())
+                                .unwrap();
+                        }
                         _ => {}
                     }
                 }
-                let mut image_copy = display_thread_image.lock().unwrap().clone();
+                let (exposure, tone_mapper) = *display_thread_display_settings.lock().unwrap();
+                let image_copy = if display_thread_should_denoise.load(std::sync::atomic::Ordering::Relaxed) {
+                    // denoising takes longer than a frame, so it only runs again after another pass finished
+                    let passes = display_thread_finished_passes.load(std::sync::atomic::Ordering::Relaxed);
+                    if denoised.as_ref().is_none_or(|(denoised_passes, _)| *denoised_passes != passes) {
+                        let buffers = display_thread_buffers.lock().unwrap();
+                        let mut image = buffers.film.image();
+                        let (albedo, normal) = buffers.guide_images(&display_thread_settings).unwrap();
+                        drop(buffers);
+                        image.apply_filter(|x| to_working_space(is_spectral, working_space, x));
+                        denoised = Some((passes, denoise(&image, &albedo, &normal, &DenoiseSettings::default())));
+                    }
+                    denoised.as_ref().unwrap().1.clone()
+                } else {
+                    let mut image = display_thread_buffers.lock().unwrap().film.image();
+                    image.apply_filter(|x| to_working_space(is_spectral, working_space, x));
+                    image
+                };
 
                 texture
                     .update(
                         None,
-                        image_copy
-                        .apply_filter(|x| {
-                            average_samples(
-                                display_thread_next_sample
-                                .load(std::sync::atomic::Ordering::Relaxed),
-                                x,
-                                )
-                        })
-                        .apply_filter(|x| gamma_correct(2.0, x))
+                        post_process(&image_copy, &display_thread_post_process)
+                        .apply_filter(|x| display_transform(exposure, tone_mapper, working_space, x))
                         .get_bytes_inverse_y()
                         .as_slice(),
                         (3 * scene.width) as usize,
@@ -130,7 +186,6 @@ fn main() {
         let mut saving_thread: Option<thread::JoinHandle<()>> = None;
         let rendering_start = Instant::now();
 
-        let scanlines = (0..scene.height).collect::<Vec<u32>>();
         print!(
             "\r[{:>width$}/{:>width$}][{:>6.2}%]{} Rendering...{:>10}",
             1,
@@ -144,85 +199,91 @@ fn main() {
             width = samples_per_pixel.to_string().len()
             );
 
-        if let Some(bvh) = &scene.bvh {
-            for sample in 0..samples_per_pixel {
-
-                stdout().flush().unwrap();
-                scanlines.par_iter().for_each(
-                    |y: &u32| {
-                        let mut this_row: Vec<Vec3> =
-                            vec![Vec3::new(0.0, 0.0, 0.0); scene.width as usize];
-                        for x in 0..scene.width {
-                            let x_offset: f32 = rand::random();
-                            let y_offset: f32 = rand::random();
+        for sample in 0..samples_per_pixel {
 
-                        let u = (x as f32 + x_offset) / scene.width as f32;
-                        let v = (*y as f32 + y_offset) / scene.height as f32;
-
-                        let ray = scene.camera.get_ray(u, v);
-
-                        this_row[x as usize] += trace_ray::trace_ray(ray, bvh, max_depth);
-                        }
-                        let mut image = protected_image.lock().unwrap();
-                        for x in 0..scene.width {
-                            image[(x, *y)] += this_row[x as usize];
-                        }
-                    },
-                    );
-                if should_end.load(std::sync::atomic::Ordering::Relaxed) {
+            stdout().flush().unwrap();
+            render_pass(&scene, sample as u32, &buffers);
+            finished_passes.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            if should_end.load(std::sync::atomic::Ordering::Relaxed) {
+                break;
+            }
+            if let Some(threshold) = scene.settings.noise_threshold {
+                let statistics = &mut buffers.lock().unwrap().statistics;
+                statistics.update_convergence(threshold, scene.settings.min_samples as u32);
+                if statistics.converged_fraction() == 1.0 {
                     break;
                 }
-                if sample % 40 == 0 {
-                    let mut image_copy = protected_image.lock().unwrap().clone();
-                    let samples_until_now = sample + 1;
-                    saving_thread = Some(thread::spawn(move || {
-                        image_copy
-                            .apply_filter(|x| average_samples(samples_until_now, x))
-                            .apply_filter(|x| gamma_correct(2.0, x))
-                            .save_to_file("/tmp/test.ppm")
-                            .unwrap();
-                    }));
-                }
-                match &saving_thread {
-                    Some(thread) => {
-                        if thread.is_finished() {
-                            saving_thread = None;
-                        }
-                    }
-                    None => {}
-                };
-                let local_next_sample = sample+1;
-                next_sample.store(local_next_sample, std::sync::atomic::Ordering::Relaxed);
-                print!(
-                    "\r[{:>width$}/{:>width$}][{:>6.2}%]{} Rendering...{:>10}",
-                    (local_next_sample + 1).min(samples_per_pixel),
-                    samples_per_pixel,
-                    (local_next_sample) as f32 / samples_per_pixel as f32 * 100.0,
-                    match saving_thread {
-                        Some(_) => "[Saving]",
-                        None => "",
-                    },
-                    "",
-                    width = samples_per_pixel.to_string().len()
-                    );
             }
-        } else {
-            panic!("Scene doesn't contain a BVH");
+            if scene.settings.time_limit.is_some_and(|limit| rendering_start.elapsed().as_secs_f32() > limit) {
+                break;
+            }
+            if sample % 40 == 0 {
+                let mut image_copy = buffers.lock().unwrap().film.image();
+                let (exposure, tone_mapper) = *display_settings.lock().unwrap();
+                let post_process_settings = scene.settings.post_process.clone();
+                saving_thread = Some(thread::spawn(move || {
+                    image_copy.apply_filter(|x| to_working_space(is_spectral, working_space, x));
+                    post_process(&image_copy, &post_process_settings)
+                        .apply_filter(|x| display_transform(exposure, tone_mapper, working_space, x))
+                        .save_to_file("/tmp/test.ppm")
+                        .unwrap();
+                }));
+            }
+            match &saving_thread {
+                Some(thread) => {
+                    if thread.is_finished() {
+                        saving_thread = None;
+                    }
+                }
+                None => {}
+            };
+            let local_next_sample = sample+1;
+            print!(
+                "\r[{:>width$}/{:>width$}][{:>6.2}%][{:>6.2}% converged]{} Rendering...{:>10}",
+                (local_next_sample + 1).min(samples_per_pixel),
+                samples_per_pixel,
+                (local_next_sample) as f32 / samples_per_pixel as f32 * 100.0,
+                buffers.lock().unwrap().statistics.converged_fraction() * 100.0,
+                match saving_thread {
+                    Some(_) => "[Saving]",
+                    None => "",
+                },
+                "",
+                width = samples_per_pixel.to_string().len()
+                );
         }
         should_end.store(true, std::sync::atomic::Ordering::Relaxed);
         display_thread.join().unwrap();
         println!();
         println!("Rendering took {:.2?}", rendering_start.elapsed());
-        // println!("Num intersections passed: {}", NUM_INTERSECTIONS_PASSED.load(std::sync::atomic::Ordering::Relaxed));
-
-        protected_image
-            .lock()
-            .unwrap()
-            .apply_filter(|x| {
-                average_samples(next_sample.load(std::sync::atomic::Ordering::Relaxed), x)
-            })
-        .apply_filter(|x| gamma_correct(2.0, x))
+        render_statistics::add_time(Phase::Render, rendering_start.elapsed());
+        if scene.settings.statistics {
+            let report = render_statistics::report();
+            print!("{}", report);
+            report.save_json(format!("/tmp/test{}_statistics.json", frame).as_str()).unwrap();
+        }
+
+        let buffers = buffers.lock().unwrap();
+        let statistics = &buffers.statistics;
+        let (exposure, tone_mapper) = *display_settings.lock().unwrap();
+        let mut image = buffers.film.image();
+        image.apply_filter(|x| to_working_space(is_spectral, working_space, x));
+        if should_denoise.load(std::sync::atomic::Ordering::Relaxed) {
+            let (albedo, normal) = buffers.guide_images(&scene.settings).unwrap();
+            image = denoise(&image, &albedo, &normal, &DenoiseSettings::default());
+        }
+        let aov_layers = buffers.aovs.layers(&scene.settings);
+        let mut exr_layers = vec![("", &image)];
+        exr_layers.extend(aov_layers.iter().map(|(name, layer)| (*name, layer)));
+        save_exr(format!("/tmp/test{}.exr", frame).as_str(), &exr_layers, working_space).unwrap();
+        post_process(&image, &scene.settings.post_process)
+            .apply_filter(|x| display_transform(exposure, tone_mapper, working_space, x))
             .save_to_file(format!("/tmp/test{}.ppm", frame).as_str())
             .unwrap();
+        if let Some(threshold) = scene.settings.noise_threshold {
+            statistics.noise_image(threshold).save_to_file(format!("/tmp/test{}_noise.ppm", frame).as_str()).unwrap();
+        }
+        statistics.sample_count_image(samples_per_pixel).save_to_file(format!("/tmp/test{}_samples.ppm", frame).as_str()).unwrap();
+        buffers.aovs.save(format!("/tmp/test{}", frame).as_str(), &scene.settings).unwrap();
     }
 }
@@ -0,0 +1,241 @@
+//! Performance benchmarks: building the BVH, ray traversal and full renders.
+//!
+//! Run them with `cargo bench -p light`, or a single group with e.g. `cargo bench -p light -- traversal`. Everything
+//! runs on a single thread. Besides Criterion's own reports, all results end up in `target/criterion/summary.json`
+//! for tracking them over time.
+//!
+//! - `bvh_build`: `BVH::build_recursive` for the bundled meshes and a generated mesh with a million triangles.
+//! - `traversal`: closest hit queries for coherent camera rays and incoherent random rays. The throughput is in rays.
+//! - `render`: one sample per pixel of the reference scenes in `tests/scenes`, so iterations per second are spp/s.
+
+use std::{fs, path::{Path, PathBuf}, time::{Duration, UNIX_EPOCH}};
+
+use criterion::{black_box, criterion_group, BatchSize, BenchmarkId, Criterion, Throughput};
+use light::{bounding_box::BVH, hit_result::HitResult, hittable::Hittable, importing::load_from_blender, mesh::parse_obj_triangles, random::random_on_unit_sphere, ray::Ray, sampler::{create_sampler, IndependentSampler, Sampler}, scene::Scene, trace_ray::trace_camera_ray, triangle::Triangle};
+use serde_json::{json, Value};
+use ultraviolet::{Vec2, Vec3, Vec4};
+
+const MESHES: [&str; 3] = ["default_cube", "ico_sphere", "uv_sphere"];
+const SCENES: [&str; 5] = ["cornell_box", "glass_sphere", "metal_sphere", "emissive_only", "default_cube"];
+/// Resolution of the coherent camera rays.
+const RAY_GRID: (u32, u32) = (256, 192);
+const INCOHERENT_RAYS: u32 = 50000;
+
+fn load_mesh(name: &str) -> Vec<Triangle>{
+    let filename = format!("../meshes/{}.obj", name);
+    parse_obj_triangles(&fs::read_to_string(&filename).unwrap(), &filename).unwrap()
+}
+
+/// A bumpy UV sphere with `segments * segments` triangles.
+fn generated_mesh(segments: u32) -> Vec<Triangle>{
+    let rings = segments / 2;
+    let point = |segment: u32, ring: u32| {
+        let phi = segment as f32 / segments as f32 * std::f32::consts::TAU;
+        let theta = ring as f32 / rings as f32 * std::f32::consts::PI;
+        let radius = 1.0 + 0.05 * (phi * 12.0).sin() * (theta * 9.0).sin();
+        Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()) * radius
+    };
+    let triangle = |vertices: [Vec3; 3]| {
+        let normal = (vertices[1] - vertices[0]).cross(vertices[2] - vertices[0]).normalized();
+        Triangle { vertices, normals: [normal; 3], uv_coordinates: [Vec2::zero(); 3], tangents: [Vec4::new(1.0, 0.0, 0.0, 1.0); 3] }
+    };
+    let mut triangles = Vec::with_capacity((segments * segments) as usize);
+    for ring in 0..rings{
+        for segment in 0..segments{
+            let [a, b, c, d] = [point(segment, ring), point(segment + 1, ring), point(segment, ring + 1), point(segment + 1, ring + 1)];
+            triangles.push(triangle([a, c, b]));
+            triangles.push(triangle([b, c, d]));
+        }
+    }
+    triangles
+}
+
+fn build(triangles: &[Triangle]) -> Box<dyn Hittable>{
+    BVH::<dyn Hittable>::build_recursive(triangles.iter().map(|x| Box::new(*x) as Box<dyn Hittable>).collect())
+}
+
+fn bvh_build(c: &mut Criterion){
+    let mut group = c.benchmark_group("bvh_build");
+    for name in MESHES{
+        let triangles = load_mesh(name);
+        group.throughput(Throughput::Elements(triangles.len() as u64));
+        group.bench_function(name, |b| b.iter_batched(
+            || triangles.iter().map(|x| Box::new(*x) as Box<dyn Hittable>).collect::<Vec<_>>(),
+            BVH::<dyn Hittable>::build_recursive,
+            BatchSize::LargeInput,
+        ));
+    }
+
+    let triangles = generated_mesh(1000);
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(30));
+    group.throughput(Throughput::Elements(triangles.len() as u64));
+    group.bench_function("million_triangles", |b| b.iter_batched(
+        || triangles.iter().map(|x| Box::new(*x) as Box<dyn Hittable>).collect::<Vec<_>>(),
+        BVH::<dyn Hittable>::build_recursive,
+        BatchSize::LargeInput,
+    ));
+    group.finish();
+}
+
+/// Pinhole rays through a grid of pixel centers, from `origin` towards `target`, in scanline order.
+fn camera_rays(origin: Vec3, target: Vec3, fov: f32) -> Vec<Ray>{
+    let forward = (target - origin).normalized();
+    let right = forward.cross(Vec3::unit_y()).normalized();
+    let up = right.cross(forward);
+    let half_height = (fov.to_radians() / 2.0).tan();
+    let half_width = half_height * RAY_GRID.0 as f32 / RAY_GRID.1 as f32;
+    let mut rays = Vec::with_capacity((RAY_GRID.0 * RAY_GRID.1) as usize);
+    for y in 0..RAY_GRID.1{
+        for x in 0..RAY_GRID.0{
+            let u = (x as f32 + 0.5) / RAY_GRID.0 as f32 * 2.0 - 1.0;
+            let v = (y as f32 + 0.5) / RAY_GRID.1 as f32 * 2.0 - 1.0;
+            rays.push(Ray { origin, direction: (forward + right * u * half_width + up * v * half_height).normalized() });
+        }
+    }
+    rays
+}
+
+/// Rays from random points within the bounds of `object` in random directions.
+fn random_rays(object: &dyn Hittable) -> Vec<Ray>{
+    let (min, max) = (object.get_min_bounds(), object.get_max_bounds());
+    let mut sampler = IndependentSampler::new(0);
+    (0..INCOHERENT_RAYS).map(|i| {
+        sampler.start_sample((i, 0), 0);
+        let origin = min + (max - min) * Vec3::new(sampler.next_1d(), sampler.next_1d(), sampler.next_1d());
+        Ray { origin, direction: random_on_unit_sphere(&mut sampler) }
+    }).collect()
+}
+
+fn bench_rays(group: &mut criterion::BenchmarkGroup<criterion::measurement::WallTime>, name: &str, kind: &str, object: &dyn Hittable, rays: &[Ray]){
+    group.throughput(Throughput::Elements(rays.len() as u64));
+    group.bench_function(BenchmarkId::new(kind, name), |b| b.iter(|| {
+        let mut hits = 0;
+        for ray in rays{
+            let mut hit = HitResult::default();
+            hits += object.hit(black_box(*ray), &mut hit, 1e-4) as u32;
+        }
+        hits
+    }));
+}
+
+fn load_scene(name: &str) -> Scene{
+    load_from_blender(format!("tests/scenes/{}.toml", name).as_str()).unwrap()
+}
+
+fn traversal(c: &mut Criterion){
+    let mut group = c.benchmark_group("traversal");
+    for name in ["cornell_box", "default_cube"]{
+        let scene = load_scene(name);
+        // the scene camera at a higher resolution, through the pixel centers
+        let mut sampler = IndependentSampler::new(0);
+        let mut rays = Vec::with_capacity((RAY_GRID.0 * RAY_GRID.1) as usize);
+        for y in 0..RAY_GRID.1{
+            for x in 0..RAY_GRID.0{
+                let (u, v) = ((x as f32 + 0.5) / RAY_GRID.0 as f32, (y as f32 + 0.5) / RAY_GRID.1 as f32);
+                rays.extend(scene.camera.get_ray(u, v, &mut sampler));
+            }
+        }
+        bench_rays(&mut group, name, "coherent", &scene, &rays);
+        bench_rays(&mut group, name, "incoherent", &scene, &random_rays(&scene));
+    }
+
+    let mesh = build(&generated_mesh(1000));
+    bench_rays(&mut group, "million_triangles", "coherent", mesh.as_ref(), &camera_rays(Vec3::new(0.0, 1.0, 3.0), Vec3::zero(), 40.0));
+    bench_rays(&mut group, "million_triangles", "incoherent", mesh.as_ref(), &random_rays(mesh.as_ref()));
+    group.finish();
+}
+
+fn render(c: &mut Criterion){
+    let mut group = c.benchmark_group("render");
+    group.sample_size(20);
+    for name in SCENES{
+        let scene = load_scene(name);
+        let samples_per_pixel = scene.settings.samples_per_pixel;
+        let mut sampler = create_sampler(scene.settings.sampler, scene.settings.seed, samples_per_pixel);
+        let mut sample_index = 0;
+        group.throughput(Throughput::Elements((scene.width * scene.height) as u64));
+        group.bench_function(name, |b| b.iter(|| {
+            let mut sum = Vec3::zero();
+            for y in 0..scene.height{
+                for x in 0..scene.width{
+                    sampler.start_sample((x, y), sample_index);
+                    let offset = sampler.next_2d();
+                    if let Some(ray) = scene.camera.get_ray((x as f32 + offset.x) / scene.width as f32, (y as f32 + offset.y) / scene.height as f32, sampler.as_mut()){
+                        sum += trace_camera_ray(ray, &scene, sampler.as_mut());
+                    }
+                }
+            }
+            sample_index = (sample_index + 1) % samples_per_pixel as u32;
+            sum
+        }));
+    }
+    group.finish();
+}
+
+/// Where Criterion puts its results, looked up the same way Criterion does.
+fn criterion_directory() -> PathBuf{
+    if let Some(directory) = std::env::var_os("CRITERION_HOME"){
+        return PathBuf::from(directory);
+    }
+    match std::env::var_os("CARGO_TARGET_DIR"){
+        Some(directory) => PathBuf::from(directory).join("criterion"),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().join("target/criterion"),
+    }
+}
+
+/// Collects the latest estimate of every benchmark into one JSON file.
+fn write_summary(){
+    let directory = criterion_directory();
+    let mut benchmarks = Vec::new();
+    let mut pending = vec![directory.clone()];
+    while let Some(path) = pending.pop(){
+        let Ok(entries) = fs::read_dir(&path) else{
+            continue;
+        };
+        for entry in entries.flatten(){
+            if entry.path().is_dir() && entry.file_name() != "report"{
+                pending.push(entry.path());
+            }
+        }
+        let new = path.join("new");
+        let read = |name: &str| fs::read_to_string(new.join(name)).ok().and_then(|x| serde_json::from_str::<Value>(&x).ok());
+        let (Some(benchmark), Some(estimates)) = (read("benchmark.json"), read("estimates.json")) else{
+            continue;
+        };
+        let mean = estimates["mean"]["point_estimate"].as_f64().unwrap_or(f64::NAN);
+        let elements = benchmark["throughput"]["Elements"].as_f64();
+        let measured_at = fs::metadata(new.join("estimates.json")).and_then(|x| x.modified()).ok()
+            .and_then(|x| x.duration_since(UNIX_EPOCH).ok()).map(|x| x.as_secs());
+        benchmarks.push(json!({
+            "id": benchmark["full_id"],
+            "mean_ns": mean,
+            "mean_lower_bound_ns": estimates["mean"]["confidence_interval"]["lower_bound"],
+            "mean_upper_bound_ns": estimates["mean"]["confidence_interval"]["upper_bound"],
+            "median_ns": estimates["median"]["point_estimate"],
+            "std_dev_ns": estimates["std_dev"]["point_estimate"],
+            "iterations_per_second": 1e9 / mean,
+            "elements_per_iteration": elements,
+            "elements_per_second": elements.map(|x| x * 1e9 / mean),
+            "measured_at": measured_at,
+        }));
+    }
+    benchmarks.sort_by(|a, b| a["id"].as_str().cmp(&b["id"].as_str()));
+    let summary = json!({ "benchmarks": benchmarks });
+    let path = directory.join("summary.json");
+    match fs::write(&path, serde_json::to_string_pretty(&summary).unwrap()){
+        Ok(()) => println!("Wrote the summary of {} benchmarks to {}", benchmarks.len(), path.display()),
+        Err(error) => println!("Couldn't write the summary to {}: {}", path.display(), error),
+    }
+}
+
+criterion_group!(benches, bvh_build, traversal, render);
+
+fn main(){
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+    // `cargo test --benches` runs every benchmark once without measuring anything
+    if std::env::args().any(|x| x == "--bench"){
+        write_summary();
+    }
+}
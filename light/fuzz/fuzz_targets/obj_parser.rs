@@ -0,0 +1,11 @@
+//! `cargo +nightly fuzz run obj_parser`, from the `light` directory.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(content) = std::str::from_utf8(data){
+        let _ = light::mesh::Mesh::parse_obj(content, "fuzz.obj");
+    }
+});
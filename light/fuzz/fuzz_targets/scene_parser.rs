@@ -0,0 +1,12 @@
+//! `cargo +nightly fuzz run scene_parser tests/scenes`, from the `light` directory. Mesh files are loaded relative to
+//! the working directory, so the test scenes are a good starting corpus.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(content) = std::str::from_utf8(data){
+        let _ = light::importing::parse_scene(content, "fuzz.toml");
+    }
+});
@@ -0,0 +1,119 @@
+use ultraviolet::Vec3;
+
+use crate::{image::Image, math_utils::component_average};
+
+/// Running mean and variance of the samples of one pixel (Welford's algorithm).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PixelStatistics{
+    pub sample_count: u32,
+    pub mean: Vec3,
+    squared_deviations: Vec3,
+}
+
+impl PixelStatistics{
+    pub fn add_sample(&mut self, sample: Vec3){
+        self.sample_count += 1;
+        let delta = sample - self.mean;
+        self.mean += delta / self.sample_count as f32;
+        self.squared_deviations += delta * (sample - self.mean);
+    }
+
+    /// Standard error of the mean, relative to the square root of the brightness. Noise in dark areas is more visible
+    /// after gamma correction, so this roughly matches how noisy the pixel looks.
+    pub fn error(&self) -> f32{
+        if self.sample_count < 2{
+            return f32::INFINITY;
+        }
+        let variance = self.squared_deviations / (self.sample_count - 1) as f32;
+        let standard_error = component_average(variance.map(|x| x.max(0.0).sqrt())) / (self.sample_count as f32).sqrt();
+        standard_error / component_average(self.mean).max(1e-4).sqrt()
+    }
+}
+
+/// Per pixel sample statistics for the whole image. Decides which pixels still need samples.
+#[derive(Clone, Debug)]
+pub struct SampleStatistics{
+    width: u32,
+    height: u32,
+    pixels: Vec<PixelStatistics>,
+    converged: Vec<bool>,
+}
+
+impl SampleStatistics{
+    pub fn new(width: u32, height: u32) -> SampleStatistics{
+        SampleStatistics {
+            width,
+            height,
+            pixels: vec![PixelStatistics::default(); (width * height) as usize],
+            converged: vec![false; (width * height) as usize],
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize{
+        (y * self.width + x) as usize
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> &PixelStatistics{
+        &self.pixels[self.index(x, y)]
+    }
+
+    pub fn add_sample(&mut self, x: u32, y: u32, sample: Vec3){
+        let index = self.index(x, y);
+        self.pixels[index].add_sample(sample);
+    }
+
+    pub fn is_converged(&self, x: u32, y: u32) -> bool{
+        self.converged[self.index(x, y)]
+    }
+
+    /// Marks pixels with at least `min_samples` samples as converged once the error of every pixel in their 3x3
+    /// neighbourhood is below `threshold`. Looking at the neighbourhood stops single pixels that got lucky early.
+    pub fn update_convergence(&mut self, threshold: f32, min_samples: u32){
+        let mut converged = vec![false; self.converged.len()];
+        for y in 0..self.height{
+            for x in 0..self.width{
+                if self.pixel(x, y).sample_count < min_samples{
+                    continue;
+                }
+                let mut max_error: f32 = 0.0;
+                for neighbour_y in y.saturating_sub(1)..=(y + 1).min(self.height - 1){
+                    for neighbour_x in x.saturating_sub(1)..=(x + 1).min(self.width - 1){
+                        max_error = max_error.max(self.pixel(neighbour_x, neighbour_y).error());
+                    }
+                }
+                converged[self.index(x, y)] = max_error < threshold;
+            }
+        }
+        self.converged = converged;
+    }
+
+    pub fn converged_fraction(&self) -> f32{
+        self.converged.iter().filter(|x| **x).count() as f32 / self.converged.len() as f32
+    }
+
+    /// The mean of every pixel.
+    pub fn mean_image(&self) -> Image{
+        self.map_to_image(|pixel| pixel.mean)
+    }
+
+    /// The error of every pixel divided by `threshold`, so converged pixels are darker than 1.
+    pub fn noise_image(&self, threshold: f32) -> Image{
+        self.map_to_image(|pixel| Vec3::broadcast((pixel.error() / threshold).min(f32::MAX)))
+    }
+
+    /// The sample count of every pixel divided by `max_samples`.
+    pub fn sample_count_image(&self, max_samples: usize) -> Image{
+        self.map_to_image(|pixel| Vec3::broadcast(pixel.sample_count as f32 / max_samples as f32))
+    }
+
+    fn map_to_image<F>(&self, f: F) -> Image
+    where F: Fn(&PixelStatistics) -> Vec3{
+        let mut image = Image::new(self.width, self.height);
+        for y in 0..self.height{
+            for x in 0..self.width{
+                image[(x, y)] = f(self.pixel(x, y));
+            }
+        }
+        image
+    }
+}
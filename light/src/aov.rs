@@ -0,0 +1,173 @@
+use ultraviolet::Vec3;
+
+use crate::{image::Image, scene::Scene, sampler::Sampler, image_filters::to_working_space, render_settings::RenderSettings, error::LightError, trace_ray::{FirstHit, trace_first_hit, trace_camera_ray_to_depth}};
+
+/// Extra passes that can be rendered alongside the colour.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aov{
+    Albedo,
+    Normal,
+    Depth,
+    Position,
+    ObjectId,
+    MaterialId,
+    /// Light reaching the camera after at most one bounce.
+    Direct,
+    /// Light reaching the camera after two or more bounces.
+    Indirect,
+    SampleCount,
+}
+
+impl Aov{
+    pub fn name(&self) -> &'static str{
+        match self{
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+            Aov::SampleCount => "sample_count",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Aov>{
+        [Aov::Albedo, Aov::Normal, Aov::Depth, Aov::Position, Aov::ObjectId, Aov::MaterialId, Aov::Direct, Aov::Indirect, Aov::SampleCount]
+            .into_iter()
+            .find(|aov| aov.name() == name)
+    }
+}
+
+/// The AOV values of a single camera sample.
+#[derive(Clone, Copy, Debug)]
+pub struct AovSample{
+    pub first_hit: FirstHit,
+    pub direct: Vec3,
+    pub indirect: Vec3,
+}
+
+/// Computes the AOVs of the camera sample `sample_index` of `pixel`, whose colour was `color`.
+///
+/// The direct light is found by tracing the same sample again with a maximum depth of two. The sampler hands out
+/// the same numbers again, so the path takes the same first bounce and the indirect light is simply the rest.
+pub fn trace_aovs(scene: &Scene, sampler: &mut dyn Sampler, pixel: (u32, u32), sample_index: u32, color: Vec3, aovs: &[Aov]) -> AovSample{
+    sampler.start_sample(pixel, sample_index);
+    let offset = sampler.next_2d();
+    let u = (pixel.0 as f32 + offset.x) / scene.width as f32;
+    let v = (pixel.1 as f32 + offset.y) / scene.height as f32;
+    let ray = match scene.camera.get_ray(u, v, sampler){
+        Some(ray) => ray,
+        // outside of a fisheye's image circle, nothing is seen
+        None => {
+            let first_hit = FirstHit { albedo: Vec3::zero(), normal: Vec3::zero(), depth: 0.0, position: Vec3::zero(), object_id: 0, material_id: 0 };
+            return AovSample { first_hit, direct: Vec3::zero(), indirect: color };
+        }
+    };
+
+    let first_hit = trace_first_hit(ray, scene);
+    let direct = if aovs.contains(&Aov::Direct) || aovs.contains(&Aov::Indirect){
+        trace_camera_ray_to_depth(ray, scene, sampler, scene.settings.max_depth.min(2))
+    }
+    else{
+        Vec3::zero()
+    };
+    AovSample { first_hit, direct, indirect: color - direct }
+}
+
+/// Accumulates the requested AOVs of every pixel.
+#[derive(Clone, Debug)]
+pub struct AovBuffers{
+    aovs: Vec<Aov>,
+    images: Vec<Image>,
+    sample_counts: Vec<u32>,
+    width: u32,
+    height: u32,
+}
+
+impl AovBuffers{
+    pub fn new(aovs: &[Aov], width: u32, height: u32) -> AovBuffers{
+        AovBuffers {
+            aovs: aovs.to_vec(),
+            images: aovs.iter().map(|_| Image::new(width, height)).collect(),
+            sample_counts: vec![0; (width * height) as usize],
+            width,
+            height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.aovs.is_empty()
+    }
+
+    pub fn aovs(&self) -> &[Aov]{
+        &self.aovs
+    }
+
+    pub fn add_sample(&mut self, x: u32, y: u32, sample: &AovSample){
+        let sample_count = &mut self.sample_counts[(y * self.width + x) as usize];
+        *sample_count += 1;
+        let first_hit = &sample.first_hit;
+        for (aov, image) in self.aovs.iter().zip(self.images.iter_mut()){
+            let value = match aov{
+                Aov::Albedo => first_hit.albedo,
+                Aov::Normal => first_hit.normal,
+                Aov::Depth => Vec3::broadcast(first_hit.depth),
+                Aov::Position => first_hit.position,
+                Aov::Direct => sample.direct,
+                Aov::Indirect => sample.indirect,
+                // ids can't be averaged, so they come from the first sample
+                Aov::ObjectId => {
+                    if *sample_count == 1{
+                        image[(x, y)] = Vec3::broadcast(first_hit.object_id as f32);
+                    }
+                    continue;
+                }
+                Aov::MaterialId => {
+                    if *sample_count == 1{
+                        image[(x, y)] = Vec3::broadcast(first_hit.material_id as f32);
+                    }
+                    continue;
+                }
+                Aov::SampleCount => continue,
+            };
+            image[(x, y)] += value;
+        }
+    }
+
+    /// The finished image of `aov`, or `None` if it isn't rendered. Colours are in the working space.
+    pub fn image(&self, aov: Aov, settings: &RenderSettings) -> Option<Image>{
+        let index = self.aovs.iter().position(|x| *x == aov)?;
+        let mut image = self.images[index].clone();
+        for y in 0..self.height{
+            for x in 0..self.width{
+                let sample_count = self.sample_counts[(y * self.width + x) as usize];
+                image[(x, y)] = match aov{
+                    Aov::ObjectId | Aov::MaterialId => image[(x, y)],
+                    Aov::SampleCount => Vec3::broadcast(sample_count as f32),
+                    _ => image[(x, y)] / sample_count.max(1) as f32,
+                };
+            }
+        }
+        if aov == Aov::Direct || aov == Aov::Indirect{
+            image.apply_filter(|x| to_working_space(settings.spectral, settings.working_space, x));
+        }
+        Some(image)
+    }
+
+    /// All finished AOVs with their names, e.g. to save them as EXR layers.
+    pub fn layers(&self, settings: &RenderSettings) -> Vec<(&'static str, Image)>{
+        self.aovs.iter().filter_map(|aov| Some((aov.name(), self.image(*aov, settings)?))).collect()
+    }
+
+    /// Saves every AOV as a separate PFM file named `<prefix>_<aov>.pfm`.
+    pub fn save(&self, prefix: &str, settings: &RenderSettings) -> Result<(), LightError>{
+        for aov in &self.aovs{
+            if let Some(image) = self.image(*aov, settings){
+                image.save_to_pfm(format!("{}_{}.pfm", prefix, aov.name()).as_str())?;
+            }
+        }
+        Ok(())
+    }
+}
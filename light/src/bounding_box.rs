@@ -1,20 +1,21 @@
-use std::mem::swap;
+use std::{mem::swap, time::Instant};
 
 use ultraviolet::Vec3;
 
-use crate::hittable::Hittable;
+use crate::{hittable::Hittable, render_statistics::{self, Phase}, validation::Diagnostic};
 
+/// What the BVH of nothing contains.
 struct EmptyHittable{}
 
 impl Hittable for EmptyHittable{
     fn get_max_bounds(&self) -> Vec3 {
-        unreachable!()
+        Vec3::zero()
     }
     fn get_min_bounds(&self) -> Vec3 {
-        unreachable!()
+        Vec3::zero()
     }
-    fn hit(&self, _: crate::ray::Ray, _: &mut crate::hit_result::HitResult, _: f32) -> bool {
-        unreachable!()
+    fn hit<'a>(&'a self, _: crate::ray::Ray, _: &mut crate::hit_result::HitResult<'a>, _: f32) -> bool {
+        false
     }
 }
 
@@ -30,12 +31,26 @@ pub struct BoundingBox{
 }
 
 impl<T: ?Sized> BVH<T>{
-    pub fn build_recursive(mut objects: Vec<Box<dyn Hittable>>) -> Box<dyn Hittable>{
+    pub fn build_recursive(objects: Vec<Box<dyn Hittable>>) -> Box<dyn Hittable>{
+        let start = Instant::now();
+        let bvh = BVH::<dyn Hittable>::build_node(objects);
+        render_statistics::add_time(Phase::BvhBuild, start.elapsed());
+        bvh
+    }
+
+    fn build_node(mut objects: Vec<Box<dyn Hittable>>) -> Box<dyn Hittable>{
+        if objects.is_empty(){
+            return Box::new(EmptyHittable{});
+        }
         if objects.len() == 1{
             return objects.remove(0);
         }
         else{
-            let axis = rand::random::<u32>() % 3;
+            // split along the axis with the largest spread
+            let lowest = objects.iter().map(|obj| obj.get_min_bounds()).fold(Vec3::broadcast(f32::INFINITY), |a, b| a.min_by_component(b));
+            let highest = objects.iter().map(|obj| obj.get_min_bounds()).fold(Vec3::broadcast(f32::NEG_INFINITY), |a, b| a.max_by_component(b));
+            let extent = highest - lowest;
+            let axis = if extent.x >= extent.y && extent.x >= extent.z {0} else if extent.y >= extent.z {1} else {2};
             objects.sort_unstable_by(|a, b|{
                 return a.get_min_bounds()[axis as usize].total_cmp(&b.get_min_bounds()[axis as usize]);
             });
@@ -43,27 +58,23 @@ impl<T: ?Sized> BVH<T>{
             let right = objects.split_off(objects.len()/2);
             
             let mut bvh = Box::new(BVH::<dyn Hittable>{
-                left: BVH::<dyn Hittable>::build_recursive(objects) as Box<dyn Hittable>,
-                right: BVH::<dyn Hittable>::build_recursive(right) as Box<dyn Hittable>,
+                left: BVH::<dyn Hittable>::build_node(objects) as Box<dyn Hittable>,
+                right: BVH::<dyn Hittable>::build_node(right) as Box<dyn Hittable>,
                 bbox: BoundingBox { min: Vec3::zero(), max: Vec3::zero() }
             });
 
             bvh.bbox.min = bvh.left.get_min_bounds().min_by_component(bvh.right.get_min_bounds());
             bvh.bbox.max = bvh.left.get_max_bounds().max_by_component(bvh.right.get_max_bounds());
 
-            println!("Min {:?}", bvh.bbox.min);
-            println!("Max {:?}", bvh.bbox.max);
-
-
             return bvh;
         }
     }
 
 }
 
-// pub static NUM_INTERSECTIONS_PASSED: AtomicUsize = AtomicUsize::new(0);
 impl<T: Hittable + ?Sized> Hittable for BVH<T>{
-    fn hit(&self, ray: crate::ray::Ray, hit: &mut crate::hit_result::HitResult, min_distance: f32) -> bool {
+    fn hit<'a>(&'a self, ray: crate::ray::Ray, hit: &mut crate::hit_result::HitResult<'a>, min_distance: f32) -> bool {
+        render_statistics::count_bvh_node();
         let mut t_min = min_distance;
         let mut t_max = hit.t;
         for a in 0..3 {
@@ -79,7 +90,6 @@ impl<T: Hittable + ?Sized> Hittable for BVH<T>{
                 return false;
             }
         }
-        // NUM_INTERSECTIONS_PASSED.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         return self.left.hit(ray, hit, min_distance) | self.right.hit(ray, hit, min_distance);
     }
 
@@ -89,4 +99,11 @@ impl<T: Hittable + ?Sized> Hittable for BVH<T>{
     fn get_min_bounds(&self) -> Vec3 {
         self.bbox.min
     }
+    fn memory_usage(&self) -> usize {
+        size_of_val(self) + self.left.memory_usage() + self.right.memory_usage()
+    }
+    fn validate(&self, diagnostics: &mut Vec<Diagnostic>) {
+        self.left.validate(diagnostics);
+        self.right.validate(diagnostics);
+    }
 }
@@ -1,13 +1,97 @@
-use ultraviolet::Vec3;
+use std::f32::consts::PI;
 
-use crate::ray::Ray;
+use ultraviolet::{Vec2, Vec3, Mat4, Rotor3};
 
+use crate::{ray::Ray, random::{random_in_unit_disk, random_in_regular_polygon}, sampler::Sampler, math_utils::is_finite, validation::{Diagnostic, SourceLocation}};
 
-fn random_in_unit_disk() -> Vec3{
-    loop{  
-        let v = Vec3{x: rand::random(), y: rand::random(), z: 0.5} * 2.0 - Vec3::new(1.0, 1.0, 1.0);
-        if v.mag_sq() < 1.0{
-            return v;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Projection{
+    #[default]
+    Perspective,
+    Orthographic,
+    /// 360° around and 180° from top to bottom, looking forward at the center of the image.
+    Equirectangular,
+    /// The angle from the view direction grows linearly with the distance from the center. The image circle spans
+    /// the longer image side.
+    FisheyeEquidistant,
+    /// Like a real fisheye lens: the distance from the center on the sensor is `2 * fisheye_lens * sin(angle / 2)`.
+    FisheyeEquisolid,
+    /// Six 90° faces in a 3x2 grid: right, left and up on top, down, back and front below. Up and down have the
+    /// back of the camera at their top and bottom.
+    CubeMap,
+}
+
+impl Projection{
+    /// Panoramic projections ignore the lens, shift and field of view settings.
+    pub fn is_panoramic(self) -> bool{
+        !matches!(self, Projection::Perspective | Projection::Orthographic)
+    }
+}
+
+/// Everything a `[camera]` section can set.
+#[derive(Clone)]
+pub struct CameraParameters{
+    pub position: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    /// Camera to world transform like in Blender: the camera looks along -z with +y up. Replaces `position`, `target`
+    /// and `up` when set.
+    pub matrix: Option<Mat4>,
+    /// Rotation around the view direction in degrees, counter clockwise as seen from behind the camera.
+    pub roll: f32,
+    pub projection: Projection,
+    /// Vertical field of view in degrees, only used by perspective cameras.
+    pub fov: f32,
+    /// Focal length in mm. Replaces `fov` when set, together with `sensor_width`.
+    pub focal_length: Option<f32>,
+    /// Size of the longer sensor side in mm, like Blender's automatic sensor fit.
+    pub sensor_width: f32,
+    /// Field of view of the fisheye projections in degrees.
+    pub fisheye_fov: f32,
+    /// Focal length of the equisolid fisheye projection in mm.
+    pub fisheye_lens: f32,
+    /// Size of the longer image side in world units, only used by orthographic cameras.
+    pub ortho_scale: f32,
+    /// Moves the image window without rotating the camera, in units of the longer image side like Blender's shift.
+    pub shift: Vec2,
+    /// Diameter of the lens in world units, 0 for a pinhole camera.
+    pub aperture_size: f32,
+    /// Replaces `aperture_size` by `focal_length / f_stop` when set, assuming world units are meters like in Blender.
+    /// Without `focal_length`, the one matching `fov` and `sensor_width` is used.
+    pub f_stop: Option<f32>,
+    /// Number of aperture blades, 0 for a round aperture.
+    pub aperture_blades: u32,
+    /// Rotation of the aperture blades in degrees.
+    pub aperture_rotation: f32,
+    /// Anamorphic bokeh: the aperture is this many times higher than wide.
+    pub aperture_ratio: f32,
+    /// Distance of the plane that is in focus.
+    pub focus_distance: f32,
+}
+
+impl Default for CameraParameters{
+    fn default() -> Self {
+        CameraParameters{
+            position: Vec3::zero(),
+            target: Vec3::zero(),
+            up: Vec3::unit_y(),
+            matrix: None,
+            roll: 0.0,
+            projection: Projection::Perspective,
+            fov: 0.0,
+            focal_length: None,
+            sensor_width: 36.0,
+            fisheye_fov: 180.0,
+            fisheye_lens: 10.5,
+            ortho_scale: 6.0,
+            shift: Vec2::zero(),
+            aperture_size: 0.0,
+            f_stop: None,
+            aperture_blades: 0,
+            aperture_rotation: 0.0,
+            aperture_ratio: 1.0,
+            focus_distance: 1.0,
         }
     }
 }
@@ -19,44 +103,190 @@ pub struct Camera{
     width_world_space: Vec3,
     height_world_space: Vec3,
     lens_radius: f32,
+    aperture_blades: u32,
+    aperture_rotation: f32,
+    aperture_ratio: f32,
+    projection: Projection,
+    focus_distance: f32,
+    aspect_ratio: f32,
+    sensor_width: f32,
+    /// In radians.
+    fisheye_fov: f32,
+    fisheye_lens: f32,
 
     u: Vec3,
     v: Vec3,
+    /// Points backwards, away from the target.
+    w: Vec3,
+    up: Vec3,
 }
 
 impl Camera{
-    pub fn new(pos: Vec3, target: Vec3, fov: f32, aspect_ratio: f32, aperture_size: f32, depth_of_field: f32) -> Camera{
-        let half_height = (fov.to_radians()/2.0).tan();
-        let image_height = half_height * 2.0;
-        let image_width = aspect_ratio * image_height;
-
-        let up = Vec3::new(0.0, 1.0, 0.0);
-        
-        let w = (pos - target).normalized();
+    pub fn new(parameters: &CameraParameters, aspect_ratio: f32) -> Camera{
+        let (pos, w, up) = match parameters.matrix{
+            Some(matrix) => (matrix.cols[3].xyz(), matrix.cols[2].xyz().normalized(), matrix.cols[1].xyz()),
+            None => (parameters.position, (parameters.position - parameters.target).normalized(), parameters.up),
+        };
         let u = up.cross(w).normalized();
         let v = w.cross(u);
+        // a positive roll turns the camera counter clockwise, so the image turns clockwise
+        let roll = Rotor3::from_angle_plane(parameters.roll.to_radians(), u.wedge(v));
+        let (u, v) = (roll * u, roll * v);
+
+        // size of the image window at the distance of the focal plane
+        let focus_distance = parameters.focus_distance;
+        let image_height = match (parameters.projection, parameters.focal_length){
+            (Projection::Perspective, Some(focal_length)) => focus_distance * parameters.sensor_width / focal_length / aspect_ratio.max(1.0),
+            (Projection::Perspective, None) => focus_distance * (parameters.fov.to_radians()/2.0).tan() * 2.0,
+            // panoramic projections don't use the window
+            _ => parameters.ortho_scale / aspect_ratio.max(1.0),
+        };
+        let image_width = aspect_ratio * image_height;
 
-        let width_world_space = depth_of_field * image_width * u;
-        let height_world_space = depth_of_field * image_height * v;
+        let width_world_space = image_width * u;
+        let height_world_space = image_height * v;
+        let shift = image_width.max(image_height) * (parameters.shift.x * u + parameters.shift.y * v);
 
         Camera{
             pos,
             width_world_space,
             height_world_space,
-            lower_left_corner_world_space: pos - width_world_space/2.0 - height_world_space/2.0 - depth_of_field * w,
-            lens_radius: aperture_size / 2.0,
-            u, v,
+            lower_left_corner_world_space: pos - width_world_space/2.0 - height_world_space/2.0 - focus_distance * w + shift,
+            lens_radius: match parameters.f_stop{
+                // the focal length follows from the field of view if it isn't given, mm to meters
+                Some(f_stop) => parameters.sensor_width * focus_distance / image_width.max(image_height) / 1000.0 / f_stop / 2.0,
+                None => parameters.aperture_size / 2.0,
+            },
+            aperture_blades: parameters.aperture_blades,
+            aperture_rotation: parameters.aperture_rotation.to_radians(),
+            aperture_ratio: parameters.aperture_ratio,
+            projection: parameters.projection,
+            focus_distance,
+            aspect_ratio,
+            sensor_width: parameters.sensor_width,
+            fisheye_fov: parameters.fisheye_fov.to_radians(),
+            fisheye_lens: parameters.fisheye_lens,
+            u, v, w, up,
+        }
+    }
+
+    /// Adds the problems of the camera, see `Scene::validate`. `location` is where it was defined.
+    pub fn validate(&self, location: Option<&SourceLocation>, diagnostics: &mut Vec<Diagnostic>){
+        if !is_finite(self.pos){
+            diagnostics.push(Diagnostic::error(location, format!("The camera has an invalid position {:?}.", self.pos)));
+        }
+        else if !is_finite(self.w){
+            diagnostics.push(Diagnostic::error(location, "The camera target is the same as its position, so there is no view direction."));
+        }
+        else if !is_finite(self.u){
+            let up = (self.up.x, self.up.y, self.up.z);
+            diagnostics.push(Diagnostic::error(location, format!("The camera looks parallel to its up vector {:?}, set 'up' to a different direction.", up)));
+        }
+        // the image window is at the focus distance, so it has to be valid for the field of view check
+        else if !(self.focus_distance > 0.0 && self.focus_distance.is_finite()){
+            diagnostics.push(Diagnostic::error(location, format!("The focus distance {} has to be positive.", self.focus_distance)));
+        }
+        else if !self.projection.is_panoramic() && (!is_finite(self.height_world_space) || self.height_world_space.dot(self.v) <= 0.0){
+            diagnostics.push(Diagnostic::error(location, match self.projection{
+                Projection::Perspective => "The field of view has to be between 0 and 180 degrees and the focal length positive.",
+                _ => "The orthographic scale has to be positive.",
+            }));
+        }
+        let is_fisheye = matches!(self.projection, Projection::FisheyeEquidistant | Projection::FisheyeEquisolid);
+        if is_fisheye && !(self.fisheye_fov > 0.0 && self.fisheye_fov <= 2.0 * PI){
+            diagnostics.push(Diagnostic::error(location, "The fisheye field of view has to be between 0 and 360 degrees."));
+        }
+        if self.projection == Projection::FisheyeEquisolid && !(self.fisheye_lens > 0.0 && self.fisheye_lens.is_finite()){
+            diagnostics.push(Diagnostic::error(location, "The fisheye lens has to be positive."));
+        }
+        if self.projection == Projection::Equirectangular && (self.aspect_ratio - 2.0).abs() > 1e-3{
+            diagnostics.push(Diagnostic::warning(location, "An equirectangular image should be twice as wide as high, otherwise its pixels aren't square."));
+        }
+        if self.projection == Projection::CubeMap && (self.aspect_ratio - 1.5).abs() > 1e-3{
+            diagnostics.push(Diagnostic::warning(location, "A cube map should be 3:2, otherwise its faces aren't square."));
+        }
+        if !(self.lens_radius >= 0.0 && self.lens_radius.is_finite()){
+            diagnostics.push(Diagnostic::error(location, "The aperture size and f-stop have to be positive."));
+        }
+        if self.aperture_blades == 1 || self.aperture_blades == 2{
+            diagnostics.push(Diagnostic::error(location, format!("An aperture needs at least 3 blades, not {}. Use 0 for a round one.", self.aperture_blades)));
+        }
+        if !(self.aperture_ratio > 0.0 && self.aperture_ratio.is_finite()){
+            diagnostics.push(Diagnostic::error(location, format!("The aperture ratio {} has to be positive.", self.aperture_ratio)));
         }
     }
-    pub fn get_ray(&self, x: f32, y: f32) -> Ray {
+    /// The ray through the point (`x`, `y`) of the image, both from 0 to 1 with y going up. `None` outside of the image
+    /// circle of a fisheye camera.
+    pub fn get_ray(&self, x: f32, y: f32, sampler: &mut dyn Sampler) -> Option<Ray> {
+        if self.projection.is_panoramic(){
+            let direction = self.panoramic_direction(x, y)?;
+            return Some(Ray { origin: self.pos, direction: (direction.x * self.u + direction.y * self.v + direction.z * self.w).normalized() });
+        }
         // depth of field
-        let point_on_lens = self.lens_radius * random_in_unit_disk();
+        let mut point_on_lens = self.lens_radius * match self.aperture_blades{
+            0 => random_in_unit_disk(sampler),
+            blades => random_in_regular_polygon(blades, self.aperture_rotation, sampler),
+        };
+        point_on_lens.x /= self.aperture_ratio;
         let offset = self.u * point_on_lens.x + self.v * point_on_lens.y;
+        let point_in_focus = self.lower_left_corner_world_space + x*self.width_world_space + y*self.height_world_space;
+        // orthographic rays all start in the plane of the camera, parallel to the view direction
+        let origin = match self.projection{
+            Projection::Perspective => self.pos + offset,
+            _ => point_in_focus + self.focus_distance * self.w + offset,
+        };
    
-        Ray{
-            direction: (self.lower_left_corner_world_space + x*self.width_world_space + y*self.height_world_space - self.pos - offset).normalized(),
-            origin: self.pos + offset, 
+        Some(Ray{
+            direction: (point_in_focus - origin).normalized(),
+            origin,
+        })
+    }
+
+    /// The direction through (`x`, `y`) for panoramic projections, relative to the camera: x is right, y up and z back.
+    fn panoramic_direction(&self, x: f32, y: f32) -> Option<Vec3>{
+        // from the view direction on the way to a point in the image plane with x right, y up
+        let towards = |angle: f32, in_plane: Vec2| Vec3::new(angle.sin() * in_plane.x, angle.sin() * in_plane.y, -angle.cos());
+        match self.projection{
+            Projection::Equirectangular => {
+                let longitude = (x - 0.5) * 2.0 * PI;
+                let latitude = (y - 0.5) * PI;
+                Some(Vec3::new(latitude.cos() * longitude.sin(), latitude.sin(), -latitude.cos() * longitude.cos()))
+            }
+            Projection::FisheyeEquidistant | Projection::FisheyeEquisolid => {
+                // the longer side goes from -0.5 to 0.5
+                let point = Vec2::new((x - 0.5) * self.aspect_ratio, y - 0.5) / self.aspect_ratio.max(1.0);
+                let angle = if self.projection == Projection::FisheyeEquidistant{
+                    point.mag() * self.fisheye_fov
+                }
+                else{
+                    let ratio = point.mag() * self.sensor_width / (2.0 * self.fisheye_lens);
+                    if ratio > 1.0{
+                        return None;
+                    }
+                    2.0 * ratio.asin()
+                };
+                if angle > self.fisheye_fov / 2.0{
+                    return None;
+                }
+                let in_plane = if point.mag_sq() > 0.0 {point.normalized()} else {Vec2::zero()};
+                Some(towards(angle, in_plane))
+            }
+            Projection::CubeMap => {
+                let column = (x * 3.0).floor().clamp(0.0, 2.0);
+                let row = ((1.0 - y) * 2.0).floor().clamp(0.0, 1.0);
+                // -1 to 1 on the face
+                let on_face = Vec2::new(x * 3.0 - column, y * 2.0 - (1.0 - row)) * 2.0 - Vec2::one();
+                let (forward, up) = [
+                    (Vec3::unit_x(), Vec3::unit_y()),
+                    (-Vec3::unit_x(), Vec3::unit_y()),
+                    (Vec3::unit_y(), Vec3::unit_z()),
+                    (-Vec3::unit_y(), -Vec3::unit_z()),
+                    (Vec3::unit_z(), Vec3::unit_y()),
+                    (-Vec3::unit_z(), Vec3::unit_y()),
+                ][row as usize * 3 + column as usize];
+                Some(forward + on_face.x * forward.cross(up) + on_face.y * up)
+            }
+            Projection::Perspective | Projection::Orthographic => None,
         }
     }
 }
-
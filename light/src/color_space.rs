@@ -0,0 +1,109 @@
+use ultraviolet::{Vec2, Vec3};
+
+/// Linear RGB colour spaces that can be used for rendering and output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace{
+    /// Linear sRGB, the primaries and white point of Rec.709. Blender's default and the colour space of scene files.
+    LinearRec709,
+    /// The ACES working space (AP1 primaries, D60 white point).
+    AcesCg,
+    Rec2020,
+}
+
+/// Primaries and white point as CIE xy coordinates.
+#[derive(Clone, Copy, Debug)]
+pub struct Chromaticities{
+    pub red: Vec2,
+    pub green: Vec2,
+    pub blue: Vec2,
+    pub white: Vec2,
+}
+
+type Matrix = [[f32; 3]; 3];
+
+// conversions between D65 and D60 use the Bradford transform
+const REC709_TO_ACESCG: Matrix = [
+    [0.6130974, 0.3395231, 0.0473795],
+    [0.07019372, 0.9163539, 0.01345242],
+    [0.02061559, 0.1095698, 0.8698147],
+];
+const ACESCG_TO_REC709: Matrix = [
+    [1.705051, -0.6217921, -0.08325893],
+    [-0.1302564, 1.140805, -0.01054847],
+    [-0.02400335, -0.128969, 1.152972],
+];
+const REC709_TO_REC2020: Matrix = [
+    [0.627404, 0.329282, 0.0433136],
+    [0.069097, 0.91954, 0.0113612],
+    [0.0163916, 0.0880132, 0.895595],
+];
+const REC2020_TO_REC709: Matrix = [
+    [1.660491, -0.5876411, -0.07284986],
+    [-0.1245505, 1.1329, -0.00834942],
+    [-0.01815077, -0.1005789, 1.11873],
+];
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, 0.0, 1.0],
+];
+
+fn multiply(matrix: &Matrix, color: Vec3) -> Vec3{
+    Vec3::new(
+        matrix[0][0] * color.x + matrix[0][1] * color.y + matrix[0][2] * color.z,
+        matrix[1][0] * color.x + matrix[1][1] * color.y + matrix[1][2] * color.z,
+        matrix[2][0] * color.x + matrix[2][1] * color.y + matrix[2][2] * color.z,
+    )
+}
+
+impl ColorSpace{
+    pub fn name(&self) -> &'static str{
+        match self{
+            ColorSpace::LinearRec709 => "linear_rec709",
+            ColorSpace::AcesCg => "acescg",
+            ColorSpace::Rec2020 => "rec2020",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ColorSpace>{
+        [ColorSpace::LinearRec709, ColorSpace::AcesCg, ColorSpace::Rec2020].into_iter().find(|x| x.name() == name)
+    }
+
+    pub fn chromaticities(&self) -> Chromaticities{
+        let d65 = Vec2::new(0.3127, 0.3290);
+        match self{
+            ColorSpace::LinearRec709 => Chromaticities { red: Vec2::new(0.64, 0.33), green: Vec2::new(0.30, 0.60), blue: Vec2::new(0.15, 0.06), white: d65 },
+            ColorSpace::AcesCg => Chromaticities { red: Vec2::new(0.713, 0.293), green: Vec2::new(0.165, 0.830), blue: Vec2::new(0.128, 0.044), white: Vec2::new(0.32168, 0.33767) },
+            ColorSpace::Rec2020 => Chromaticities { red: Vec2::new(0.708, 0.292), green: Vec2::new(0.170, 0.797), blue: Vec2::new(0.131, 0.046), white: d65 },
+        }
+    }
+
+    fn matrix_to_rec709(self) -> &'static Matrix{
+        match self{
+            ColorSpace::LinearRec709 => &IDENTITY,
+            ColorSpace::AcesCg => &ACESCG_TO_REC709,
+            ColorSpace::Rec2020 => &REC2020_TO_REC709,
+        }
+    }
+
+    fn matrix_from_rec709(self) -> &'static Matrix{
+        match self{
+            ColorSpace::LinearRec709 => &IDENTITY,
+            ColorSpace::AcesCg => &REC709_TO_ACESCG,
+            ColorSpace::Rec2020 => &REC709_TO_REC2020,
+        }
+    }
+}
+
+/// Converts a linear colour from one colour space to another, adapting the white point if necessary.
+pub fn convert(color: Vec3, from: ColorSpace, to: ColorSpace) -> Vec3{
+    if from == to{
+        return color;
+    }
+    multiply(to.matrix_from_rec709(), multiply(from.matrix_to_rec709(), color))
+}
+
+/// Decodes the sRGB transfer function.
+pub fn srgb_to_linear(color: Vec3) -> Vec3{
+    color.map(|x| if x <= 0.04045 {x / 12.92} else {((x + 0.055) / 1.055).powf(2.4)})
+}
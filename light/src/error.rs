@@ -0,0 +1,51 @@
+use std::{error::Error, fmt, io};
+
+/// Everything that can go wrong in `light`. Bad input is always reported as one of these, never as a panic.
+#[derive(Debug)]
+pub enum LightError{
+    /// Reading or writing `filename` failed.
+    Io{filename: String, source: io::Error},
+    /// Malformed input. `line` and `column` start at 1. Both are 0 where there are no lines, like in binary files.
+    Parse{filename: String, line: usize, column: usize, message: String},
+    /// Input that is well formed but can't be used, like a scene without objects.
+    Validation(String),
+    /// Valid input that uses something `light` doesn't implement, like a compressed EXR file.
+    UnsupportedFeature(String),
+}
+
+impl LightError{
+    pub fn io(filename: &str, source: io::Error) -> LightError{
+        LightError::Io { filename: filename.to_owned(), source }
+    }
+
+    pub fn parse(filename: &str, line: usize, column: usize, message: impl Into<String>) -> LightError{
+        LightError::Parse { filename: filename.to_owned(), line, column, message: message.into() }
+    }
+}
+
+impl Error for LightError{
+    fn source(&self) -> Option<&(dyn Error + 'static)>{
+        match self{
+            LightError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LightError{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match self{
+            LightError::Io { filename, source } => write!(f, "{}: {}", filename, source),
+            LightError::Parse { filename, line: 0, message, .. } => write!(f, "{}: {}", filename, message),
+            LightError::Parse { filename, line, column, message } => write!(f, "{}:{}:{}  {}", filename, line, column, message),
+            LightError::Validation(message) => write!(f, "{}", message),
+            LightError::UnsupportedFeature(message) => write!(f, "Unsupported: {}", message),
+        }
+    }
+}
+
+/// The column of `part` in `line`, for `LightError::Parse`. `part` has to be a slice of `line`.
+pub(crate) fn column_of(line: &str, part: &str) -> usize{
+    let offset = (part.as_ptr() as usize).saturating_sub(line.as_ptr() as usize).min(line.len());
+    line.get(..offset).map_or(offset, |x| x.chars().count()) + 1
+}
@@ -0,0 +1,232 @@
+use std::{fs::File, io::{BufWriter, Write}, path::Path};
+
+use crate::{image::Image, color_space::ColorSpace, error::LightError};
+
+/// Saves images as the layers of an uncompressed OpenEXR file with 32 bit float channels. A layer with an empty name
+/// becomes the main RGB layer, the others get their name as channel prefix (`albedo.R`, ...). The file is tagged with
+/// the chromaticities of `color_space`, so compositing applications interpret the colours correctly.
+pub fn save_exr(filename: &str, layers: &[(&str, &Image)], color_space: ColorSpace) -> Result<(), LightError>{
+    let (width, height) = match layers.first(){
+        Some((_, image)) => (image.width(), image.height()),
+        None => return Err(LightError::Validation(format!("{}: no layers to save", filename))),
+    };
+    if layers.iter().any(|(_, image)| image.width() != width || image.height() != height){
+        return Err(LightError::Validation(format!("{}: all layers need the same size", filename)));
+    }
+
+    // EXR wants the channels sorted by name
+    let mut channels: Vec<(String, usize, usize)> = Vec::new();
+    for (layer_index, (name, _)) in layers.iter().enumerate(){
+        for (component, channel) in ["R", "G", "B"].iter().enumerate(){
+            let channel_name = if name.is_empty() {channel.to_string()} else {format!("{}.{}", name, channel)};
+            channels.push((channel_name, layer_index, component));
+        }
+    }
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut header: Vec<u8> = Vec::new();
+    header.extend_from_slice(&20000630i32.to_le_bytes());
+    header.extend_from_slice(&2i32.to_le_bytes());
+
+    let mut channel_list: Vec<u8> = Vec::new();
+    for (name, _, _) in &channels{
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        // float pixels, not perceptually linear, reserved, x and y sampling
+        channel_list.extend_from_slice(&2i32.to_le_bytes());
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+    write_attribute(&mut header, "channels", "chlist", &channel_list);
+
+    let chromaticities = color_space.chromaticities();
+    let mut chromaticity_values: Vec<u8> = Vec::new();
+    for value in [chromaticities.red, chromaticities.green, chromaticities.blue, chromaticities.white]{
+        chromaticity_values.extend_from_slice(&value.x.to_le_bytes());
+        chromaticity_values.extend_from_slice(&value.y.to_le_bytes());
+    }
+    write_attribute(&mut header, "chromaticities", "chromaticities", &chromaticity_values);
+
+    write_attribute(&mut header, "compression", "compression", &[0]);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1].iter().flat_map(|x| x.to_le_bytes()).collect();
+    write_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_attribute(&mut header, "displayWindow", "box2i", &window);
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0.0f32.to_le_bytes(), 0.0f32.to_le_bytes()].concat());
+    write_attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    header.push(0);
+
+    // one scanline per block: the y coordinate, the data size and then all channels one after another
+    let line_size = channels.len() * width as usize * 4;
+    let block_size = 8 + line_size;
+    let table_end = header.len() + height as usize * 8;
+
+    let write = || -> std::io::Result<()>{
+        let mut file = BufWriter::new(File::create(Path::new(filename))?);
+        file.write_all(&header)?;
+        for line in 0..height as usize{
+            file.write_all(&((table_end + line * block_size) as u64).to_le_bytes())?;
+        }
+        for line in 0..height{
+            file.write_all(&(line as i32).to_le_bytes())?;
+            file.write_all(&(line_size as i32).to_le_bytes())?;
+            // EXR starts at the top, the images at the bottom
+            let y = height - 1 - line;
+            for (_, layer_index, component) in &channels{
+                let image = layers[*layer_index].1;
+                for x in 0..width{
+                    file.write_all(&image[(x, y)][*component].to_le_bytes())?;
+                }
+            }
+        }
+        file.flush()
+    };
+    write().map_err(|error| LightError::io(filename, error))
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, attribute_type: &str, value: &[u8]){
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(attribute_type.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// Loads the RGB channels of a single part, uncompressed scanline OpenEXR file, such as the ones written by
+/// `save_exr`. Half, float and uint channels are supported. Files with only a `Y` channel load as grey.
+pub fn load_exr(filename: &str) -> Result<Image, LightError>{
+    let data = std::fs::read(filename).map_err(|error| LightError::io(filename, error))?;
+    let invalid = |message: &str| LightError::parse(filename, 0, 0, message);
+    let unsupported = |message: &str| LightError::UnsupportedFeature(format!("{}: {}", filename, message));
+    let mut reader = ByteReader { data: &data, position: 0 };
+
+    if reader.u32().ok_or_else(|| invalid("not an EXR file"))? != 20000630{
+        return Err(invalid("not an EXR file"));
+    }
+    let version = reader.u32().ok_or_else(|| invalid("unexpected end of file"))?;
+    if version & 0xff != 2 || version & 0x1e00 != 0{
+        return Err(unsupported("only single part scanline files are supported"));
+    }
+
+    // name and pixel type of every channel, in file order
+    let mut channels: Vec<(String, u32)> = Vec::new();
+    let mut compression = None;
+    let mut data_window = None;
+    loop{
+        let name = reader.string().ok_or_else(|| invalid("unexpected end of header"))?;
+        if name.is_empty(){
+            break;
+        }
+        let attribute_type = reader.string().ok_or_else(|| invalid("unexpected end of header"))?;
+        let size = reader.u32().ok_or_else(|| invalid("unexpected end of header"))? as usize;
+        let value = reader.bytes(size).ok_or_else(|| invalid("unexpected end of header"))?;
+        let mut value_reader = ByteReader { data: value, position: 0 };
+        match (name.as_str(), attribute_type.as_str()){
+            ("channels", "chlist") => {
+                while let Some(channel_name) = value_reader.string(){
+                    if channel_name.is_empty(){
+                        break;
+                    }
+                    let pixel_type = value_reader.u32().ok_or_else(|| invalid("invalid channel list"))?;
+                    value_reader.bytes(12).ok_or_else(|| invalid("invalid channel list"))?;
+                    channels.push((channel_name, pixel_type));
+                }
+            }
+            ("compression", _) => compression = value.first().copied(),
+            ("dataWindow", "box2i") => {
+                let mut window = [0i32; 4];
+                for coordinate in &mut window{
+                    *coordinate = value_reader.u32().ok_or_else(|| invalid("invalid data window"))? as i32;
+                }
+                data_window = Some(window);
+            }
+            _ => {}
+        }
+    }
+    if compression != Some(0){
+        return Err(unsupported("only uncompressed files are supported"));
+    }
+    let [min_x, min_y, max_x, max_y] = data_window.ok_or_else(|| invalid("missing data window"))?;
+    let (width, height) = (max_x as i64 - min_x as i64 + 1, max_y as i64 - min_y as i64 + 1);
+    // every pixel takes at least two bytes, which also keeps broken headers from allocating huge images
+    if width <= 0 || height <= 0 || width * height > data.len() as i64{
+        return Err(invalid("invalid data window"));
+    }
+    let (width, height) = (width as u32, height as u32);
+
+    let find_channel = |names: &[&str]| names.iter().find_map(|name| channels.iter().position(|(channel, _)| channel == name));
+    let targets: Vec<usize> = match (find_channel(&["R"]), find_channel(&["G"]), find_channel(&["B"]), find_channel(&["Y"])){
+        (Some(r), Some(g), Some(b), _) => vec![r, g, b],
+        (_, _, _, Some(y)) => vec![y, y, y],
+        _ => return Err(invalid("no R, G and B or Y channels")),
+    };
+    let channel_size = |pixel_type: u32| if pixel_type == 1 {2} else {4};
+
+    // skip the offset table, the blocks follow it directly
+    reader.bytes(height as usize * 8).ok_or_else(|| invalid("unexpected end of file"))?;
+    let mut image = Image::new(width, height);
+    for _ in 0..height{
+        let line = reader.u32().ok_or_else(|| invalid("unexpected end of file"))? as i32 as i64 - min_y as i64;
+        reader.u32().ok_or_else(|| invalid("unexpected end of file"))?;
+        if line < 0 || line >= height as i64{
+            return Err(invalid("scanline outside of the data window"));
+        }
+        // EXR starts at the top, the images at the bottom
+        let y = height - 1 - line as u32;
+        for (channel_index, (_, pixel_type)) in channels.iter().enumerate(){
+            let values = reader.bytes(channel_size(*pixel_type) * width as usize).ok_or_else(|| invalid("unexpected end of file"))?;
+            for (component, _) in targets.iter().enumerate().filter(|(_, target)| **target == channel_index){
+                for x in 0..width as usize{
+                    let bytes = &values[x * channel_size(*pixel_type)..(x + 1) * channel_size(*pixel_type)];
+                    image[(x as u32, y)][component] = match pixel_type{
+                        0 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32,
+                        1 => half_to_f32(u16::from_le_bytes([bytes[0], bytes[1]])),
+                        _ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                    };
+                }
+            }
+        }
+    }
+    Ok(image)
+}
+
+struct ByteReader<'a>{
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a>{
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]>{
+        let bytes = self.data.get(self.position..self.position + count)?;
+        self.position += count;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32>{
+        let bytes = self.bytes(4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// A null terminated string.
+    fn string(&mut self) -> Option<String>{
+        let length = self.data.get(self.position..)?.iter().position(|x| *x == 0)?;
+        let string = String::from_utf8_lossy(self.bytes(length)?).into_owned();
+        self.position += 1;
+        Some(string)
+    }
+}
+
+fn half_to_f32(half: u16) -> f32{
+    let sign = if half & 0x8000 != 0 {-1.0} else {1.0};
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    sign * match exponent{
+        0 => mantissa * 2.0f32.powi(-24),
+        31 => if mantissa == 0.0 {f32::INFINITY} else {f32::NAN},
+        _ => (1.0 + mantissa / 1024.0) * 2.0f32.powi(exponent - 15),
+    }
+}
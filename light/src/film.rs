@@ -0,0 +1,161 @@
+use std::f32::consts::PI;
+
+use ultraviolet::{Vec2, Vec3};
+
+use crate::image::Image;
+
+/// Pixels with a smaller total filter weight are left black. Filters with negative lobes can push the weight of a pixel
+/// that only has samples near the edge of the filter to zero or below, and dividing by it would blow the pixel up.
+const MIN_WEIGHT: f32 = 1e-3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterType{
+    Box,
+    Tent,
+    Gaussian,
+    /// Mitchell-Netravali with B = C = 1/3. Sharper than the Gaussian, with slightly negative lobes.
+    Mitchell,
+    BlackmanHarris,
+}
+
+impl FilterType{
+    pub fn name(&self) -> &'static str{
+        match self{
+            FilterType::Box => "box",
+            FilterType::Tent => "tent",
+            FilterType::Gaussian => "gaussian",
+            FilterType::Mitchell => "mitchell",
+            FilterType::BlackmanHarris => "blackman_harris",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FilterType>{
+        [FilterType::Box, FilterType::Tent, FilterType::Gaussian, FilterType::Mitchell, FilterType::BlackmanHarris].into_iter().find(|x| x.name() == name)
+    }
+
+    /// The usual radius of the filter in pixels.
+    pub fn default_radius(&self) -> f32{
+        match self{
+            FilterType::Box => 0.5,
+            FilterType::Tent => 1.0,
+            FilterType::Gaussian => 1.5,
+            FilterType::Mitchell => 2.0,
+            FilterType::BlackmanHarris => 2.0,
+        }
+    }
+}
+
+/// A separable pixel reconstruction filter. Larger radii give smoother, less aliased but blurrier images.
+#[derive(Clone, Copy, Debug)]
+pub struct Filter{
+    pub filter_type: FilterType,
+    /// In pixels.
+    pub radius: f32,
+}
+
+impl Filter{
+    pub fn new(filter_type: FilterType, radius: f32) -> Filter{
+        Filter { filter_type, radius }
+    }
+
+    /// Weight of a sample at `offset` pixels from the pixel center.
+    pub fn evaluate(&self, offset: Vec2) -> f32{
+        self.evaluate_1d(offset.x) * self.evaluate_1d(offset.y)
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32{
+        let r = self.radius;
+        match self.filter_type{
+            // half open, so a sample exactly between two pixels only counts once
+            FilterType::Box => if -r < x && x <= r {1.0} else {0.0},
+            FilterType::Tent => (1.0 - x.abs() / r).max(0.0),
+            FilterType::Gaussian => {
+                // sigma of a third of the radius, shifted down so the filter ends at zero
+                let sigma = r / 3.0;
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(r)).max(0.0)
+            }
+            FilterType::Mitchell => {
+                // the standard filter spans [-2, 2], scaled to the radius
+                let x = (2.0 * x / r).abs();
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                let value = if x < 1.0{
+                    (12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)
+                }
+                else if x < 2.0{
+                    (-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)
+                }
+                else{
+                    0.0
+                };
+                value / 6.0
+            }
+            FilterType::BlackmanHarris => {
+                if x.abs() >= r{
+                    return 0.0;
+                }
+                let t = 2.0 * PI * (x / (2.0 * r) + 0.5);
+                0.35875 - 0.48829 * t.cos() + 0.14128 * (2.0 * t).cos() - 0.01168 * (3.0 * t).cos()
+            }
+        }
+    }
+}
+
+/// Collects camera samples into pixels. Every sample is splatted into all pixels within the filter radius,
+/// weighted by the filter, and every pixel is the weighted average of the samples around it.
+#[derive(Clone, Debug)]
+pub struct Film{
+    width: u32,
+    height: u32,
+    filter: Filter,
+    weighted_sum: Image,
+    weight_sum: Vec<f32>,
+}
+
+impl Film{
+    pub fn new(width: u32, height: u32, filter: Filter) -> Film{
+        Film {
+            width,
+            height,
+            filter,
+            weighted_sum: Image::new(width, height),
+            weight_sum: vec![0.0; (width * height) as usize],
+        }
+    }
+
+    /// Adds a sample taken at `offset` within `pixel`, with the offset in [0, 1)^2 like the one used for the camera ray.
+    pub fn add_sample(&mut self, pixel: (u32, u32), offset: Vec2, color: Vec3){
+        // pixel offsets are kept relative to the sample's pixel, so the box filter matches the sample's pixel exactly
+        let radius = self.filter.radius;
+        let min = (offset - Vec2::broadcast(0.5 + radius)).map(|x| x.ceil());
+        let max = (offset + Vec2::broadcast(radius - 0.5)).map(|x| x.floor());
+        let min_x = (pixel.0 as i64 + min.x as i64).max(0);
+        let min_y = (pixel.1 as i64 + min.y as i64).max(0);
+        let max_x = (pixel.0 as i64 + max.x as i64).min(self.width as i64 - 1);
+        let max_y = (pixel.1 as i64 + max.y as i64).min(self.height as i64 - 1);
+        for y in min_y..=max_y{
+            for x in min_x..=max_x{
+                let relative = Vec2::new((x - pixel.0 as i64) as f32, (y - pixel.1 as i64) as f32);
+                let weight = self.filter.evaluate(relative + Vec2::broadcast(0.5) - offset);
+                if weight == 0.0{
+                    continue;
+                }
+                let (x, y) = (x as u32, y as u32);
+                self.weighted_sum[(x, y)] += color * weight;
+                self.weight_sum[(y * self.width + x) as usize] += weight;
+            }
+        }
+    }
+
+    /// The reconstructed image.
+    pub fn image(&self) -> Image{
+        let mut image = self.weighted_sum.clone();
+        for y in 0..self.height{
+            for x in 0..self.width{
+                let weight = self.weight_sum[(y * self.width + x) as usize];
+                image[(x, y)] = if weight > MIN_WEIGHT {image[(x, y)] / weight} else {Vec3::zero()};
+            }
+        }
+        image
+    }
+}
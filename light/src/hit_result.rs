@@ -1,27 +1,39 @@
-use ultraviolet::Vec3;
+use ultraviolet::{Vec3, Vec2};
 
 use crate::material::Material;
 
-pub struct HitResult{
+pub struct HitResult<'a>{
     pub t: f32,
     pub normal: Vec3,
-    pub material: Option<Material>,
+    pub uv: Vec2,
+    /// Shading tangent frame pointing along increasing u and v. Only meaningful for surfaces with UV coordinates.
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    pub material: Option<&'a Material>,
+    /// Ids of the hit object and its material, starting at 1. Used for the ID passes.
+    pub object_id: u32,
+    pub material_id: u32,
     pub is_front_face: bool,
 }
 
-impl HitResult{
+impl HitResult<'_>{
     pub fn set_face_normal(&mut self, ray_direction: Vec3, outward_normal: Vec3){
         self.is_front_face = ray_direction.dot(outward_normal) < 0.0;
         self.normal = if self.is_front_face {outward_normal} else {-outward_normal};
     }
 }
 
-impl Default for HitResult{
-    fn default() -> HitResult{
+impl Default for HitResult<'_>{
+    fn default() -> Self{
         HitResult{
             t: f32::INFINITY,
             normal: Vec3{x: 0.0, y: 0.0, z: 0.0},
+            uv: Vec2{x: 0.0, y: 0.0},
+            tangent: Vec3{x: 0.0, y: 0.0, z: 0.0},
+            bitangent: Vec3{x: 0.0, y: 0.0, z: 0.0},
             material: None,
+            object_id: 0,
+            material_id: 0,
             is_front_face: false,
         }
     }
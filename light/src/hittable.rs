@@ -1,16 +1,24 @@
 use ultraviolet::Vec3;
 
-use crate::{ray::Ray, hit_result::HitResult};
+use crate::{ray::Ray, hit_result::HitResult, validation::Diagnostic};
 
 pub trait Hittable: Sync + Send{
-    fn hit(&self, ray: Ray, hit: &mut HitResult, min_distance: f32) -> bool;
+    fn hit<'a>(&'a self, ray: Ray, hit: &mut HitResult<'a>, min_distance: f32) -> bool;
 
     fn get_min_bounds(&self) -> Vec3;
     fn get_max_bounds(&self) -> Vec3;
+
+    /// Approximate number of bytes used by this object and everything it owns.
+    fn memory_usage(&self) -> usize{
+        size_of_val(self)
+    }
+
+    /// Adds the problems of this object and everything it owns, see `Scene::validate`.
+    fn validate(&self, _diagnostics: &mut Vec<Diagnostic>){}
 }
 
 impl Hittable for Vec<Box<dyn Hittable>>{
-    fn hit(&self, ray: Ray, hit: &mut HitResult, min_distance: f32) -> bool{
+    fn hit<'a>(&'a self, ray: Ray, hit: &mut HitResult<'a>, min_distance: f32) -> bool{
         let mut did_hit = false;
         for hittable in self.iter() {
             did_hit |= hittable.hit(ray, hit, min_distance);
@@ -19,9 +27,17 @@ impl Hittable for Vec<Box<dyn Hittable>>{
     }
     
     fn get_min_bounds(&self) -> Vec3{
-        self.iter().map(|obj| obj.get_min_bounds()).reduce(|a, b| a.min_by_component(b)).unwrap()
+        self.iter().map(|obj| obj.get_min_bounds()).reduce(|a, b| a.min_by_component(b)).unwrap_or(Vec3::zero())
     }
     fn get_max_bounds(&self) -> Vec3{
-        self.iter().map(|obj| obj.get_max_bounds()).reduce(|a, b| a.max_by_component(b)).unwrap()
+        self.iter().map(|obj| obj.get_max_bounds()).reduce(|a, b| a.max_by_component(b)).unwrap_or(Vec3::zero())
+    }
+    fn validate(&self, diagnostics: &mut Vec<Diagnostic>){
+        for hittable in self.iter(){
+            hittable.validate(diagnostics);
+        }
+    }
+    fn memory_usage(&self) -> usize{
+        size_of_val(self) + self.capacity() * size_of::<Box<dyn Hittable>>() + self.iter().map(|obj| obj.memory_usage()).sum::<usize>()
     }
 }
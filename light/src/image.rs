@@ -1,7 +1,9 @@
-use std::{ops::{Index, IndexMut}, path::Path, fs::File, io::Write};
+use std::{ops::{Index, IndexMut}, path::Path, fs::{File, read}, io::Write};
 
 use ultraviolet::Vec3;
 
+use crate::error::LightError;
+
 #[derive(Debug, Clone)]
 pub struct Image{
     pixels: Vec<Vec3>,
@@ -18,15 +20,117 @@ impl Image{
         };
     }
 
-    pub fn save_to_file(&self, filename: &str) -> std::io::Result<()>{
+    pub fn save_to_file(&self, filename: &str) -> Result<(), LightError>{
+        self.write_ppm(filename).map_err(|error| LightError::io(filename, error))
+    }
+
+    fn write_ppm(&self, filename: &str) -> std::io::Result<()>{
         let path = Path::new(filename);
         let mut file = File::create(& path)?;
         write!(file, "P6\n{} {} 255\n", self.width, self.height)?;
 
-        file.write(self.get_bytes_inverse_y().as_slice())?;
+        file.write_all(self.get_bytes_inverse_y().as_slice())?;
         return Ok(());
     }
 
+    /// Saves the unclamped linear values as a little endian PFM file.
+    pub fn save_to_pfm(&self, filename: &str) -> Result<(), LightError>{
+        self.write_pfm(filename).map_err(|error| LightError::io(filename, error))
+    }
+
+    fn write_pfm(&self, filename: &str) -> std::io::Result<()>{
+        let mut file = File::create(Path::new(filename))?;
+        write!(file, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+
+        // PFM stores the bottom row first, just like the pixels are stored here
+        let mut bytes: Vec<u8> = Vec::with_capacity(self.pixels.len() * 12);
+        for pixel in &self.pixels{
+            for channel in [pixel.x, pixel.y, pixel.z]{
+                bytes.extend_from_slice(&channel.to_le_bytes());
+            }
+        }
+        file.write_all(bytes.as_slice())
+    }
+
+    /// Loads a PNG, JPEG, binary PPM (P6) or PFM (PF) file. Rows are flipped so that y = 0 is the bottom row, like in rendered images.
+    /// 8 and 16 bit values are mapped to [0, 1] without decoding the transfer function, alpha is dropped.
+    pub fn load_from_file(filename: &str) -> Result<Image, LightError>{
+        let data = read(filename).map_err(|error| LightError::io(filename, error))?;
+        if data.starts_with(b"\x89PNG\r\n\x1a\n"){
+            return load_png(filename, &data);
+        }
+        if data.starts_with(&[0xFF, 0xD8]){
+            return load_jpeg(filename, &data);
+        }
+        let invalid = |message: &str| LightError::parse(filename, 0, 0, message);
+
+        // the header consists of four whitespace separated tokens followed by a single whitespace character
+        let mut tokens: Vec<String> = Vec::new();
+        let mut position = 0;
+        while tokens.len() < 4{
+            while position < data.len() && data[position].is_ascii_whitespace(){
+                position += 1;
+            }
+            if position < data.len() && data[position] == b'#'{
+                while position < data.len() && data[position] != b'\n'{
+                    position += 1;
+                }
+                continue;
+            }
+            let start = position;
+            while position < data.len() && !data[position].is_ascii_whitespace(){
+                position += 1;
+            }
+            if start == position{
+                return Err(invalid("unexpected end of header"));
+            }
+            tokens.push(String::from_utf8_lossy(&data[start..position]).into_owned());
+        }
+        position += 1;
+
+        let width: u32 = tokens[1].parse().map_err(|_| invalid("invalid width"))?;
+        let height: u32 = tokens[2].parse().map_err(|_| invalid("invalid height"))?;
+        let pixel_count = width.checked_mul(height).filter(|x| x.checked_mul(12).is_some()).ok_or_else(|| invalid("the image is too large"))? as usize;
+        let pixel_data = &data[position.min(data.len())..];
+
+        let image = match tokens[0].as_str(){
+            "P6" => {
+                let max_value: u32 = tokens[3].parse().map_err(|_| invalid("invalid maximum value"))?;
+                let bytes_per_channel = if max_value < 256 {1} else {2};
+                if pixel_data.len() / (3 * bytes_per_channel as usize) < pixel_count{
+                    return Err(invalid("not enough pixel data"));
+                }
+                let mut image = Image::new(width, height);
+                for (i, channel) in pixel_data.chunks_exact(bytes_per_channel as usize).take(pixel_count * 3).enumerate(){
+                    let value = if bytes_per_channel == 1 {channel[0] as u32} else {(channel[0] as u32) << 8 | channel[1] as u32};
+                    let pixel = i as u32 / 3;
+                    image[(pixel % width, height - 1 - pixel / width)][i % 3] = value as f32 / max_value as f32;
+                }
+                image
+            }
+            "PF" => {
+                let scale: f32 = tokens[3].parse().map_err(|_| invalid("invalid scale"))?;
+                if pixel_data.len() / 12 < pixel_count{
+                    return Err(invalid("not enough pixel data"));
+                }
+                let mut image = Image::new(width, height);
+                // PFM stores the bottom row first
+                for (i, channel) in pixel_data.chunks_exact(4).take(pixel_count * 3).enumerate(){
+                    let bytes = [channel[0], channel[1], channel[2], channel[3]];
+                    let value = if scale < 0.0 {f32::from_le_bytes(bytes)} else {f32::from_be_bytes(bytes)};
+                    let pixel = i as u32 / 3;
+                    image[(pixel % width, pixel / width)][i % 3] = value;
+                }
+                image
+            }
+            magic => {
+                return Err(LightError::UnsupportedFeature(format!("{}: image format '{}'", filename, magic)));
+            }
+        };
+
+        return Ok(image);
+    }
+
     pub fn get_bytes_inverse_y(&self) -> Vec<u8>{
         let mut bytes: Vec<u8> = Vec::new();
        
@@ -75,6 +179,68 @@ impl Image{
     pub fn height(&self) -> u32 {self.height}
 }
 
+fn load_png(filename: &str, data: &[u8]) -> Result<Image, LightError>{
+    let invalid = |error: png::DecodingError| LightError::parse(filename, 0, 0, format!("invalid PNG file: {}", error));
+    let mut decoder = png::Decoder::new(data);
+    // palettes and bit depths below 8 are expanded to 8 bit gray or RGB(A)
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(invalid)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(invalid)?;
+
+    let channels = info.color_type.samples();
+    let max_value = match info.bit_depth{
+        png::BitDepth::Sixteen => 65535.0,
+        _ => 255.0,
+    };
+    let value = |offset: usize| -> f32{
+        if info.bit_depth == png::BitDepth::Sixteen{
+            u16::from_be_bytes([buffer[2 * offset], buffer[2 * offset + 1]]) as f32 / max_value
+        }
+        else{
+            buffer[offset] as f32 / max_value
+        }
+    };
+    let samples_per_line = info.line_size / if info.bit_depth == png::BitDepth::Sixteen {2} else {1};
+    let mut image = Image::new(info.width, info.height);
+    for y in 0..info.height{
+        for x in 0..info.width{
+            let offset = y as usize * samples_per_line + x as usize * channels;
+            image[(x, info.height - 1 - y)] = if channels < 3{
+                Vec3::broadcast(value(offset))
+            }
+            else{
+                Vec3::new(value(offset), value(offset + 1), value(offset + 2))
+            };
+        }
+    }
+    Ok(image)
+}
+
+fn load_jpeg(filename: &str, data: &[u8]) -> Result<Image, LightError>{
+    let invalid = |error: jpeg_decoder::Error| LightError::parse(filename, 0, 0, format!("invalid JPEG file: {}", error));
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    let pixels = decoder.decode().map_err(invalid)?;
+    let info = decoder.info().ok_or_else(|| LightError::parse(filename, 0, 0, "invalid JPEG file: missing header"))?;
+
+    let (width, height) = (info.width as u32, info.height as u32);
+    let mut image = Image::new(width, height);
+    for y in 0..height{
+        for x in 0..width{
+            let i = (x + y * width) as usize;
+            image[(x, height - 1 - y)] = match info.pixel_format{
+                jpeg_decoder::PixelFormat::L8 => Vec3::broadcast(pixels[i] as f32 / 255.0),
+                jpeg_decoder::PixelFormat::L16 => Vec3::broadcast(u16::from_ne_bytes([pixels[2 * i], pixels[2 * i + 1]]) as f32 / 65535.0),
+                jpeg_decoder::PixelFormat::RGB24 => Vec3::new(pixels[3 * i] as f32, pixels[3 * i + 1] as f32, pixels[3 * i + 2] as f32) / 255.0,
+                jpeg_decoder::PixelFormat::CMYK32 => {
+                    return Err(LightError::UnsupportedFeature(format!("{}: CMYK JPEG files", filename)));
+                }
+            };
+        }
+    }
+    Ok(image)
+}
+
 impl Index<(u32, u32)> for Image{
     type Output = Vec3;
     fn index(&self, idx: (u32, u32)) -> &Vec3{
@@ -1,4 +1,6 @@
-use ultraviolet::Vec3;
+use ultraviolet::{Vec2, Vec3};
+
+use crate::{image::Image, spectrum, color_space::{ColorSpace, convert}};
 
 pub fn gamma_correct(gamma: f32, color: Vec3) -> Vec3{
     Vec3{
@@ -11,3 +13,438 @@ pub fn gamma_correct(gamma: f32, color: Vec3) -> Vec3{
 pub fn average_samples(num_samples: usize, color: Vec3) -> Vec3{
     color / num_samples as f32
 }
+
+/// Converts the XYZ colours accumulated by spectral rendering to linear RGB.
+pub fn xyz_to_rgb(color: Vec3) -> Vec3{
+    spectrum::xyz_to_rgb(color)
+}
+
+/// Converts rendered colours to the working space. They already are in it, unless they are XYZ from spectral
+/// rendering.
+pub fn to_working_space(is_spectral: bool, working_space: ColorSpace, color: Vec3) -> Vec3{
+    if is_spectral{
+        return convert(xyz_to_rgb(color), ColorSpace::LinearRec709, working_space);
+    }
+    color
+}
+
+/// Scales linear colours by `2^ev`.
+pub fn exposure(ev: f32, color: Vec3) -> Vec3{
+    color * 2.0f32.powf(ev)
+}
+
+/// The piecewise sRGB transfer function, for linear colours in [0, 1].
+pub fn srgb_transfer(color: Vec3) -> Vec3{
+    color.map(|x| {
+        let x = x.clamp(0.0, 1.0);
+        if x <= 0.0031308 {12.92 * x} else {1.055 * x.powf(1.0 / 2.4) - 0.055}
+    })
+}
+
+fn luminance(color: Vec3) -> f32{
+    color.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+/// Maps HDR colours to displayable linear colours in [0, 1].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapper{
+    /// Just clip everything above 1.
+    Clamp,
+    Reinhard,
+    Aces,
+    Agx,
+}
+
+impl ToneMapper{
+    pub fn name(&self) -> &'static str{
+        match self{
+            ToneMapper::Clamp => "clamp",
+            ToneMapper::Reinhard => "reinhard",
+            ToneMapper::Aces => "aces",
+            ToneMapper::Agx => "agx",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ToneMapper>{
+        [ToneMapper::Clamp, ToneMapper::Reinhard, ToneMapper::Aces, ToneMapper::Agx].into_iter().find(|x| x.name() == name)
+    }
+
+    /// The next operator, for cycling through them in the GUI.
+    pub fn next(&self) -> ToneMapper{
+        match self{
+            ToneMapper::Clamp => ToneMapper::Reinhard,
+            ToneMapper::Reinhard => ToneMapper::Aces,
+            ToneMapper::Aces => ToneMapper::Agx,
+            ToneMapper::Agx => ToneMapper::Clamp,
+        }
+    }
+}
+
+pub fn tone_map(tone_mapper: ToneMapper, color: Vec3) -> Vec3{
+    match tone_mapper{
+        ToneMapper::Clamp => color.clamped(Vec3::zero(), Vec3::one()),
+        ToneMapper::Reinhard => reinhard(color),
+        ToneMapper::Aces => aces_filmic(color),
+        ToneMapper::Agx => agx(color),
+    }
+}
+
+/// Exposure, tone mapping and the sRGB transfer function: turns linear colours in the working space into colours
+/// for an sRGB display.
+pub fn display_transform(ev: f32, tone_mapper: ToneMapper, working_space: ColorSpace, color: Vec3) -> Vec3{
+    srgb_transfer(tone_map(tone_mapper, exposure(ev, convert(color, working_space, ColorSpace::LinearRec709))))
+}
+
+/// Reinhard's operator applied to the luminance, so the hue is preserved. Saturated colours can still end up above
+/// one in a single channel; those are scaled down as a whole instead of being clipped per channel.
+pub fn reinhard(color: Vec3) -> Vec3{
+    let color = color.max_by_component(Vec3::zero());
+    let mapped = color / (1.0 + luminance(color));
+    mapped / mapped.component_max().max(1.0)
+}
+
+fn multiply_rows(rows: [[f32; 3]; 3], color: Vec3) -> Vec3{
+    Vec3::new(
+        rows[0][0] * color.x + rows[0][1] * color.y + rows[0][2] * color.z,
+        rows[1][0] * color.x + rows[1][1] * color.y + rows[1][2] * color.z,
+        rows[2][0] * color.x + rows[2][1] * color.y + rows[2][2] * color.z,
+    )
+}
+
+/// Stephen Hill's fit of the ACES reference rendering and output transforms for sRGB displays.
+pub fn aces_filmic(color: Vec3) -> Vec3{
+    const INPUT: [[f32; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: [[f32; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let color = multiply_rows(INPUT, color.max_by_component(Vec3::zero()));
+    let fitted = color.map(|x| (x * (x + 0.0245786) - 0.000090537) / (x * (0.983729 * x + 0.432951) + 0.238081));
+    multiply_rows(OUTPUT, fitted).clamped(Vec3::zero(), Vec3::one())
+}
+
+/// The AgX base look by Troy Sobotka, using Benjamin Wrensch's polynomial approximation of the sigmoid.
+pub fn agx(color: Vec3) -> Vec3{
+    const INSET: [[f32; 3]; 3] = [
+        [0.8424791, 0.0784336, 0.07922375],
+        [0.04232824, 0.8784686, 0.07916613],
+        [0.04237565, 0.0784336, 0.879143],
+    ];
+    const OUTSET: [[f32; 3]; 3] = [
+        [1.196879, -0.09802088, -0.09902974],
+        [-0.05289685, 1.151903, -0.09896118],
+        [-0.05297164, -0.09804345, 1.151074],
+    ];
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let color = multiply_rows(INSET, color.max_by_component(Vec3::zero()));
+    let curve = color.map(|x| {
+        let x = ((x.max(1e-10).log2() - MIN_EV) / (MAX_EV - MIN_EV)).clamp(0.0, 1.0);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+    });
+    // the curve produces display encoded values with a gamma of about 2.2
+    multiply_rows(OUTSET, curve).map(|x| x.max(0.0).powf(2.2)).clamped(Vec3::zero(), Vec3::one())
+}
+
+/// Parameters of the edge-avoiding à-trous denoiser.
+#[derive(Clone, Debug)]
+pub struct DenoiseSettings{
+    /// Number of filter passes. Each pass doubles the filter radius.
+    pub iterations: u32,
+    /// How different two (tone compressed) colours may be before they stop blurring into each other.
+    pub color_sigma: f32,
+    pub normal_sigma: f32,
+    pub albedo_sigma: f32,
+}
+
+impl Default for DenoiseSettings{
+    fn default() -> DenoiseSettings{
+        DenoiseSettings {
+            iterations: 5,
+            color_sigma: 0.6,
+            normal_sigma: 0.3,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+const ATROUS_KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Denoises linear HDR colours with an edge-avoiding à-trous wavelet filter (Dammertz et al. 2010), guided by the
+/// albedo and normals of the first hit. The lighting is filtered without the albedo so textures stay sharp.
+pub fn denoise(color: &Image, albedo: &Image, normal: &Image, settings: &DenoiseSettings) -> Image{
+    let width = color.width();
+    let height = color.height();
+    let demodulation = |albedo: Vec3| albedo.map(|x| x.max(1e-2));
+
+    let mut illumination = color.clone();
+    for y in 0..height{
+        for x in 0..width{
+            illumination[(x, y)] = color[(x, y)] / demodulation(albedo[(x, y)]);
+        }
+    }
+
+    for iteration in 0..settings.iterations{
+        let step = 1i64 << iteration;
+        // later passes only smooth what is left, so they are stricter about colour differences
+        let color_sigma = settings.color_sigma / (1 << iteration) as f32;
+        let mut filtered = illumination.clone();
+        for y in 0..height{
+            for x in 0..width{
+                let center_color = compress(illumination[(x, y)]);
+                let center_normal = normal[(x, y)];
+                let center_albedo = albedo[(x, y)];
+                let mut sum = Vec3::zero();
+                let mut weight_sum = 0.0;
+                for (j, kernel_y) in ATROUS_KERNEL.iter().enumerate(){
+                    let sample_y = y as i64 + (j as i64 - 2) * step;
+                    if sample_y < 0 || sample_y >= height as i64{
+                        continue;
+                    }
+                    for (i, kernel_x) in ATROUS_KERNEL.iter().enumerate(){
+                        let sample_x = x as i64 + (i as i64 - 2) * step;
+                        if sample_x < 0 || sample_x >= width as i64{
+                            continue;
+                        }
+                        let position = (sample_x as u32, sample_y as u32);
+                        let sample = illumination[position];
+                        let weight = kernel_x * kernel_y
+                            * edge_weight((compress(sample) - center_color).mag_sq(), color_sigma)
+                            * edge_weight((normal[position] - center_normal).mag_sq(), settings.normal_sigma)
+                            * edge_weight((albedo[position] - center_albedo).mag_sq(), settings.albedo_sigma);
+                        sum += sample * weight;
+                        weight_sum += weight;
+                    }
+                }
+                filtered[(x, y)] = sum / weight_sum;
+            }
+        }
+        illumination = filtered;
+    }
+
+    for y in 0..height{
+        for x in 0..width{
+            illumination[(x, y)] *= demodulation(albedo[(x, y)]);
+        }
+    }
+    illumination
+}
+
+/// Maps HDR colours to [0, 1) so that colour differences mean the same thing in dark and bright areas.
+fn compress(color: Vec3) -> Vec3{
+    color.map(|x| x.max(0.0) / (1.0 + x.max(0.0)))
+}
+
+fn edge_weight(distance_squared: f32, sigma: f32) -> f32{
+    (-distance_squared / (sigma * sigma)).exp()
+}
+
+/// Lens and camera effects applied to the linear HDR image before tone mapping. Everything is off by default.
+#[derive(Clone, Debug)]
+pub struct PostProcessSettings{
+    /// Luminance above which pixels start to bloom and glare.
+    pub bloom_threshold: f32,
+    /// Strength of the soft glow around bright areas.
+    pub bloom_intensity: f32,
+    /// Standard deviation of the glow as a fraction of the image height.
+    pub bloom_radius: f32,
+    /// Strength of the star shaped streaks around bright areas.
+    pub glare_intensity: f32,
+    /// Number of streaks of the star.
+    pub glare_streaks: u32,
+    /// Length of the streaks as a fraction of the image height.
+    pub glare_length: f32,
+    /// How much the corners are darkened, from 0 to 1.
+    pub vignette: f32,
+    /// Radial distortion. Positive values give barrel, negative ones pincushion distortion.
+    pub lens_distortion: f32,
+    /// Lateral chromatic aberration, the difference in distortion between the red and the blue channel.
+    pub chromatic_aberration: f32,
+}
+
+impl Default for PostProcessSettings{
+    fn default() -> PostProcessSettings{
+        PostProcessSettings {
+            bloom_threshold: 1.0,
+            bloom_intensity: 0.0,
+            bloom_radius: 0.01,
+            glare_intensity: 0.0,
+            glare_streaks: 6,
+            glare_length: 0.1,
+            vignette: 0.0,
+            lens_distortion: 0.0,
+            chromatic_aberration: 0.0,
+        }
+    }
+}
+
+/// Applies bloom, glare, lens distortion, chromatic aberration and vignetting, in the order light would encounter them.
+pub fn post_process(image: &Image, settings: &PostProcessSettings) -> Image{
+    let mut result = image.clone();
+    let height = image.height() as f32;
+
+    if settings.bloom_intensity > 0.0 || settings.glare_intensity > 0.0{
+        let bright = bright_pass(image, settings.bloom_threshold);
+        if settings.bloom_intensity > 0.0{
+            let bloom = gaussian_blur(&bright, settings.bloom_radius * height);
+            add_scaled(&mut result, &bloom, settings.bloom_intensity);
+        }
+        if settings.glare_intensity > 0.0 && settings.glare_streaks > 0{
+            let glare = star_glare(&bright, settings.glare_streaks, settings.glare_length * height);
+            add_scaled(&mut result, &glare, settings.glare_intensity);
+        }
+    }
+    if settings.lens_distortion != 0.0 || settings.chromatic_aberration != 0.0{
+        result = distort(&result, settings.lens_distortion, settings.chromatic_aberration);
+    }
+    if settings.vignette > 0.0{
+        let (width, height) = (result.width(), result.height());
+        for y in 0..height{
+            for x in 0..width{
+                let r_squared = normalized_position(&result, Vec2::new(x as f32 + 0.5, y as f32 + 0.5)).mag_sq();
+                result[(x, y)] *= (1.0 - settings.vignette.min(1.0) * r_squared).powi(2);
+            }
+        }
+    }
+    result
+}
+
+/// Keeps the part of every pixel above `threshold`, preserving the hue.
+fn bright_pass(image: &Image, threshold: f32) -> Image{
+    let mut bright = image.clone();
+    bright.apply_filter(|color| {
+        let color = color.max_by_component(Vec3::zero());
+        let luminance = luminance(color);
+        if luminance <= threshold {Vec3::zero()} else {color * (luminance - threshold) / luminance}
+    });
+    bright
+}
+
+fn add_scaled(image: &mut Image, other: &Image, scale: f32){
+    for y in 0..image.height(){
+        for x in 0..image.width(){
+            image[(x, y)] += other[(x, y)] * scale;
+        }
+    }
+}
+
+/// Separable Gaussian blur with a standard deviation of `sigma` pixels. Weights outside the image are dropped and the
+/// rest is renormalized per source pixel, so no energy is lost or gained at the borders.
+fn gaussian_blur(image: &Image, sigma: f32) -> Image{
+    if sigma <= 0.0{
+        return image.clone();
+    }
+    let radius = (3.0 * sigma).ceil() as i64;
+    let kernel: Vec<f32> = (-radius..=radius).map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp()).collect();
+    let blur_pass = |image: &Image, direction: (i64, i64)| {
+        let (width, height) = (image.width() as i64, image.height() as i64);
+        let length = if direction.0 != 0 {width} else {height};
+        // the part of the kernel around each pixel that lies inside of the image, so every pixel spreads exactly its
+        // own energy, even next to the border
+        let kept_weights: Vec<f32> = (0..length)
+            .map(|center| (-radius..=radius).zip(kernel.iter()).filter(|(i, _)| (0..length).contains(&(center + i))).map(|(_, weight)| weight).sum())
+            .collect();
+        let mut blurred = Image::new(image.width(), image.height());
+        for y in 0..height{
+            for x in 0..width{
+                let mut sum = Vec3::zero();
+                for (i, weight) in (-radius..=radius).zip(kernel.iter()){
+                    let (sample_x, sample_y) = (x + i * direction.0, y + i * direction.1);
+                    if sample_x < 0 || sample_y < 0 || sample_x >= width || sample_y >= height{
+                        continue;
+                    }
+                    let kept_weight = kept_weights[(sample_x * direction.0 + sample_y * direction.1) as usize];
+                    sum += image[(sample_x as u32, sample_y as u32)] * (*weight / kept_weight);
+                }
+                blurred[(x as u32, y as u32)] = sum;
+            }
+        }
+        blurred
+    };
+    blur_pass(&blur_pass(image, (1, 0)), (0, 1))
+}
+
+/// Smears every pixel into `streaks` evenly spaced rays of `length` pixels that fade out quadratically.
+fn star_glare(bright: &Image, streaks: u32, length: f32) -> Image{
+    let (width, height) = (bright.width(), bright.height());
+    let mut glare = Image::new(width, height);
+    let falloff: Vec<f32> = (1..=length.ceil() as u32).map(|i| (1.0 - i as f32 / (length + 1.0)).powi(2)).collect();
+    let total: f32 = falloff.iter().sum::<f32>() * streaks as f32;
+    if total <= 0.0{
+        return glare;
+    }
+    let directions: Vec<Vec2> = (0..streaks)
+        .map(|i| {
+            let angle = std::f32::consts::TAU * i as f32 / streaks as f32;
+            Vec2::new(angle.cos(), angle.sin())
+        })
+        .collect();
+    for y in 0..height{
+        for x in 0..width{
+            let color = bright[(x, y)];
+            if color == Vec3::zero(){
+                continue;
+            }
+            let center = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+            for direction in &directions{
+                for (i, weight) in falloff.iter().enumerate(){
+                    let position = center + *direction * (i + 1) as f32;
+                    if position.x < 0.0 || position.y < 0.0 || position.x >= width as f32 || position.y >= height as f32{
+                        break;
+                    }
+                    glare[(position.x as u32, position.y as u32)] += color * (*weight / total);
+                }
+            }
+        }
+    }
+    glare
+}
+
+/// Position relative to the image center, scaled so the corners are at a distance of 1.
+fn normalized_position(image: &Image, position: Vec2) -> Vec2{
+    let center = Vec2::new(image.width() as f32, image.height() as f32) / 2.0;
+    (position - center) / center.mag()
+}
+
+/// Radial lens distortion `r' = r (1 + k r^2)`, with a slightly different `k` per channel for chromatic aberration.
+fn distort(image: &Image, distortion: f32, chromatic_aberration: f32) -> Image{
+    let (width, height) = (image.width(), image.height());
+    let center = Vec2::new(width as f32, height as f32) / 2.0;
+    let scale = center.mag();
+    let mut distorted = Image::new(width, height);
+    for y in 0..height{
+        for x in 0..width{
+            let position = normalized_position(image, Vec2::new(x as f32 + 0.5, y as f32 + 0.5));
+            let r_squared = position.mag_sq();
+            let mut color = Vec3::zero();
+            for (channel, k) in [distortion + chromatic_aberration, distortion, distortion - chromatic_aberration].iter().enumerate(){
+                let source = center + position * (1.0 + k * r_squared) * scale;
+                color[channel] = sample_bilinear(image, source)[channel];
+            }
+            distorted[(x, y)] = color;
+        }
+    }
+    distorted
+}
+
+/// Bilinear interpolation at `position` in pixel coordinates, repeating the border pixels outside the image.
+fn sample_bilinear(image: &Image, position: Vec2) -> Vec3{
+    let max_x = image.width() as i64 - 1;
+    let max_y = image.height() as i64 - 1;
+    let position = position - Vec2::broadcast(0.5);
+    let (x0, y0) = (position.x.floor(), position.y.floor());
+    let (tx, ty) = (position.x - x0, position.y - y0);
+    let pixel = |x: i64, y: i64| image[(x.clamp(0, max_x) as u32, y.clamp(0, max_y) as u32)];
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let bottom = pixel(x0, y0) * (1.0 - tx) + pixel(x0 + 1, y0) * tx;
+    let top = pixel(x0, y0 + 1) * (1.0 - tx) + pixel(x0 + 1, y0 + 1) * tx;
+    bottom * (1.0 - ty) + top * ty
+}
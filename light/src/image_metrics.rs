@@ -0,0 +1,317 @@
+use ultraviolet::Vec3;
+
+use crate::{error::LightError, image::Image};
+
+/// Offset in the denominator of the relative MSE, so black reference pixels don't dominate it.
+const RELATIVE_MSE_EPSILON: f32 = 0.01;
+
+/// Assumed viewing conditions for FLIP: a 0.7 m wide 4K monitor seen from 0.7 m.
+const PIXELS_PER_DEGREE: f32 = 67.0;
+
+const RGB_TO_XYZ: [[f32; 3]; 3] = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.072175],
+    [0.0193339, 0.119192, 0.9503041],
+];
+const XYZ_TO_RGB: [[f32; 3]; 3] = [
+    [3.240454, -1.537139, -0.4985314],
+    [-0.969266, 1.876011, 0.041556],
+    [0.0556434, -0.2040259, 1.057225],
+];
+
+/// Error metrics between an image and a reference. All of them return a validation error if the images have different
+/// sizes and compare linear colours; PSNR, SSIM and FLIP treat 1 as white, so HDR images should be exposed accordingly.
+impl Image{
+    /// Mean squared error over all pixels and channels.
+    pub fn mse(&self, reference: &Image) -> Result<f32, LightError>{
+        self.mean_over_channels(reference, |value, reference| (value - reference).powi(2))
+    }
+
+    pub fn rmse(&self, reference: &Image) -> Result<f32, LightError>{
+        Ok(self.mse(reference)?.sqrt())
+    }
+
+    /// Squared error relative to the squared reference value, so errors in dark and bright regions count the same.
+    pub fn relative_mse(&self, reference: &Image) -> Result<f32, LightError>{
+        self.mean_over_channels(reference, |value, reference| (value - reference).powi(2) / (reference * reference + RELATIVE_MSE_EPSILON))
+    }
+
+    /// Peak signal to noise ratio in dB with a peak value of 1. Identical images give infinity.
+    pub fn psnr(&self, reference: &Image) -> Result<f32, LightError>{
+        Ok(-10.0 * self.mse(reference)?.log10())
+    }
+
+    /// Structural similarity (Wang et al. 2004) with an 11x11 Gaussian window, averaged over the channels. 1 means
+    /// identical images. Colours are clamped to [0, 1].
+    pub fn ssim(&self, reference: &Image) -> Result<f32, LightError>{
+        self.check_same_size(reference)?;
+        const C1: f32 = 0.01 * 0.01;
+        const C2: f32 = 0.03 * 0.03;
+        let (width, height) = (self.width(), self.height());
+        let window = gaussian_kernel(1.5, 5);
+        let mut sum = 0.0;
+        for channel in 0..3{
+            let x = self.channel(channel, |value| value.clamp(0.0, 1.0));
+            let y = reference.channel(channel, |value| value.clamp(0.0, 1.0));
+            let product = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| a * b).collect::<Vec<f32>>();
+            let blur = |values: &[f32]| convolve_separable(values, width, height, &window, &window);
+            let (mean_x, mean_y) = (blur(&x), blur(&y));
+            let (mean_xx, mean_yy, mean_xy) = (blur(&product(&x, &x)), blur(&product(&y, &y)), blur(&product(&x, &y)));
+            for i in 0..x.len(){
+                let variance_x = mean_xx[i] - mean_x[i] * mean_x[i];
+                let variance_y = mean_yy[i] - mean_y[i] * mean_y[i];
+                let covariance = mean_xy[i] - mean_x[i] * mean_y[i];
+                sum += (2.0 * mean_x[i] * mean_y[i] + C1) * (2.0 * covariance + C2)
+                    / ((mean_x[i] * mean_x[i] + mean_y[i] * mean_y[i] + C1) * (variance_x + variance_y + C2));
+            }
+        }
+        Ok(sum / (3 * width * height) as f32)
+    }
+
+    /// The mean of the LDR FLIP error map, see `flip_error_map`. 0 means identical images.
+    pub fn flip(&self, reference: &Image) -> Result<f32, LightError>{
+        let errors = self.flip_error_map(reference)?;
+        Ok(errors.iter().sum::<f32>() / errors.len() as f32)
+    }
+
+    /// Per pixel perceived difference in [0, 1] following LDR FLIP (Andersson et al. 2020): the colour difference of
+    /// both images filtered like the human eye sees them, amplified where edges and points differ. Colours are
+    /// clamped to [0, 1].
+    pub fn flip_error_map(&self, reference: &Image) -> Result<Vec<f32>, LightError>{
+        self.check_same_size(reference)?;
+        let (width, height) = (self.width(), self.height());
+        let test_ycxcz = to_ycxcz(self);
+        let reference_ycxcz = to_ycxcz(reference);
+
+        let test_lab = hunt_adjusted_lab(&spatially_filter(&test_ycxcz, width, height), width, height);
+        let reference_lab = hunt_adjusted_lab(&spatially_filter(&reference_ycxcz, width, height), width, height);
+
+        // the largest possible difference is the one between green and blue
+        let max_difference = hyab(hunt(linear_rgb_to_lab(Vec3::unit_y())), hunt(linear_rgb_to_lab(Vec3::unit_z()))).powf(0.7);
+        let (p_c, p_t) = (0.4, 0.95);
+
+        let test_features = detect_features(&test_ycxcz, width, height);
+        let reference_features = detect_features(&reference_ycxcz, width, height);
+
+        Ok((0..(width * height) as usize).map(|i| {
+            let difference = hyab(test_lab[i], reference_lab[i]).powf(0.7);
+            let color_error = if difference < p_c * max_difference{
+                p_t / (p_c * max_difference) * difference
+            }
+            else{
+                p_t + (difference - p_c * max_difference) / (max_difference - p_c * max_difference) * (1.0 - p_t)
+            };
+            let edge_difference = (test_features.edges[i] - reference_features.edges[i]).abs();
+            let point_difference = (test_features.points[i] - reference_features.points[i]).abs();
+            let feature_error = (edge_difference.max(point_difference) / 2.0f32.sqrt()).powf(0.5);
+            color_error.powf(1.0 - feature_error)
+        }).collect())
+    }
+
+    /// The FLIP error map as an image for viewing, using the magma colour map: black means no visible difference,
+    /// pale yellow the largest. The colours are display encoded, so they can be saved as PPM directly.
+    pub fn error_heatmap(&self, reference: &Image) -> Result<Image, LightError>{
+        let errors = self.flip_error_map(reference)?;
+        let mut heatmap = Image::new(self.width(), self.height());
+        for y in 0..self.height(){
+            for x in 0..self.width(){
+                heatmap[(x, y)] = magma(errors[(y * self.width() + x) as usize]);
+            }
+        }
+        Ok(heatmap)
+    }
+
+    fn check_same_size(&self, reference: &Image) -> Result<(), LightError>{
+        if self.width() != reference.width() || self.height() != reference.height(){
+            return Err(LightError::Validation(format!("Can't compare a {}x{} image to a {}x{} one.", self.width(), self.height(), reference.width(), reference.height())));
+        }
+        Ok(())
+    }
+
+    fn mean_over_channels<F>(&self, reference: &Image, error: F) -> Result<f32, LightError>
+        where F: Fn(f32, f32) -> f32{
+        self.check_same_size(reference)?;
+        let mut sum = 0.0;
+        for y in 0..self.height(){
+            for x in 0..self.width(){
+                for channel in 0..3{
+                    sum += error(self[(x, y)][channel], reference[(x, y)][channel]) as f64;
+                }
+            }
+        }
+        Ok((sum / (3 * self.width() * self.height()) as f64) as f32)
+    }
+
+    fn channel<F>(&self, channel: usize, f: F) -> Vec<f32>
+        where F: Fn(f32) -> f32{
+        let mut values = Vec::with_capacity((self.width() * self.height()) as usize);
+        for y in 0..self.height(){
+            for x in 0..self.width(){
+                values.push(f(self[(x, y)][channel]));
+            }
+        }
+        values
+    }
+}
+
+fn multiply(matrix: &[[f32; 3]; 3], color: Vec3) -> Vec3{
+    Vec3::new(
+        matrix[0][0] * color.x + matrix[0][1] * color.y + matrix[0][2] * color.z,
+        matrix[1][0] * color.x + matrix[1][1] * color.y + matrix[1][2] * color.z,
+        matrix[2][0] * color.x + matrix[2][1] * color.y + matrix[2][2] * color.z,
+    )
+}
+
+/// XYZ of the white point, so that white has a luminance of 1.
+fn white_xyz() -> Vec3{
+    multiply(&RGB_TO_XYZ, Vec3::one())
+}
+
+/// The opponent colour space YCxCz, a linear version of CIELAB.
+fn to_ycxcz(image: &Image) -> [Vec<f32>; 3]{
+    let white = white_xyz();
+    let mut channels = [Vec::new(), Vec::new(), Vec::new()];
+    for y in 0..image.height(){
+        for x in 0..image.width(){
+            let xyz = multiply(&RGB_TO_XYZ, image[(x, y)].clamped(Vec3::zero(), Vec3::one())) / white;
+            channels[0].push(116.0 * xyz.y - 16.0);
+            channels[1].push(500.0 * (xyz.x - xyz.y));
+            channels[2].push(200.0 * (xyz.y - xyz.z));
+        }
+    }
+    channels
+}
+
+fn ycxcz_to_linear_rgb(ycxcz: Vec3) -> Vec3{
+    let y = (ycxcz.x + 16.0) / 116.0;
+    let xyz = Vec3::new(ycxcz.y / 500.0 + y, y, y - ycxcz.z / 200.0) * white_xyz();
+    multiply(&XYZ_TO_RGB, xyz)
+}
+
+fn linear_rgb_to_lab(color: Vec3) -> Vec3{
+    let delta: f32 = 6.0 / 29.0;
+    let f = |t: f32| if t > delta.powi(3) {t.cbrt()} else {t / (3.0 * delta * delta) + 4.0 / 29.0};
+    let xyz = multiply(&RGB_TO_XYZ, color) / white_xyz();
+    Vec3::new(116.0 * f(xyz.y) - 16.0, 500.0 * (f(xyz.x) - f(xyz.y)), 200.0 * (f(xyz.y) - f(xyz.z)))
+}
+
+/// Hunt effect: colours look less saturated when they are darker.
+fn hunt(lab: Vec3) -> Vec3{
+    Vec3::new(lab.x, 0.01 * lab.x * lab.y, 0.01 * lab.x * lab.z)
+}
+
+fn hyab(a: Vec3, b: Vec3) -> f32{
+    (a.x - b.x).abs() + ((a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+fn hunt_adjusted_lab(ycxcz: &[Vec<f32>; 3], width: u32, height: u32) -> Vec<Vec3>{
+    (0..(width * height) as usize)
+        .map(|i| hunt(linear_rgb_to_lab(ycxcz_to_linear_rgb(Vec3::new(ycxcz[0][i], ycxcz[1][i], ycxcz[2][i])).clamped(Vec3::zero(), Vec3::one()))))
+        .collect()
+}
+
+/// Blurs each YCxCz channel with the contrast sensitivity of the eye for it, a sum of Gaussians per channel.
+fn spatially_filter(ycxcz: &[Vec<f32>; 3], width: u32, height: u32) -> [Vec<f32>; 3]{
+    // (a, b) pairs of the two Gaussians of the achromatic, red-green and blue-yellow channels
+    const PARAMETERS: [[(f32, f32); 2]; 3] = [
+        [(1.0, 0.0047), (0.0, 1e-5)],
+        [(1.0, 0.0053), (0.0, 1e-5)],
+        [(34.1, 0.04), (13.5, 0.025)],
+    ];
+    let pi = std::f32::consts::PI;
+    let radius = (3.0 * (0.04 / (2.0 * pi * pi)).sqrt() * PIXELS_PER_DEGREE).ceil() as i64;
+    let mut filtered = [Vec::new(), Vec::new(), Vec::new()];
+    for (channel, parameters) in PARAMETERS.iter().enumerate(){
+        // every Gaussian is separable; the sum of both is normalized as a whole
+        let kernels: Vec<(f32, Vec<f32>)> = parameters.iter()
+            .filter(|(a, _)| *a > 0.0)
+            .map(|(a, b)| {
+                let kernel = (-radius..=radius).map(|i| (-pi * pi * (i as f32 / PIXELS_PER_DEGREE).powi(2) / b).exp()).collect::<Vec<f32>>();
+                (a * (pi / b).sqrt(), kernel)
+            })
+            .collect();
+        let total: f32 = kernels.iter().map(|(scale, kernel)| scale * kernel.iter().sum::<f32>().powi(2)).sum();
+        let mut result = vec![0.0; (width * height) as usize];
+        for (scale, kernel) in &kernels{
+            let convolved = convolve_separable(&ycxcz[channel], width, height, kernel, kernel);
+            for (result, value) in result.iter_mut().zip(convolved){
+                *result += scale / total * value;
+            }
+        }
+        filtered[channel] = result;
+    }
+    filtered
+}
+
+struct Features{
+    edges: Vec<f32>,
+    points: Vec<f32>,
+}
+
+/// Strength of edges and points in the achromatic channel, using first and second derivatives of a Gaussian.
+fn detect_features(ycxcz: &[Vec<f32>; 3], width: u32, height: u32) -> Features{
+    let luminance: Vec<f32> = ycxcz[0].iter().map(|y| (y + 16.0) / 116.0).collect();
+    let sigma = 0.5 * 0.082 * PIXELS_PER_DEGREE;
+    let radius = (3.0 * sigma).ceil() as i64;
+    let gaussian = gaussian_kernel(sigma, radius);
+    // positive and negative weights each sum to 1
+    let normalize = |kernel: Vec<f32>| {
+        let positive: f32 = kernel.iter().filter(|x| **x > 0.0).sum();
+        let negative: f32 = -kernel.iter().filter(|x| **x < 0.0).sum::<f32>();
+        kernel.into_iter().map(|x| if x > 0.0 {x / positive} else {x / negative}).collect::<Vec<f32>>()
+    };
+    let edge = normalize((-radius..=radius).zip(&gaussian).map(|(i, g)| -(i as f32) * g).collect());
+    let point = normalize((-radius..=radius).zip(&gaussian).map(|(i, g)| ((i * i) as f32 / (sigma * sigma) - 1.0) * g).collect());
+
+    let magnitude = |kernel: &[f32]| {
+        let along_x = convolve_separable(&luminance, width, height, kernel, &gaussian);
+        let along_y = convolve_separable(&luminance, width, height, &gaussian, kernel);
+        along_x.iter().zip(along_y).map(|(x, y)| (x * x + y * y).sqrt()).collect::<Vec<f32>>()
+    };
+    Features { edges: magnitude(&edge), points: magnitude(&point) }
+}
+
+/// A normalized Gaussian with the given standard deviation, `2 * radius + 1` taps wide.
+fn gaussian_kernel(sigma: f32, radius: i64) -> Vec<f32>{
+    let kernel: Vec<f32> = (-radius..=radius).map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp()).collect();
+    let sum: f32 = kernel.iter().sum();
+    kernel.into_iter().map(|x| x / sum).collect()
+}
+
+/// Convolves a single channel image with `kernel_x` horizontally and `kernel_y` vertically, repeating the border
+/// pixels outside the image.
+fn convolve_separable(values: &[f32], width: u32, height: u32, kernel_x: &[f32], kernel_y: &[f32]) -> Vec<f32>{
+    let (width, height) = (width as i64, height as i64);
+    let pass = |values: &[f32], kernel: &[f32], horizontal: bool| {
+        let radius = (kernel.len() / 2) as i64;
+        let mut result = vec![0.0; values.len()];
+        for y in 0..height{
+            for x in 0..width{
+                let mut sum = 0.0;
+                for (i, weight) in (-radius..=radius).zip(kernel){
+                    let (sample_x, sample_y) = if horizontal {((x + i).clamp(0, width - 1), y)} else {(x, (y + i).clamp(0, height - 1))};
+                    sum += values[(sample_y * width + sample_x) as usize] * weight;
+                }
+                result[(y * width + x) as usize] = sum;
+            }
+        }
+        result
+    };
+    pass(&pass(values, kernel_x, true), kernel_y, false)
+}
+
+/// Approximation of the magma colour map, for values in [0, 1].
+fn magma(value: f32) -> Vec3{
+    const STOPS: [Vec3; 6] = [
+        Vec3::new(0.001462, 0.000466, 0.013866),
+        Vec3::new(0.232077, 0.059889, 0.437695),
+        Vec3::new(0.550287, 0.161158, 0.505719),
+        Vec3::new(0.868793, 0.287728, 0.409303),
+        Vec3::new(0.994738, 0.62435, 0.427397),
+        Vec3::new(0.987053, 0.991438, 0.749504),
+    ];
+    let position = value.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let index = (position as usize).min(STOPS.len() - 2);
+    let t = position - index as f32;
+    STOPS[index] * (1.0 - t) + STOPS[index + 1] * t
+}
@@ -1,30 +1,49 @@
-use std::{fs::read_to_string, error::Error, iter::Peekable};
+use std::{fs::read_to_string, path::{Path, PathBuf}, iter::Peekable, collections::HashMap, str::FromStr, fmt::Display, time::Instant};
 
-use ultraviolet::Vec3;
+use ultraviolet::{Vec3, Vec4, Mat4};
 
-use crate::{parsing_error::ParsingError, mesh::Mesh, scene::Scene, camera::Camera, material::Material, bounding_box::BVH, hittable::Hittable, sphere::Sphere};
+use crate::{error::{LightError, column_of}, mesh::Mesh, scene::Scene, camera::{Camera, CameraParameters, Projection}, material::{Material, SurfaceDetail, Dispersion}, bounding_box::BVH, hittable::Hittable, sphere::Sphere, texture::{Texture, WrapMode}, medium::Medium, render_settings::RenderSettings, sampler::SamplerType, aov::Aov, image_filters::ToneMapper, color_space::{ColorSpace, convert}, film::{Filter, FilterType}, render_statistics::{self, Phase}, validation::SourceLocation};
 
 enum ObjectHeader{
     Mesh,
     Sphere,
     Camera,
+    Texture,
+    Fog,
+    Render,
 }
 
-
-#[derive(Default)]
-struct CameraParameters{
-    pub pos: Vec3,
-    pub target: Vec3,
-    pub fov: f32,
-    pub aperture_size: f32,
-    pub depth_of_field: f32,
+pub fn load_from_blender(filename: &str) -> Result<Scene, LightError>{
+    let start = Instant::now();
+    let content = read_to_string(filename).map_err(|error| LightError::io(filename, error))?;
+    let scene = parse_scene(&content, filename);
+    render_statistics::add_time(Phase::Load, start.elapsed());
+    scene
 }
 
-pub fn load_from_blender(filename: &str) -> Result<Scene, Box<dyn Error>>{
-    let mut scene: Scene = Scene::default();
-    let mut objects: Vec<Box<dyn Hittable>> =Vec::new();
-
-    let file_content = read_to_string(filename)?;
+/// Parses the content of a scene file. `filename` is used for error messages, and relative mesh and texture files
+/// start at its directory.
+pub fn parse_scene(file_content: &str, filename: &str) -> Result<Scene, LightError>{
+    let mut scene: Scene = Scene { location: Some(SourceLocation::new(filename, 1)), ..Scene::default() };
+    // colours are converted to the working space while they are parsed
+    let working_space = find_working_space(file_content, filename)?;
+    let mut meshes: Vec<Mesh> = Vec::new();
+    let mut spheres: Vec<Sphere> = Vec::new();
+    let mut textures: HashMap<String, Texture> = HashMap::new();
+    // objects sharing a material name share the material id, unnamed materials get their own
+    let mut material_ids: HashMap<String, u32> = HashMap::new();
+    let mut next_material_id: u32 = 1;
+    let mut assign_material_id = |name: Option<String>| {
+        let new_id = next_material_id;
+        let id = match name{
+            Some(name) => *material_ids.entry(name).or_insert(new_id),
+            None => new_id,
+        };
+        if id == new_id{
+            next_material_id += 1;
+        }
+        id
+    };
 
     let mut line_number: usize = 0;
     let mut lines = file_content.lines().peekable();
@@ -39,164 +58,360 @@ pub fn load_from_blender(filename: &str) -> Result<Scene, Box<dyn Error>>{
                 match parse_object_header(iter, filename, line_number)?{ 
                     ObjectHeader::Mesh => {
                         let mut obj = parse_mesh_object(&mut lines, filename, &mut line_number)?;
-                        obj.material = parse_material(&mut lines, filename, &mut line_number)?;
-                        objects.push(Box::new(obj));
+                        let material_name;
+                        (obj.material, material_name) = parse_material(&mut lines, filename, &mut line_number, &textures, working_space)?;
+                        obj.object_id = (meshes.len() + spheres.len()) as u32 + 1;
+                        obj.material_id = assign_material_id(material_name);
+                        meshes.push(obj);
                     },
                     ObjectHeader::Sphere =>  {
+                        let location = SourceLocation::new(filename, line_number);
                         let mut obj = parse_sphere_object(&mut lines, filename, &mut line_number)?;
-                        obj.material = parse_material(&mut lines, filename, &mut line_number)?;
-                        objects.push(Box::new(obj));
+                        obj.location = Some(location);
+                        let material_name;
+                        (obj.material, material_name) = parse_material(&mut lines, filename, &mut line_number, &textures, working_space)?;
+                        obj.object_id = (meshes.len() + spheres.len()) as u32 + 1;
+                        obj.material_id = assign_material_id(material_name);
+                        spheres.push(obj);
                     },
                     ObjectHeader::Camera => {
+                       scene.camera_location = Some(SourceLocation::new(filename, line_number));
                        (scene.camera, scene.width, scene.height) = parse_camera(&mut lines, filename, &mut line_number)?;
                     }
+                    ObjectHeader::Render => {
+                        scene.settings = parse_render_settings(&mut lines, filename, &mut line_number)?;
+                    }
+                    ObjectHeader::Fog => {
+                        scene.fog = Some(parse_fog(&mut lines, filename, &mut line_number)?);
+                    }
+                    ObjectHeader::Texture => {
+                        let (name, texture) = parse_texture_object(&mut lines, filename, &mut line_number, working_space)?;
+                        textures.insert(name, texture);
+                    }
                 }
 
             }
             None => {},
-            Some(x) => { return Err(Box::new(ParsingError{filename: filename.to_owned(), line: line_number, message: format!("Unknown line beginning with '{}'", x)})); },
+            Some(x) => { return Err(LightError::parse(filename, line_number, column_of(raw_line, line), format!("Unknown line beginning with '{}'", x))); },
         }
     }
 
-    scene.bvh = Some(BVH::<dyn Hittable>::build_recursive(objects));
-    // scene.bvh = Some(Box::new(objects));
+    // an empty scene keeps no BVH, `Scene::validate` reports it
+    if !meshes.is_empty() || !spheres.is_empty(){
+        let mut objects: Vec<Box<dyn Hittable>> = Vec::new();
+        for mesh in meshes{
+            objects.push(Box::new(mesh));
+        }
+        for sphere in spheres{
+            objects.push(Box::new(sphere));
+        }
+        scene.bvh = Some(BVH::<dyn Hittable>::build_recursive(objects));
+        // scene.bvh = Some(Box::new(objects));
+    }
+    render_statistics::set_geometry_memory(scene.memory_usage());
 
     return Ok(scene);
 }
 
-fn parse_vec3(value: &str) -> Result<Vec3, Box<dyn Error>> {
-    let mut parts = value.split(";").map(|x| x.trim()).map(|x| x.parse::<f32>());
-    Ok(Vec3{
-        x: parts.next().unwrap()?,
-        y: parts.next().unwrap()?,
-        z: parts.next().unwrap()?,
-    })
-} 
+/// The colour space colours are rendered in. It has to be known before the first colour is parsed, but the render
+/// settings can come anywhere in the file.
+fn find_working_space(file_content: &str, filename: &str) -> Result<ColorSpace, LightError>{
+    let mut settings = RenderSettings::default();
+    let mut lines = file_content.lines();
+    let mut line_number: usize = 0;
+    while let Some(line) = lines.next(){
+        line_number += 1;
+        if line.trim() == "[render]"{
+            settings = parse_render_settings(&mut lines.clone().peekable(), filename, &mut line_number.clone())?;
+        }
+    }
+    // scene files use linear Rec.709 like Blender; spectral rendering upsamples from Rec.709 as well
+    Ok(if settings.spectral {ColorSpace::LinearRec709} else {settings.working_space})
+}
+
+/// A `key = value` line of a scene file.
+struct Entry<'a>{
+    key: &'a str,
+    value: &'a str,
+    line: &'a str,
+    filename: &'a str,
+    line_number: usize,
+}
+
+impl<'a> Entry<'a>{
+    /// Splits `line` at the first '='. Anything after another '=' is ignored.
+    fn new(line: &'a str, filename: &'a str, line_number: usize) -> Option<Entry<'a>>{
+        let mut parts = line.split('=').map(|x| x.trim());
+        Some(Entry { key: parts.next()?, value: parts.next()?, line, filename, line_number })
+    }
+
+    /// An error pointing at the key, e.g. for unknown keys.
+    fn key_error(&self, message: String) -> LightError{
+        LightError::parse(self.filename, self.line_number, column_of(self.line, self.key), message)
+    }
+
+    /// An error pointing at `part` of the value.
+    fn error_at(&self, part: &str, message: String) -> LightError{
+        LightError::parse(self.filename, self.line_number, column_of(self.line, part), message)
+    }
+
+    fn error(&self, message: String) -> LightError{
+        self.error_at(self.value, message)
+    }
+
+    fn parse<T: FromStr>(&self) -> Result<T, LightError>
+    where T::Err: Display{
+        self.value.parse().map_err(|error| self.error(format!("Invalid value '{}' for '{}': {}.", self.value, self.key, error)))
+    }
+
+    /// A vector written as `x;y;z`.
+    fn vec3(&self) -> Result<Vec3, LightError>{
+        let mut parts = self.value.split(';').map(|x| x.trim());
+        let mut next = || {
+            let part = parts.next().ok_or_else(|| self.error(format!("Expected three values separated by ';' instead of '{}'.", self.value)))?;
+            part.parse::<f32>().map_err(|_| self.error_at(part, format!("'{}' isn't a number.", part)))
+        };
+        Ok(Vec3{
+            x: next()?,
+            y: next()?,
+            z: next()?,
+        })
+    }
+
+    /// A 4x4 matrix written as 16 values separated by ';', row by row.
+    fn mat4(&self) -> Result<Mat4, LightError>{
+        let parts: Vec<&str> = self.value.split(';').map(|x| x.trim()).collect();
+        if parts.len() != 16{
+            return Err(self.error(format!("Expected 16 values separated by ';', got {}.", parts.len())));
+        }
+        let mut values = [0.0; 16];
+        for (value, part) in values.iter_mut().zip(parts){
+            *value = part.parse::<f32>().map_err(|_| self.error_at(part, format!("'{}' isn't a number.", part)))?;
+        }
+        let row = |i: usize| Vec4::new(values[i*4], values[i*4 + 1], values[i*4 + 2], values[i*4 + 3]);
+        Ok(Mat4::new(row(0), row(1), row(2), row(3)).transposed())
+    }
+}
+
+/// Parses a colour texture value: either a constant `r;g;b` or a reference `@name` to a previously declared texture.
+fn parse_texture(entry: &Entry, textures: &HashMap<String, Texture>) -> Result<Texture, LightError> {
+    match entry.value.strip_prefix('@'){
+        Some(name) => {
+            match textures.get(name.trim()){
+                Some(texture) => Ok(texture.clone()),
+                None => Err(entry.error(format!("Unknown texture '{}'.", name))),
+            }
+        }
+        None => Ok(Texture::constant(entry.vec3()?)),
+    }
+}
 
-fn parse_material<'a, I>(lines: &mut Peekable<I>, filename: &str, line_number: &mut usize) -> Result<Material, Box<dyn Error>>
+/// Like `parse_texture`, but constant colours are converted from linear Rec.709 to `working_space`. Textures are
+/// converted when they are declared.
+fn parse_color_texture(entry: &Entry, textures: &HashMap<String, Texture>, working_space: ColorSpace) -> Result<Texture, LightError> {
+    if entry.value.starts_with('@'){
+        return parse_texture(entry, textures);
+    }
+    Ok(Texture::constant(convert(entry.vec3()?, ColorSpace::LinearRec709, working_space)))
+}
+
+/// Like `parse_texture`, but constants are a single number that is used for all channels.
+fn parse_scalar_texture(entry: &Entry, textures: &HashMap<String, Texture>) -> Result<Texture, LightError> {
+    if entry.value.starts_with('@'){
+        return parse_texture(entry, textures);
+    }
+    Ok(Texture::constant(Vec3::one() * entry.parse::<f32>()?))
+}
+
+fn parse_material<'a, I>(lines: &mut Peekable<I>, filename: &str, line_number: &mut usize, textures: &HashMap<String, Texture>, working_space: ColorSpace) -> Result<(Material, Option<String>), LightError>
 where
 I: DoubleEndedIterator<Item = &'a str> + Clone{
     let mut mat = Material::NormalMaterial();
-    while (*lines.peek().or(Some(&"[]")).unwrap()).chars().nth(0) != Some('['){
+    let mut name: Option<String> = None;
+    while lines.peek().is_some_and(|line| !line.starts_with('[')){
         match lines.next(){
             Some(line) => {
-                if let [key, value] = &line.split("=").map(|x| x.trim()).take(2).collect::<Vec<_>>()[..]{
-                    match *key{
+                *line_number += 1;
+                if let Some(entry) = Entry::new(line, filename, *line_number){
+                    let (key, value) = (entry.key, entry.value);
+                    match key{
+                        "material_name" => {
+                            name = Some(value.to_string());
+                        },
                         "material_type" => {
-                            mat = match *value{
-                                "diffuse_material" => Material::DiffuseMaterial{albedo: Vec3::one()},
-                                "metallic_material" => Material::MetallicMaterial{albedo: Vec3::one(), roughness: 0.0},
-                                "emissive_material" => Material::EmissiveMaterial{emission_color: Vec3::one(), strength: 0.5},
-                                "dielectric_material" => Material::DielectricMaterial{albedo: Vec3::one(), ior: 1.0},
+                            mat = match value{
+                                "diffuse_material" => Material::DiffuseMaterial{albedo: Texture::constant(Vec3::one()), detail: SurfaceDetail::default()},
+                                "metallic_material" => Material::MetallicMaterial{albedo: Texture::constant(Vec3::one()), roughness: Texture::constant(Vec3::zero()), detail: SurfaceDetail::default()},
+                                "emissive_material" => Material::EmissiveMaterial{emission_color: Texture::constant(Vec3::one()), strength: 0.5},
+                                "dielectric_material" => Material::DielectricMaterial{albedo: Texture::constant(Vec3::one()), ior: 1.0, detail: SurfaceDetail::default(), medium: None, priority: 0, dispersion: None},
+                                "volume_material" => Material::VolumeMaterial{medium: Medium::default(), priority: 0},
                                 _ => {
-                                    return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: format!("Unimplemented material type while parsing material '{}'.", key)}));
+                                    return Err(entry.error(format!("Unimplemented material type '{}'.", value)));
                                 }
                             };
                         },
                         "albedo" => {
                             match &mut mat{
-                                Material::MetallicMaterial { albedo, roughness: _} => {
-                                    *albedo = parse_vec3(value)?; 
+                                Material::MetallicMaterial { albedo, .. } => {
+                                    *albedo = parse_color_texture(&entry, textures, working_space)?; 
                                 }
-                                Material::DiffuseMaterial { albedo } => {
-                                    *albedo = parse_vec3(value)?; 
+                                Material::DiffuseMaterial { albedo, .. } => {
+                                    *albedo = parse_color_texture(&entry, textures, working_space)?; 
                                 }
-                                Material::DielectricMaterial { albedo, ior: _ } => {
-                                    *albedo = parse_vec3(value)?;
+                                Material::DielectricMaterial { albedo, .. } => {
+                                    *albedo = parse_color_texture(&entry, textures, working_space)?;
                                 }
                                 _ => {
-                                    return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: format!("Emissive material has no property '{}'.", key)}));
+                                    return Err(entry.key_error(format!("Emissive material has no property '{}'.", key)));
                                 }
                             }
                         }
                          "ior" => {
                             match &mut mat{
-                                Material::DielectricMaterial{ albedo: _, ior } => {
-                                    *ior = value.parse::<f32>()?; 
+                                Material::DielectricMaterial{ ior, .. } => {
+                                    *ior = entry.parse::<f32>()?; 
                                 }
                                 _ => {
-                                    return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: format!("Emissive material has no property '{}'.", key)}));
+                                    return Err(entry.key_error(format!("Emissive material has no property '{}'.", key)));
                                 }
                             }
                         }
                         "emission_color" => {
                             match &mut mat{
                                 Material::EmissiveMaterial { emission_color, strength: _ } => {
-                                    *emission_color = parse_vec3(value)?; 
+                                    *emission_color = parse_color_texture(&entry, textures, working_space)?; 
                                 }
                                 _ => {
-                                    return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: format!("Emissive material has no property '{}'.", key)}));
+                                    return Err(entry.key_error(format!("Emissive material has no property '{}'.", key)));
                                 }
                             }
                         }
                         "strength" => {
                             match &mut mat{
                                 Material::EmissiveMaterial { emission_color: _, strength } => {
-                                    *strength = value.parse::<f32>()?; 
+                                    *strength = entry.parse::<f32>()?; 
                                 }
                                 _ => {
-                                    return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: format!("Emissive material has no property '{}'.", key)}));
+                                    return Err(entry.key_error(format!("Emissive material has no property '{}'.", key)));
                                 }
                             }
                         }
                         "roughness" => {
                             match &mut mat{
-                                Material::MetallicMaterial { albedo: _, roughness} => {
-                                    *roughness = value.parse::<f32>()?; 
+                                Material::MetallicMaterial { roughness, .. } => {
+                                    *roughness = parse_scalar_texture(&entry, textures)?; 
                                 }
                                 _ => {
-                                    return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: format!("Emissive material has no property '{}'.", key)}));
+                                    return Err(entry.key_error(format!("Emissive material has no property '{}'.", key)));
+                                }
+                            }
+                        }
+                        "absorption" | "scattering" | "anisotropy" => {
+                            let medium = match &mut mat{
+                                Material::DielectricMaterial { medium, .. } => medium.get_or_insert(Medium::default()),
+                                Material::VolumeMaterial { medium, .. } => medium,
+                                _ => {
+                                    return Err(entry.key_error(format!("Material has no property '{}'.", key)));
+                                }
+                            };
+                            parse_medium_property(medium, &entry)?;
+                        }
+                        "cauchy_b" | "sellmeier_b" | "sellmeier_c" => {
+                            match &mut mat{
+                                Material::DielectricMaterial { dispersion, .. } => {
+                                    match (key, dispersion){
+                                        ("cauchy_b", dispersion) => *dispersion = Some(Dispersion::Cauchy { b: entry.parse::<f32>()? }),
+                                        ("sellmeier_b", Some(Dispersion::Sellmeier { b, .. })) => *b = entry.vec3()?,
+                                        ("sellmeier_c", Some(Dispersion::Sellmeier { c, .. })) => *c = entry.vec3()?,
+                                        ("sellmeier_b", dispersion) => *dispersion = Some(Dispersion::Sellmeier { b: entry.vec3()?, c: Vec3::zero() }),
+                                        (_, dispersion) => *dispersion = Some(Dispersion::Sellmeier { b: Vec3::zero(), c: entry.vec3()? }),
+                                    }
+                                }
+                                _ => {
+                                    return Err(entry.key_error(format!("Material has no property '{}'.", key)));
+                                }
+                            }
+                        }
+                        "priority" => {
+                            match &mut mat{
+                                Material::DielectricMaterial { priority, .. } | Material::VolumeMaterial { priority, .. } => {
+                                    *priority = entry.parse::<i32>()?;
+                                }
+                                _ => {
+                                    return Err(entry.key_error(format!("Material has no property '{}'.", key)));
+                                }
+                            }
+                        }
+                        "normal_map" | "bump_map" | "bump_strength" => {
+                            match mat.surface_detail_mut(){
+                                Some(detail) => {
+                                    match key{
+                                        "normal_map" => detail.normal_map = Some(parse_texture(&entry, textures)?),
+                                        "bump_map" => detail.bump_map = Some(parse_scalar_texture(&entry, textures)?),
+                                        _ => detail.bump_strength = entry.parse::<f32>()?,
+                                    }
+                                }
+                                None => {
+                                    return Err(entry.key_error(format!("Material has no property '{}'.", key)));
                                 }
                             }
                         }
                         _ => {
-                            return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: format!("Unimplemented key while parsing material '{}'.", key)}));
+                            return Err(entry.key_error(format!("Unimplemented key while parsing material '{}'.", key)));
                         }
                     }
                 }
                 else{
-                    return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: "Hit end of line while parsing material.".to_owned()}));
+                    return Err(LightError::parse(filename, *line_number, 1, "Hit end of line while parsing material."));
                 }
             },
-            None => { return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: "Hit end of file while parsing material.".to_string()})); },
+            None => { return Err(LightError::parse(filename, *line_number, 1, "Hit end of file while parsing material.")); },
         };
     }
-    return Ok(mat);
+    return Ok((mat, name));
 } 
 
-fn parse_mesh_object<'a, I>(lines: &mut Peekable<I>, filename: &str, line_number: &mut usize) -> Result<Mesh, Box<dyn Error>>
+/// The path of `file`, referenced by the scene file `filename`. Relative paths start at the directory of the scene
+/// file, not at the working directory.
+fn resolve_path(filename: &str, file: &str) -> String{
+    Path::new(filename).parent().map_or_else(|| PathBuf::from(file), |directory| directory.join(file)).to_string_lossy().into_owned()
+}
+
+fn parse_mesh_object<'a, I>(lines: &mut Peekable<I>, filename: &str, line_number: &mut usize) -> Result<Mesh, LightError>
 where
 I: DoubleEndedIterator<Item = &'a str> + Clone{
     let mut object: Option<Mesh> = None;
     match lines.next(){
         Some(line) => {
-            if let [key, value] = &line.split("=").map(|x| x.trim()).take(2).collect::<Vec<_>>()[..]{
-                match *key{
+            *line_number += 1;
+            if let Some(entry) = Entry::new(line, filename, *line_number){
+                let (key, value) = (entry.key, entry.value);
+                match key{
                     "mesh_file" => {
-                        object = Some(Mesh::from_obj(value)?);
+                        object = Some(Mesh::from_obj(&resolve_path(filename, value))?);
                     },
                     _ => {
-                        return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: format!("Unimplemented key while parsing mesh object '{}'.", key)}));
+                        return Err(entry.key_error(format!("Unimplemented key while parsing mesh object '{}'.", key)));
                     }
                 }
             }
             else{
-                return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: "Hit end of line while parsing key.".to_owned()}));
+                return Err(LightError::parse(filename, *line_number, 1, "Hit end of line while parsing key."));
             }
         },
-        None => { return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: "Hit end of file while parsing mesh object.".to_string()})); },
+        None => { return Err(LightError::parse(filename, *line_number, 1, "Hit end of file while parsing mesh object.")); },
     };
     if let Some(mut obj) = object{
         obj.material = Material::NormalMaterial();
         return Ok(obj);
     }   
     else{
-        return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: "No mesh file proveded for mesh file object".to_owned()}));
+        return Err(LightError::parse(filename, *line_number, 1, "No mesh file proveded for mesh file object"));
     }
 
 }
 
-fn parse_sphere_object<'a, I>(lines: &mut Peekable<I>, filename: &str, line_number: &mut usize) -> Result<Sphere, Box<dyn Error>>
+fn parse_sphere_object<'a, I>(lines: &mut Peekable<I>, filename: &str, line_number: &mut usize) -> Result<Sphere, LightError>
 where
 I: DoubleEndedIterator<Item = &'a str> + Clone{
     let mut pos = Vec3::zero();
@@ -204,90 +419,452 @@ I: DoubleEndedIterator<Item = &'a str> + Clone{
     for _ in 0..2{
         match lines.next(){
             Some(line) => {
-                if let [key, value] = &line.split("=").map(|x| x.trim()).take(2).collect::<Vec<_>>()[..]{
-                    match *key{
+                *line_number += 1;
+                if let Some(entry) = Entry::new(line, filename, *line_number){
+                    let key = entry.key;
+                    match key{
                         "radius" => {
-                            radius = value.parse()?;
+                            radius = entry.parse()?;
                         },
                         "pos" => {
-                            pos = parse_vec3(value)?;
+                            pos = entry.vec3()?;
                         }
                         _ => {
-                            return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: format!("Unimplemented key while parsing sphere object '{}'.", key)}));
+                            return Err(entry.key_error(format!("Unimplemented key while parsing sphere object '{}'.", key)));
                         }
                     }
                 }
                 else{
-                    return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: "Hit end of line while parsing key.".to_owned()}));
+                    return Err(LightError::parse(filename, *line_number, 1, "Hit end of line while parsing key."));
                 }
             },
-            None => { return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: "Hit end of file while parsing sphere object.".to_string()})); },
+            None => { return Err(LightError::parse(filename, *line_number, 1, "Hit end of file while parsing sphere object.")); },
         };
     }
 
-    return Ok(Sphere { center: pos, radius, material: Material::NormalMaterial() });
+    return Ok(Sphere { center: pos, radius, material: Material::NormalMaterial(), object_id: 0, material_id: 0, location: None });
 
 }
 
-fn parse_camera<'a, I>(lines: &mut Peekable<I>, filename: &str, line_number: &mut usize) -> Result<(Camera, u32, u32), Box<dyn Error>>
+fn parse_camera<'a, I>(lines: &mut Peekable<I>, filename: &str, line_number: &mut usize) -> Result<(Camera, u32, u32), LightError>
 where
 I: DoubleEndedIterator<Item = &'a str> + Clone{
     let mut cam_params: CameraParameters = CameraParameters::default();
-    cam_params.depth_of_field = 1.0;
 
     let mut width: u32 = 400;
     let mut height: u32 = 225;
     // check if the first char on the next line == '['
-    while (*lines.peek().or(Some(&"[]")).unwrap()).chars().nth(0) != Some('['){
+    while lines.peek().is_some_and(|line| !line.starts_with('[')){
         match lines.next(){
             Some(line) => {
-                if let [key, value] = &line.split("=").map(|x| x.trim()).take(2).collect::<Vec<_>>()[..]{
-                    match *key{
+                *line_number += 1;
+                if let Some(entry) = Entry::new(line, filename, *line_number){
+                    let key = entry.key;
+                    match key{
                         "height" => {
-                            height = value.trim().parse()?;
+                            height = entry.parse()?;
                         },
                         "width" => {
-                            width = value.trim().parse()?;
+                            width = entry.parse()?;
                         },
                         "position" => {
-                            cam_params.pos = parse_vec3(value)?;
+                            cam_params.position = entry.vec3()?;
                         },
                         "target" => {
-                            cam_params.target = parse_vec3(value)?;
+                            cam_params.target = entry.vec3()?;
                        },
                         "fov" => {
-                            cam_params.fov = value.parse::<f32>()?
+                            cam_params.fov = entry.parse::<f32>()?
+                        },
+                        "up" => {
+                            cam_params.up = entry.vec3()?;
+                        },
+                        "roll" => {
+                            cam_params.roll = entry.parse()?;
+                        },
+                        "matrix" => {
+                            cam_params.matrix = Some(entry.mat4()?);
+                        },
+                        "projection" => {
+                            cam_params.projection = match entry.value{
+                                "perspective" => Projection::Perspective,
+                                "orthographic" => Projection::Orthographic,
+                                "equirectangular" => Projection::Equirectangular,
+                                "fisheye_equidistant" => Projection::FisheyeEquidistant,
+                                "fisheye_equisolid" => Projection::FisheyeEquisolid,
+                                "cube_map" => Projection::CubeMap,
+                                _ => return Err(entry.error(format!("Unknown projection '{}'.", entry.value))),
+                            };
+                        },
+                        "fisheye_fov" => {
+                            cam_params.fisheye_fov = entry.parse()?;
+                        },
+                        "fisheye_lens" => {
+                            cam_params.fisheye_lens = entry.parse()?;
+                        },
+                        "ortho_scale" => {
+                            cam_params.ortho_scale = entry.parse()?;
+                        },
+                        "shift_x" => {
+                            cam_params.shift.x = entry.parse()?;
+                        },
+                        "shift_y" => {
+                            cam_params.shift.y = entry.parse()?;
+                        },
+                        "focal_length" => {
+                            cam_params.focal_length = Some(entry.parse()?);
+                        },
+                        "sensor_width" => {
+                            cam_params.sensor_width = entry.parse()?;
+                        },
+                        "focus_distance" => {
+                            cam_params.focus_distance = entry.parse()?;
+                        },
+                        "aperture_size" => {
+                            cam_params.aperture_size = entry.parse()?;
+                        },
+                        "f_stop" => {
+                            cam_params.f_stop = Some(entry.parse()?);
+                        },
+                        "aperture_blades" => {
+                            cam_params.aperture_blades = entry.parse()?;
+                        },
+                        "aperture_rotation" => {
+                            cam_params.aperture_rotation = entry.parse()?;
+                        },
+                        "aperture_ratio" => {
+                            cam_params.aperture_ratio = entry.parse()?;
                         },
                         _ => {
-                            return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: format!("Unimplemented key while parsing camera {}'.", key)}));
+                            return Err(entry.key_error(format!("Unimplemented key while parsing camera {}'.", key)));
                         }
                     }
                 }
                 else{
-                    return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: "Hit end of line while parsing key.".to_owned()}));
+                    return Err(LightError::parse(filename, *line_number, 1, "Hit end of line while parsing key."));
                 }
             },
-            None => { return Err(Box::new(ParsingError{filename: filename.to_owned(), line: *line_number, message: "Hit end of file while parsing mesh object.".to_string()})); },
+            None => { return Err(LightError::parse(filename, *line_number, 1, "Hit end of file while parsing mesh object.")); },
         };
     }
-    let camera = Camera::new(cam_params.pos, cam_params.target, cam_params.fov, width as f32 / height as f32, cam_params.aperture_size, cam_params.depth_of_field);
+    let camera = Camera::new(&cam_params, width as f32 / height as f32);
     return Ok((camera, width, height));
 
 }
 
-fn parse_object_header<'a, I>(mut line: I, filename: &str, line_number: usize) -> Result<ObjectHeader, Box<dyn Error>>
+/// Parses one of the keys `absorption`, `scattering` or `anisotropy` into `medium`.
+fn parse_medium_property(medium: &mut Medium, entry: &Entry) -> Result<(), LightError>{
+    match entry.key{
+        "absorption" => medium.absorption = entry.vec3()?,
+        "scattering" => medium.scattering = entry.vec3()?,
+        "anisotropy" => medium.anisotropy = entry.parse::<f32>()?.clamp(-0.99, 0.99),
+        _ => return Err(entry.key_error(format!("Media have no property '{}'.", entry.key))),
+    }
+    Ok(())
+}
+
+fn parse_render_settings<'a, I>(lines: &mut Peekable<I>, filename: &str, line_number: &mut usize) -> Result<RenderSettings, LightError>
+where
+I: DoubleEndedIterator<Item = &'a str> + Clone{
+    let mut settings = RenderSettings::default();
+    // the radius defaults to the one of the chosen filter, no matter in which order they are given
+    let mut filter_type = settings.filter.filter_type;
+    let mut filter_radius: Option<f32> = None;
+    while lines.peek().is_some_and(|line| !line.starts_with('[')){
+        match lines.next(){
+            Some(line) => {
+                *line_number += 1;
+                if let Some(entry) = Entry::new(line, filename, *line_number){
+                    let (key, value) = (entry.key, entry.value);
+                    match key{
+                        "samples_per_pixel" => {
+                            settings.samples_per_pixel = entry.parse()?;
+                        }
+                        "max_depth" => {
+                            settings.max_depth = entry.parse()?;
+                        }
+                        "spectral" => {
+                            settings.spectral = entry.parse()?;
+                        }
+                        "seed" => {
+                            settings.seed = entry.parse()?;
+                        }
+                        "statistics" => {
+                            settings.statistics = entry.parse()?;
+                        }
+                        "sampler" => {
+                            settings.sampler = match value{
+                                "independent" => SamplerType::Independent,
+                                "stratified" => SamplerType::Stratified,
+                                "halton" => SamplerType::Halton,
+                                "sobol" => SamplerType::Sobol,
+                                _ => {
+                                    return Err(entry.error(format!("Unknown sampler '{}'.", value)));
+                                }
+                            };
+                        }
+                        "noise_threshold" => {
+                            settings.noise_threshold = Some(entry.parse()?);
+                        }
+                        "min_samples" => {
+                            settings.min_samples = entry.parse()?;
+                        }
+                        "time_limit" => {
+                            settings.time_limit = Some(entry.parse()?);
+                        }
+                        "denoise" => {
+                            settings.denoise = entry.parse()?;
+                        }
+                        "working_space" => {
+                            settings.working_space = match ColorSpace::from_name(value){
+                                Some(color_space) => color_space,
+                                None => {
+                                    return Err(entry.error(format!("Unknown color space '{}'.", value)));
+                                }
+                            };
+                        }
+                        "exposure" => {
+                            settings.exposure = entry.parse()?;
+                        }
+                        "tone_mapper" => {
+                            settings.tone_mapper = match ToneMapper::from_name(value){
+                                Some(tone_mapper) => tone_mapper,
+                                None => {
+                                    return Err(entry.error(format!("Unknown tone mapper '{}'.", value)));
+                                }
+                            };
+                        }
+                        "bloom_threshold" => {
+                            settings.post_process.bloom_threshold = entry.parse()?;
+                        }
+                        "bloom_intensity" => {
+                            settings.post_process.bloom_intensity = entry.parse()?;
+                        }
+                        "bloom_radius" => {
+                            settings.post_process.bloom_radius = entry.parse()?;
+                        }
+                        "glare_intensity" => {
+                            settings.post_process.glare_intensity = entry.parse()?;
+                        }
+                        "glare_streaks" => {
+                            settings.post_process.glare_streaks = entry.parse()?;
+                        }
+                        "glare_length" => {
+                            settings.post_process.glare_length = entry.parse()?;
+                        }
+                        "vignette" => {
+                            settings.post_process.vignette = entry.parse()?;
+                        }
+                        "lens_distortion" => {
+                            settings.post_process.lens_distortion = entry.parse()?;
+                        }
+                        "chromatic_aberration" => {
+                            settings.post_process.chromatic_aberration = entry.parse()?;
+                        }
+                        "filter" => {
+                            filter_type = match FilterType::from_name(value){
+                                Some(filter_type) => filter_type,
+                                None => {
+                                    return Err(entry.error(format!("Unknown filter '{}'.", value)));
+                                }
+                            };
+                        }
+                        "filter_radius" => {
+                            let radius: f32 = entry.parse()?;
+                            if radius <= 0.0{
+                                return Err(entry.error("The filter radius has to be positive.".to_owned()));
+                            }
+                            filter_radius = Some(radius);
+                        }
+                        "aovs" => {
+                            settings.aovs = Vec::new();
+                            for name in value.split(";").map(|x| x.trim()){
+                                match Aov::from_name(name){
+                                    Some(aov) => settings.aovs.push(aov),
+                                    None => {
+                                        return Err(LightError::parse(filename, *line_number, column_of(line, name), format!("Unknown AOV '{}'.", name)));
+                                    }
+                                }
+                            }
+                        }
+                        _ => {
+                            return Err(entry.key_error(format!("Unimplemented key while parsing render settings '{}'.", key)));
+                        }
+                    }
+                }
+                else{
+                    return Err(LightError::parse(filename, *line_number, 1, "Hit end of line while parsing render settings."));
+                }
+            },
+            None => { return Err(LightError::parse(filename, *line_number, 1, "Hit end of file while parsing render settings.")); },
+        };
+    }
+    settings.filter = Filter::new(filter_type, filter_radius.unwrap_or(filter_type.default_radius()));
+    return Ok(settings);
+}
+
+fn parse_fog<'a, I>(lines: &mut Peekable<I>, filename: &str, line_number: &mut usize) -> Result<Medium, LightError>
+where
+I: DoubleEndedIterator<Item = &'a str> + Clone{
+    let mut medium = Medium::default();
+    while lines.peek().is_some_and(|line| !line.starts_with('[')){
+        match lines.next(){
+            Some(line) => {
+                *line_number += 1;
+                if let Some(entry) = Entry::new(line, filename, *line_number){
+                    let key = entry.key;
+                    match key{
+                        "absorption" | "scattering" | "anisotropy" => {
+                            parse_medium_property(&mut medium, &entry)?;
+                        }
+                        _ => {
+                            return Err(entry.key_error(format!("Unimplemented key while parsing fog '{}'.", key)));
+                        }
+                    }
+                }
+                else{
+                    return Err(LightError::parse(filename, *line_number, 1, "Hit end of line while parsing fog."));
+                }
+            },
+            None => { return Err(LightError::parse(filename, *line_number, 1, "Hit end of file while parsing fog.")); },
+        };
+    }
+    return Ok(medium);
+}
+
+fn parse_texture_object<'a, I>(lines: &mut Peekable<I>, filename: &str, line_number: &mut usize, working_space: ColorSpace) -> Result<(String, Texture), LightError>
+where
+I: DoubleEndedIterator<Item = &'a str> + Clone{
+    let mut name: Option<String> = None;
+    let mut texture = Texture::constant(Vec3::one());
+    let mut wrap_mode = WrapMode::Repeat;
+    let mut image_file: Option<String> = None;
+    let mut is_image_texture = false;
+    let mut is_srgb_encoded = true;
+    // `None` for data like normal maps, which must not be converted
+    let mut color_space = Some(ColorSpace::LinearRec709);
+
+    while lines.peek().is_some_and(|line| !line.starts_with('[')){
+        match lines.next(){
+            Some(line) => {
+                *line_number += 1;
+                if let Some(entry) = Entry::new(line, filename, *line_number){
+                    let (key, value) = (entry.key, entry.value);
+                    match key{
+                        "name" => {
+                            name = Some(value.to_string());
+                        }
+                        "texture_type" => {
+                            is_image_texture = value == "image_texture";
+                            texture = match value{
+                                "constant_texture" => Texture::ConstantTexture { color: Vec3::one() },
+                                "image_texture" => Texture::ConstantTexture { color: Vec3::one() },
+                                "checker_texture" => Texture::CheckerTexture { even: Vec3::zero(), odd: Vec3::one(), scale: 10.0 },
+                                "noise_texture" => Texture::NoiseTexture { low: Vec3::zero(), high: Vec3::one(), scale: 1.0 },
+                                _ => {
+                                    return Err(entry.error(format!("Unimplemented texture type '{}'.", value)));
+                                }
+                            };
+                        }
+                        "file" => {
+                            image_file = Some(value.to_string());
+                        }
+                        "color_space" => {
+                            (is_srgb_encoded, color_space) = match value{
+                                "srgb" => (true, Some(ColorSpace::LinearRec709)),
+                                "linear" => (false, Some(ColorSpace::LinearRec709)),
+                                "non_color" => (false, None),
+                                _ => match ColorSpace::from_name(value){
+                                    Some(color_space) => (false, Some(color_space)),
+                                    None => {
+                                        return Err(entry.error(format!("Unknown color space '{}'.", value)));
+                                    }
+                                },
+                            };
+                        }
+                        "wrap_mode" => {
+                            wrap_mode = match value{
+                                "repeat" => WrapMode::Repeat,
+                                "mirrored_repeat" => WrapMode::MirroredRepeat,
+                                "clamp" => WrapMode::Clamp,
+                                _ => {
+                                    return Err(entry.error(format!("Unknown wrap mode '{}'.", value)));
+                                }
+                            };
+                        }
+                        "color" | "even" | "odd" | "low" | "high" => {
+                            let parsed = entry.vec3()?;
+                            match (&mut texture, key){
+                                (Texture::ConstantTexture { color }, "color") => *color = parsed,
+                                (Texture::CheckerTexture { even, .. }, "even") => *even = parsed,
+                                (Texture::CheckerTexture { odd, .. }, "odd") => *odd = parsed,
+                                (Texture::NoiseTexture { low, .. }, "low") => *low = parsed,
+                                (Texture::NoiseTexture { high, .. }, "high") => *high = parsed,
+                                _ => {
+                                    return Err(entry.key_error(format!("Texture has no property '{}'.", key)));
+                                }
+                            }
+                        }
+                        "scale" => {
+                            match &mut texture{
+                                Texture::CheckerTexture { scale, .. } | Texture::NoiseTexture { scale, .. } => {
+                                    *scale = entry.parse::<f32>()?;
+                                }
+                                _ => {
+                                    return Err(entry.key_error(format!("Texture has no property '{}'.", key)));
+                                }
+                            }
+                        }
+                        _ => {
+                            return Err(entry.key_error(format!("Unimplemented key while parsing texture '{}'.", key)));
+                        }
+                    }
+                }
+                else{
+                    return Err(LightError::parse(filename, *line_number, 1, "Hit end of line while parsing texture."));
+                }
+            },
+            None => { return Err(LightError::parse(filename, *line_number, 1, "Hit end of file while parsing texture.")); },
+        };
+    }
+
+    if is_image_texture{
+        match image_file{
+            Some(file) => {
+                let color_space = color_space.unwrap_or(working_space);
+                texture = Texture::from_file(&resolve_path(filename, &file), wrap_mode, is_srgb_encoded, color_space, working_space)?;
+            }
+            None => {
+                return Err(LightError::parse(filename, *line_number, 1, "No file provided for image texture."));
+            }
+        }
+    }
+    else if let Some(color_space) = color_space{
+        texture = texture.map_colors(|x| convert(x, color_space, working_space));
+    }
+
+    match name{
+        Some(name) => Ok((name, texture)),
+        None => Err(LightError::parse(filename, *line_number, 1, "Texture without a name.")),
+    }
+}
+
+fn parse_object_header<'a, I>(mut line: I, filename: &str, line_number: usize) -> Result<ObjectHeader, LightError>
     where
         I: DoubleEndedIterator<Item = &'a char> + Clone{
     let object_type = line.clone().take_while(|x| **x != ']').collect::<String>();
     match line.next_back(){
         Some(']') => {},
-        Some(x) => { return Err(Box::new(ParsingError{filename: filename.to_owned(), line: line_number, message: format!("Invalid last character '{}' for closing bracket of object header.", x).to_string()})); },
-        None => { return Err(Box::new(ParsingError{filename: filename.to_owned(), line: line_number, message: "Hit end of line while parsing object header.".to_string()})); },
+        Some(x) => { return Err(LightError::parse(filename, line_number, 1, format!("Invalid last character '{}' for closing bracket of object header.", x))); },
+        None => { return Err(LightError::parse(filename, line_number, 1, "Hit end of line while parsing object header.")); },
     };
     return match object_type.as_str(){
         "mesh" => Ok(ObjectHeader::Mesh),
         "sphere" => Ok(ObjectHeader::Sphere),
         "camera" => Ok(ObjectHeader::Camera),
-        x => { return Err(Box::new(ParsingError{filename: filename.to_owned(), line: line_number, message: format!("Unknown object type '{}'", x)})); },
+        "texture" => Ok(ObjectHeader::Texture),
+        "fog" => Ok(ObjectHeader::Fog),
+        "render" => Ok(ObjectHeader::Render),
+        x => { return Err(LightError::parse(filename, line_number, 1, format!("Unknown object type '{}'", x))); },
     };
 }
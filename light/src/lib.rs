@@ -12,6 +12,20 @@ pub mod trace_ray;
 pub mod math_utils;
 pub mod image_filters;
 pub mod importing;
-pub mod parsing_error;
+pub mod error;
 pub mod scene;
 pub mod bounding_box;
+pub mod texture;
+pub mod medium;
+pub mod spectrum;
+pub mod render_settings;
+pub mod sampler;
+pub mod adaptive_sampling;
+pub mod aov;
+pub mod color_space;
+pub mod exr;
+pub mod film;
+pub mod image_metrics;
+pub mod render_statistics;
+pub mod validation;
+pub mod render;
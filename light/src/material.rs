@@ -1,10 +1,148 @@
-use ultraviolet::Vec3;
+use ultraviolet::{Vec3, Vec2};
 
+use crate::{texture::Texture, hit_result::HitResult, medium::Medium};
+
+/// Extra surface detail that perturbs the shading normal.
+#[derive(Clone, Debug)]
+pub struct SurfaceDetail{
+    /// Tangent space normal map, encoded as `0.5 * normal + 0.5`.
+    pub normal_map: Option<Texture>,
+    /// Height map whose gradient tilts the normal.
+    pub bump_map: Option<Texture>,
+    pub bump_strength: f32,
+}
+
+impl Default for SurfaceDetail{
+    fn default() -> SurfaceDetail{
+        SurfaceDetail{
+            normal_map: None,
+            bump_map: None,
+            bump_strength: 1.0,
+        }
+    }
+}
+
+impl SurfaceDetail{
+    /// Replaces the shading normal of `hit` with the perturbed one, keeping the side of the surface that was hit.
+    pub fn apply(&self, hit: &mut HitResult, point: Vec3){
+        if self.normal_map.is_none() && self.bump_map.is_none(){
+            return;
+        }
+        let mut normal = if hit.is_front_face {hit.normal} else {-hit.normal};
+
+        if let Some(normal_map) = &self.normal_map{
+            let tangent_space_normal = normal_map.sample(hit.uv, point) * 2.0 - Vec3::one();
+            let perturbed = hit.tangent * tangent_space_normal.x + hit.bitangent * tangent_space_normal.y + normal * tangent_space_normal.z;
+            if perturbed.mag_sq() > 0.0{
+                normal = perturbed.normalized();
+            }
+        }
+
+        if let Some(bump_map) = &self.bump_map{
+            let step = bump_map.derivative_step();
+            let height = bump_map.sample_scalar(hit.uv, point);
+            let height_du = bump_map.sample_scalar(hit.uv + Vec2::new(step.x, 0.0), point + hit.tangent * step.x);
+            let height_dv = bump_map.sample_scalar(hit.uv + Vec2::new(0.0, step.y), point + hit.bitangent * step.y);
+            let gradient_u = (height_du - height) / step.x;
+            let gradient_v = (height_dv - height) / step.y;
+            let perturbed = normal - self.bump_strength * (gradient_u * hit.tangent + gradient_v * hit.bitangent);
+            if perturbed.mag_sq() > 0.0{
+                normal = perturbed.normalized();
+            }
+        }
+
+        hit.normal = if hit.is_front_face {normal} else {-normal};
+    }
+}
+
+/// Wavelength dependence of the index of refraction, only used when rendering spectrally.
 #[derive(Clone, Copy, Debug)]
+pub enum Dispersion{
+    /// `n(λ) = ior + b * (1/λ² - 1/λ_D²)` with λ in micrometers, so `ior` stays the index at the sodium D line.
+    Cauchy{b: f32},
+    /// `n²(λ) = 1 + Σ b_i λ² / (λ² - c_i)` with λ in micrometers.
+    Sellmeier{b: Vec3, c: Vec3},
+}
+
+impl Dispersion{
+    pub fn ior(&self, base_ior: f32, wavelength: f32) -> f32{
+        let wavelength_sq = (wavelength / 1000.0).powi(2);
+        match self{
+            Dispersion::Cauchy { b } => {
+                const SODIUM_D_LINE_SQ: f32 = 0.5893 * 0.5893;
+                base_ior + b * (1.0 / wavelength_sq - 1.0 / SODIUM_D_LINE_SQ)
+            }
+            Dispersion::Sellmeier { b, c } => {
+                let mut ior_sq = 1.0;
+                for i in 0..3{
+                    ior_sq += b[i] * wavelength_sq / (wavelength_sq - c[i]);
+                }
+                ior_sq.max(1.0).sqrt()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum Material{
     NormalMaterial(),
-    DiffuseMaterial{albedo: Vec3},
-    MetallicMaterial{albedo: Vec3, roughness: f32}, 
-    DielectricMaterial{albedo: Vec3, ior: f32},
-    EmissiveMaterial{emission_color: Vec3, strength: f32},
+    DiffuseMaterial{albedo: Texture, detail: SurfaceDetail},
+    MetallicMaterial{albedo: Texture, roughness: Texture, detail: SurfaceDetail},
+    /// `priority` decides which object wins where dielectrics overlap; higher values win.
+    DielectricMaterial{albedo: Texture, ior: f32, detail: SurfaceDetail, medium: Option<Medium>, priority: i32, dispersion: Option<Dispersion>},
+    EmissiveMaterial{emission_color: Texture, strength: f32},
+    /// An invisible boundary that only marks the extent of the medium inside of it.
+    VolumeMaterial{medium: Medium, priority: i32},
+}
+
+impl Material{
+    pub fn surface_detail(&self) -> Option<&SurfaceDetail>{
+        match self{
+            Material::DiffuseMaterial { detail, .. } => Some(detail),
+            Material::MetallicMaterial { detail, .. } => Some(detail),
+            Material::DielectricMaterial { detail, .. } => Some(detail),
+            _ => None,
+        }
+    }
+
+    /// The medium filling the inside of objects with this material, if light can get there.
+    pub fn interior_medium(&self) -> Option<&Medium>{
+        match self{
+            Material::DielectricMaterial { medium, .. } => medium.as_ref(),
+            Material::VolumeMaterial { medium, .. } => Some(medium),
+            _ => None,
+        }
+    }
+
+    /// Priority of materials that enclose a volume the ray can travel through.
+    pub fn priority(&self) -> Option<i32>{
+        match self{
+            Material::DielectricMaterial { priority, .. } => Some(*priority),
+            Material::VolumeMaterial { priority, .. } => Some(*priority),
+            _ => None,
+        }
+    }
+
+    /// Index of refraction of the inside at `wavelength` (in nm), or ignoring dispersion if there is none.
+    /// Volume boundaries don't change the index of refraction.
+    pub fn ior(&self, wavelength: Option<f32>) -> Option<f32>{
+        match (self, wavelength){
+            (Material::DielectricMaterial { ior, dispersion: Some(dispersion), .. }, Some(wavelength)) => Some(dispersion.ior(*ior, wavelength)),
+            (Material::DielectricMaterial { ior, .. }, _) => Some(*ior),
+            _ => None,
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool{
+        matches!(self, Material::DielectricMaterial { dispersion: Some(_), .. })
+    }
+
+    pub fn surface_detail_mut(&mut self) -> Option<&mut SurfaceDetail>{
+        match self{
+            Material::DiffuseMaterial { detail, .. } => Some(detail),
+            Material::MetallicMaterial { detail, .. } => Some(detail),
+            Material::DielectricMaterial { detail, .. } => Some(detail),
+            _ => None,
+        }
+    }
 }
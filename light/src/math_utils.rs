@@ -1,6 +1,17 @@
 use std::ops::{Add, Mul};
 
+use ultraviolet::Vec3;
+
 pub fn lerp<T>(t: f32, x0: T, x1: T) -> T
 where T: Add<T, Output = T> + Mul<f32, Output = T>{
     x0*(1.0-t) + x1*t
 }
+
+pub fn component_average(vector: Vec3) -> f32{
+    (vector.x + vector.y + vector.z) / 3.0
+}
+
+/// False if any component is NaN or infinite.
+pub fn is_finite(vector: Vec3) -> bool{
+    vector.x.is_finite() && vector.y.is_finite() && vector.z.is_finite()
+}
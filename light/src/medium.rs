@@ -0,0 +1,170 @@
+use ultraviolet::Vec3;
+
+use crate::{material::Material, spectrum::Spectrum};
+
+/// A homogeneous participating medium. Coefficients are per scene unit.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Medium{
+    pub absorption: Vec3,
+    pub scattering: Vec3,
+    /// Henyey-Greenstein asymmetry parameter. Positive values scatter forward, negative values backward.
+    pub anisotropy: f32,
+}
+
+pub enum MediumInteraction<S: Spectrum>{
+    /// The ray scattered at `distance` and has to continue in a new direction.
+    Scattered{distance: f32, weight: S},
+    /// The ray reached the next surface.
+    Passed{weight: S},
+}
+
+impl Medium{
+    pub fn extinction(&self) -> Vec3{
+        self.absorption + self.scattering
+    }
+
+    /// Beer-Lambert attenuation over `distance`.
+    pub fn transmittance<S: Spectrum>(&self, distance: f32, wavelengths: &S::Wavelengths) -> S{
+        S::from_rgb(self.extinction(), wavelengths).map(|extinction| beer_lambert(extinction, distance))
+    }
+
+    /// Samples a free flight distance proportionally to the transmittance of a uniformly chosen colour channel.
+    /// `surface_distance` is the distance to the next surface (infinite if there is none).
+    pub fn sample_interaction<S: Spectrum>(&self, surface_distance: f32, channel_sample: f32, distance_sample: f32, wavelengths: &S::Wavelengths) -> MediumInteraction<S>{
+        let extinction = S::from_rgb(self.extinction(), wavelengths);
+        let channel = ((channel_sample * S::channel_count() as f32) as usize).min(S::channel_count() - 1);
+        let distance = if extinction.channel(channel) > 0.0 {-(1.0 - distance_sample).ln() / extinction.channel(channel)} else {f32::INFINITY};
+
+        if distance < surface_distance{
+            let transmittance: S = self.transmittance(distance, wavelengths);
+            let pdf = (extinction * transmittance).average();
+            return MediumInteraction::Scattered { distance, weight: S::from_rgb(self.scattering, wavelengths) * transmittance / pdf };
+        }
+
+        let transmittance: S = self.transmittance(surface_distance, wavelengths);
+        let probability = transmittance.average();
+        if probability <= 0.0{
+            return MediumInteraction::Passed { weight: S::splat(0.0) };
+        }
+        MediumInteraction::Passed { weight: transmittance / probability }
+    }
+
+    /// Samples a new direction from the Henyey-Greenstein phase function around the propagation `direction`.
+    /// The phase function is sampled exactly, so no additional weight is needed.
+    pub fn sample_phase(&self, direction: Vec3, sample1: f32, sample2: f32) -> Vec3{
+        let g = self.anisotropy;
+        let cos_theta = if g.abs() < 1e-3{
+            1.0 - 2.0 * sample1
+        }
+        else{
+            let square = (1.0 - g * g) / (1.0 - g + 2.0 * g * sample1);
+            (1.0 + g * g - square * square) / (2.0 * g)
+        }.clamp(-1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * sample2;
+
+        let helper = if direction.x.abs() < 0.9 {Vec3::unit_x()} else {Vec3::unit_y()};
+        let u = direction.cross(helper).normalized();
+        let v = direction.cross(u);
+        (u * (sin_theta * phi.cos()) + v * (sin_theta * phi.sin()) + direction * cos_theta).normalized()
+    }
+
+    /// Value of the Henyey-Greenstein phase function for the angle between the propagation directions.
+    pub fn phase(&self, cos_theta: f32) -> f32{
+        let g = self.anisotropy;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * std::f32::consts::PI * denominator * denominator.sqrt())
+    }
+}
+
+fn beer_lambert(extinction: f32, distance: f32) -> f32{
+    if extinction == 0.0{
+        return 1.0;
+    }
+    (-extinction * distance).exp()
+}
+
+#[derive(Clone, Copy)]
+struct MediumStackEntry<'a>{
+    material: &'a Material,
+    priority: i32,
+}
+
+/// The objects a path is currently inside of, used to find the index of refraction and medium on both sides of an
+/// interface. Overlapping objects are resolved by priority: only the object with the highest priority is considered
+/// present, surfaces of objects with lower priority are skipped.
+#[derive(Clone, Default)]
+pub struct MediumStack<'a>{
+    entries: Vec<MediumStackEntry<'a>>,
+}
+
+impl<'a> MediumStack<'a>{
+    pub fn new() -> MediumStack<'a>{
+        MediumStack { entries: Vec::new() }
+    }
+
+    /// The entry with the highest priority. Ties go to the most recently entered object.
+    fn top_where<F>(&self, predicate: F) -> Option<&MediumStackEntry<'a>>
+    where F: Fn(&MediumStackEntry<'a>) -> bool{
+        self.entries.iter().filter(|entry| predicate(entry)).fold(None, |best: Option<&MediumStackEntry<'a>>, entry| {
+            match best{
+                Some(best) if best.priority > entry.priority => Some(best),
+                _ => Some(entry),
+            }
+        })
+    }
+
+    /// Whether a hit on a surface of `material` is an actual interface or lies inside of an object with higher priority.
+    pub fn is_real_interface(&self, material: &Material, is_entering: bool) -> bool{
+        let priority = material.priority().unwrap_or(i32::MAX);
+        if is_entering{
+            return self.top_where(|_| true).is_none_or(|top| priority >= top.priority);
+        }
+        match self.top_where(|_| true){
+            // leaving an object the path never entered (e.g. the camera is inside of it) is always real
+            Some(top) => std::ptr::eq(top.material, material) || !self.contains(material),
+            None => true,
+        }
+    }
+
+    pub fn contains(&self, material: &Material) -> bool{
+        self.entries.iter().any(|entry| std::ptr::eq(entry.material, material))
+    }
+
+    /// The stack after entering an object with `material`.
+    pub fn entered(&self, material: &'a Material) -> MediumStack<'a>{
+        let mut stack = self.clone();
+        stack.entries.push(MediumStackEntry { material, priority: material.priority().unwrap_or(0) });
+        stack
+    }
+
+    /// The stack after leaving an object with `material`.
+    pub fn left(&self, material: &Material) -> MediumStack<'a>{
+        let mut stack = self.clone();
+        if let Some(index) = stack.entries.iter().rposition(|entry| std::ptr::eq(entry.material, material)){
+            stack.entries.remove(index);
+        }
+        stack
+    }
+
+    fn ior_material(&self) -> Option<&'a Material>{
+        self.top_where(|entry| entry.material.ior(None).is_some()).map(|entry| entry.material)
+    }
+
+    /// Index of refraction at the current position, 1.0 outside of all objects.
+    pub fn ior(&self, wavelength: Option<f32>) -> f32{
+        self.ior_material().and_then(|material| material.ior(wavelength)).unwrap_or(1.0)
+    }
+
+    pub fn is_dispersive(&self) -> bool{
+        self.ior_material().is_some_and(|material| material.is_dispersive())
+    }
+
+    /// Medium at the current position, `outside` if the path isn't inside of any object.
+    pub fn medium(&self, outside: Option<&'a Medium>) -> Option<&'a Medium>{
+        match self.top_where(|_| true){
+            Some(top) => top.material.interior_medium(),
+            None => outside,
+        }
+    }
+}
@@ -1,104 +1,239 @@
-use std::{fs::read_to_string, error::Error, usize};
+use std::{fs::read_to_string, usize, collections::HashMap};
 
-use ultraviolet::{Vec3, Vec2};
+use ultraviolet::{Vec3, Vec2, Vec4};
 
-use crate::{triangle::Triangle, material::Material, hittable::Hittable, ray::Ray, hit_result::HitResult, bounding_box::BVH};
+use crate::{triangle::Triangle, material::Material, hittable::Hittable, ray::Ray, hit_result::HitResult, bounding_box::BVH, error::{LightError, column_of}, math_utils::is_finite, validation::{Diagnostic, Severity, SourceLocation}};
 
 pub struct Mesh{
     pub triangles: Option<Box<dyn Hittable>>,
     pub material: Material,
+    pub object_id: u32,
+    pub material_id: u32,
+    /// Problems found in the OBJ file, see `Scene::validate`.
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 impl Mesh {
-    pub fn from_obj(filename: &str) -> Result<Mesh, Box<dyn Error>>{
+    pub fn from_obj(filename: &str) -> Result<Mesh, LightError>{
         println!("Loading \"{}\"...", filename);
-        let mut mesh = Mesh{
-            triangles: None,
+        let content = read_to_string(filename).map_err(|error| LightError::io(filename, error))?;
+        Mesh::parse_obj(&content, filename)
+    }
+
+    /// Parses the content of an OBJ file. `filename` is only used for error messages.
+    pub fn parse_obj(content: &str, filename: &str) -> Result<Mesh, LightError>{
+        let (triangles, diagnostics) = parse_obj_file(content, filename)?;
+        Ok(Mesh{
+            triangles: Some(BVH::<dyn Hittable>::build_recursive(triangles.into_iter().map(|x| Box::new(x) as Box<dyn Hittable>).collect())),
             material: Material::NormalMaterial(),
-        };
+            object_id: 0,
+            material_id: 0,
+            diagnostics,
+        })
+    }
+}
 
-        let mut vertices: Vec<Vec3> = Vec::new();
-        let mut normals: Vec<Vec3> = Vec::new();
-        let mut uvs: Vec<Vec2> = Vec::new();
-        
-        let mut triangles: Vec<Box<dyn Hittable>> = Vec::new();
-
-        let mut do_normal_smoothing = false;
-
-        for line in read_to_string(filename)?.lines() {
-            let mut line_parts = line.split(" ");
-            match line_parts.nth(0){
-                Some("v") => {
-                    vertices.push(Vec3{
-                        x: line_parts.next().unwrap().parse::<f32>()?,
-                        y: line_parts.next().unwrap().parse::<f32>()?,
-                        z: line_parts.next().unwrap().parse::<f32>()?,
-                    });
-                }
-                Some("vt") => {
-                    uvs.push(Vec2{
-                        x: line_parts.next().unwrap().parse::<f32>()?,
-                        y: line_parts.next().unwrap().parse::<f32>()?,
-                    });
-                }
-                Some("vn") => {
-                    normals.push(Vec3{
-                        x: line_parts.next().unwrap().parse::<f32>()?,
-                        y: line_parts.next().unwrap().parse::<f32>()?,
-                        z: line_parts.next().unwrap().parse::<f32>()?,
-                    }.normalized());
-                }
-                Some("f") => {
-                    // vertices
-                    let mut triangle = Triangle::default();
-                    for i in 0..3{
-                        let part = line_parts.next().unwrap();
-                        let mut indices = part.split("/").filter(|x| x.chars().count() != 0);
-                        triangle.vertices[i] = vertices[indices.next().unwrap().parse::<usize>()? - 1];
-                        if !part.contains("//"){
-                            triangle.uv_coordinates[i] = uvs[indices.next().unwrap().parse::<usize>()? - 1];
-                        }
-                        
-                        if do_normal_smoothing{
-                            triangle.normals[i] = normals[indices.next().unwrap().parse::<usize>()? - 1];
-                        }
-                    }
-                    if !do_normal_smoothing{
-                        let face_normal = (triangle.vertices[1] - triangle.vertices[0]).cross(triangle.vertices[2] - triangle.vertices[0]).normalized();
-                        triangle.normals[0] = face_normal;
-                        triangle.normals[1] = face_normal;
-                        triangle.normals[2] = face_normal;
-                    }
+/// The triangles of an OBJ file, with tangents. Fails for files without faces.
+pub fn parse_obj_triangles(content: &str, filename: &str) -> Result<Vec<Triangle>, LightError>{
+    parse_obj_file(content, filename).map(|(triangles, _)| triangles)
+}
+
+/// At most this many diagnostics of one kind are reported per file, the rest are summarized.
+const MAX_DIAGNOSTICS: usize = 5;
+
+/// Like `parse_obj_triangles`, but also returns problems that don't stop the file from loading.
+fn parse_obj_file(content: &str, filename: &str) -> Result<(Vec<Triangle>, Vec<Diagnostic>), LightError>{
+    let mut vertices: Vec<Vec3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<Vec2> = Vec::new();
+    
+    let mut triangles: Vec<Triangle> = Vec::new();
+    // (position, uv, normal) indices of every triangle corner; 0 if the index is missing
+    let mut corner_indices: Vec<[(usize, usize, usize); 3]> = Vec::new();
 
+    let mut do_normal_smoothing = false;
+    let mut invalid_vertex_lines: Vec<usize> = Vec::new();
+    let mut degenerate_face_lines: Vec<usize> = Vec::new();
 
-                    triangles.push(Box::new(triangle));
+    for (line_index, line) in content.lines().enumerate() {
+        let line_number = line_index + 1;
+        let missing_value = || LightError::parse(filename, line_number, line.chars().count() + 1, "Missing value.");
+        let number = |part: Option<&str>| {
+            let part = part.ok_or_else(missing_value)?;
+            part.parse::<f32>().map_err(|_| LightError::parse(filename, line_number, column_of(line, part), format!("'{}' isn't a number.", part)))
+        };
+        let mut line_parts = line.split(" ");
+        match line_parts.nth(0){
+            Some("v") => {
+                vertices.push(Vec3{
+                    x: number(line_parts.next())?,
+                    y: number(line_parts.next())?,
+                    z: number(line_parts.next())?,
+                });
+                if !is_finite(vertices[vertices.len() - 1]){
+                    invalid_vertex_lines.push(line_number);
                 }
-                Some("#") => {},
-                Some("s") =>{
-                    do_normal_smoothing = match line_parts.next().unwrap(){
-                        "off" => false,
-                        _ => true,
+            }
+            Some("vt") => {
+                uvs.push(Vec2{
+                    x: number(line_parts.next())?,
+                    y: number(line_parts.next())?,
+                });
+            }
+            Some("vn") => {
+                normals.push(Vec3{
+                    x: number(line_parts.next())?,
+                    y: number(line_parts.next())?,
+                    z: number(line_parts.next())?,
+                }.normalized());
+            }
+            Some("f") => {
+                // vertices
+                let mut triangle = Triangle::default();
+                let mut corners = [(0, 0, 0); 3];
+                for i in 0..3{
+                    let part = line_parts.next().ok_or_else(missing_value)?;
+                    let mut indices = part.split("/").filter(|x| x.chars().count() != 0);
+                    corners[i].0 = parse_index(indices.next(), vertices.len(), line, filename, line_number)?;
+                    triangle.vertices[i] = vertices[corners[i].0 - 1];
+                    if !part.contains("//"){
+                        corners[i].1 = parse_index(indices.next(), uvs.len(), line, filename, line_number)?;
+                        triangle.uv_coordinates[i] = uvs[corners[i].1 - 1];
+                    }
+                    
+                    if do_normal_smoothing{
+                        corners[i].2 = parse_index(indices.next(), normals.len(), line, filename, line_number)?;
+                        triangle.normals[i] = normals[corners[i].2 - 1];
                     }
                 }
-                Some(rest) => {
-                    println!("Unhandled line {}", rest);
+                let (edge1, edge2) = (triangle.vertices[1] - triangle.vertices[0], triangle.vertices[2] - triangle.vertices[0]);
+                if edge1.cross(edge2).mag_sq() <= f32::EPSILON * f32::EPSILON * edge1.mag_sq() * edge2.mag_sq(){
+                    degenerate_face_lines.push(line_number);
+                }
+                if !do_normal_smoothing{
+                    let face_normal = (triangle.vertices[1] - triangle.vertices[0]).cross(triangle.vertices[2] - triangle.vertices[0]).normalized();
+                    triangle.normals[0] = face_normal;
+                    triangle.normals[1] = face_normal;
+                    triangle.normals[2] = face_normal;
                 }
-                None => {},
+
+
+                triangles.push(triangle);
+                corner_indices.push(corners);
+            }
+            Some("#") => {},
+            Some("s") =>{
+                do_normal_smoothing = match line_parts.next().ok_or_else(missing_value)?{
+                    "off" => false,
+                    _ => true,
+                }
+            }
+            Some(rest) => {
+                println!("Unhandled line {}", rest);
             }
+            None => {},
+        }
+    }
+
+    if triangles.is_empty(){
+        return Err(LightError::parse(filename, content.lines().count(), 1, "The mesh doesn't contain any faces."));
+    }
+
+    generate_tangents(&mut triangles, &corner_indices);
+
+    let mut diagnostics = Vec::new();
+    add_diagnostics(&mut diagnostics, Severity::Error, &invalid_vertex_lines, filename, "The vertex has a coordinate that isn't a finite number.");
+    add_diagnostics(&mut diagnostics, Severity::Warning, &degenerate_face_lines, filename, "The face has no area, so it can never be hit.");
+    Ok((triangles, diagnostics))
+}
+
+/// Adds `message` for the first few of `lines` and a summary of the others.
+fn add_diagnostics(diagnostics: &mut Vec<Diagnostic>, severity: Severity, lines: &[usize], filename: &str, message: &str){
+    for line in lines.iter().take(MAX_DIAGNOSTICS){
+        diagnostics.push(Diagnostic { severity, location: Some(SourceLocation::new(filename, *line)), message: message.to_owned() });
+    }
+    if lines.len() > MAX_DIAGNOSTICS{
+        let message = format!("{} more lines like line {}.", lines.len() - MAX_DIAGNOSTICS, lines[0]);
+        diagnostics.push(Diagnostic { severity, location: Some(SourceLocation::new(filename, lines[MAX_DIAGNOSTICS])), message });
+    }
+}
+
+/// Parses a one based index into a list of `count` elements. `value` is a part of `line`.
+fn parse_index(value: Option<&str>, count: usize, line: &str, filename: &str, line_number: usize) -> Result<usize, LightError>{
+    let value = match value{
+        Some(value) => value,
+        None => {
+            return Err(LightError::parse(filename, line_number, line.chars().count() + 1, "Missing index."));
         }
+    };
+    let error = |message: String| LightError::parse(filename, line_number, column_of(line, value), message);
+    let index = value.parse::<usize>().map_err(|_| error(format!("'{}' isn't an index.", value)))?;
+    if index == 0 || index > count{
+        return Err(error(format!("Index {} is out of range, there are only {} elements.", index, count)));
+    }
+    Ok(index)
+}
 
-        mesh.triangles = Some(BVH::<dyn Hittable>::build_recursive(triangles));
+/// Computes per vertex tangents following the MikkTSpace conventions: face tangents are weighted by the corner angle,
+/// vertices are only shared between faces of the same UV handedness, the tangent is orthogonalized against the vertex
+/// normal and the bitangent is reconstructed as `sign * normal x tangent`.
+fn generate_tangents(triangles: &mut [Triangle], corner_indices: &[[(usize, usize, usize); 3]]){
+    let mut accumulated: HashMap<((usize, usize, usize), bool), Vec3> = HashMap::new();
+    let mut face_tangents: Vec<(Vec3, bool)> = Vec::with_capacity(triangles.len());
 
-        return Ok(mesh);
+    for (triangle, corners) in triangles.iter().zip(corner_indices){
+        let edge1 = triangle.vertices[1] - triangle.vertices[0];
+        let edge2 = triangle.vertices[2] - triangle.vertices[0];
+        let delta_uv1 = triangle.uv_coordinates[1] - triangle.uv_coordinates[0];
+        let delta_uv2 = triangle.uv_coordinates[2] - triangle.uv_coordinates[0];
+        let determinant = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+
+        let (tangent, is_right_handed) = if determinant.abs() > 1e-12{
+            let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) / determinant;
+            let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) / determinant;
+            (tangent, edge1.cross(edge2).cross(tangent).dot(bitangent) >= 0.0)
+        }
+        else{
+            // no usable UVs, any tangent in the plane will do
+            (edge1, true)
+        };
+        face_tangents.push((tangent, is_right_handed));
+
+        for (i, corner) in corners.iter().enumerate(){
+            let to_next = (triangle.vertices[(i + 1) % 3] - triangle.vertices[i]).normalized();
+            let to_previous = (triangle.vertices[(i + 2) % 3] - triangle.vertices[i]).normalized();
+            let angle = to_next.dot(to_previous).clamp(-1.0, 1.0).acos();
+            let tangent_sum = accumulated.entry((*corner, is_right_handed)).or_insert(Vec3::zero());
+            *tangent_sum += tangent.normalized() * angle;
+        }
+    }
+
+    for ((triangle, corners), (face_tangent, is_right_handed)) in triangles.iter_mut().zip(corner_indices).zip(face_tangents){
+        for i in 0..3{
+            let normal = triangle.normals[i];
+            let mut tangent = accumulated[&(corners[i], is_right_handed)];
+            tangent -= normal * normal.dot(tangent);
+            if tangent.mag_sq() < 1e-12{
+                tangent = face_tangent - normal * normal.dot(face_tangent);
+            }
+            if tangent.mag_sq() < 1e-12{
+                tangent = if normal.x.abs() < 0.9 {Vec3::unit_x()} else {Vec3::unit_y()};
+                tangent -= normal * normal.dot(tangent);
+            }
+            tangent.normalize();
+            triangle.tangents[i] = Vec4::new(tangent.x, tangent.y, tangent.z, if is_right_handed {1.0} else {-1.0});
+        }
     }
 }
 
 impl Hittable for Mesh{
-    fn hit(&self, ray: Ray, hit: &mut HitResult, min_distance: f32) -> bool{
+    fn hit<'a>(&'a self, ray: Ray, hit: &mut HitResult<'a>, min_distance: f32) -> bool{
         match &self.triangles{
             Some(bvh) => {
                 if bvh.hit(ray, hit, min_distance){
-                    hit.material = Some(self.material);
+                    hit.material = Some(&self.material);
+                    hit.object_id = self.object_id;
+                    hit.material_id = self.material_id;
                     return true;
                 }
                 return false;
@@ -108,9 +243,15 @@ impl Hittable for Mesh{
     }
 
     fn get_min_bounds(&self) -> Vec3 {
-        self.triangles.as_ref().expect("A mesh should always contain triangles").get_min_bounds()
+        self.triangles.as_ref().map_or(Vec3::zero(), |triangles| triangles.get_min_bounds())
     }
     fn get_max_bounds(&self) -> Vec3 {
-        self.triangles.as_ref().expect("A mesh should always contain triangles").get_max_bounds()
+        self.triangles.as_ref().map_or(Vec3::zero(), |triangles| triangles.get_max_bounds())
+    }
+    fn validate(&self, diagnostics: &mut Vec<Diagnostic>) {
+        diagnostics.extend(self.diagnostics.iter().cloned());
+    }
+    fn memory_usage(&self) -> usize {
+        size_of_val(self) + self.triangles.as_ref().map_or(0, |bvh| bvh.memory_usage())
     }
 }
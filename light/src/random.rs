@@ -1,15 +1,48 @@
-use ultraviolet::Vec3;
-
-pub fn random_in_unit_sphere() -> Vec3{
-    loop{  
-        let v = Vec3{x: rand::random(), y: rand::random(), z: rand::random()} * 2.0 - Vec3::new(1.0, 1.0, 1.0);
-        if v.mag_sq() < 1.0{
-            return v;
-        }
-    }
+use std::f32::consts::PI;
+
+use ultraviolet::{Vec3, Vec2};
+
+use crate::sampler::Sampler;
+
+pub fn random_on_unit_sphere(sampler: &mut dyn Sampler) -> Vec3{
+    let sample = sampler.next_2d();
+    let z = 1.0 - 2.0 * sample.x;
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * sample.y;
+    Vec3::new(radius * phi.cos(), radius * phi.sin(), z)
 }
 
-pub fn random_on_unit_sphere() -> Vec3{
-    random_in_unit_sphere().normalized()
+pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Vec3{
+    let direction = random_on_unit_sphere(sampler);
+    direction * sampler.next_1d().cbrt()
 }
 
+/// Concentric mapping of the unit square to the unit disk, which keeps stratification intact.
+pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec2{
+    let offset = sampler.next_2d() * 2.0 - Vec2::one();
+    if offset.x == 0.0 && offset.y == 0.0{
+        return Vec2::zero();
+    }
+    let (radius, theta) = if offset.x.abs() > offset.y.abs(){
+        (offset.x, PI / 4.0 * (offset.y / offset.x))
+    }
+    else{
+        (offset.y, PI / 2.0 - PI / 4.0 * (offset.x / offset.y))
+    };
+    Vec2::new(radius * theta.cos(), radius * theta.sin())
+}
+
+/// Uniform point in a regular polygon with `sides` corners on the unit circle, the first one at angle `rotation`.
+/// Uses a single 2D sample like `random_in_unit_disk`: x picks the triangle of the fan and is reused inside of it.
+pub fn random_in_regular_polygon(sides: u32, rotation: f32, sampler: &mut dyn Sampler) -> Vec2{
+    let sample = sampler.next_2d();
+    let scaled = sample.x * sides as f32;
+    let side = scaled.floor().min(sides as f32 - 1.0);
+    let corner = |i: f32| {
+        let angle = rotation + 2.0 * PI * i / sides as f32;
+        Vec2::new(angle.cos(), angle.sin())
+    };
+    // uniform in the triangle between the center and two neighbouring corners
+    let distance = (scaled - side).sqrt();
+    distance * (corner(side) * (1.0 - sample.y) + corner(side + 1.0) * sample.y)
+}
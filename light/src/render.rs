@@ -0,0 +1,112 @@
+use std::sync::Mutex;
+
+use rayon::prelude::*;
+use ultraviolet::{Vec2, Vec3};
+
+use crate::{adaptive_sampling::SampleStatistics, aov::{trace_aovs, Aov, AovBuffers, AovSample}, film::Film, image::Image, render_settings::RenderSettings, render_statistics, sampler::create_sampler, scene::Scene, trace_ray::trace_camera_ray};
+
+/// Rows rendered in parallel before they are added to the buffers. Bounds the memory the samples of a pass need.
+const ROWS_PER_BATCH: usize = 64;
+
+/// One camera sample of a pixel.
+#[derive(Clone, Copy, Debug)]
+pub struct PixelSample{
+    /// Position within the pixel in [0, 1)^2.
+    pub offset: Vec2,
+    pub color: Vec3,
+    /// Only if the scene renders AOVs or the denoising guides are needed.
+    pub aovs: Option<AovSample>,
+}
+
+/// Renders sample `sample_index` of every pixel of row `y` that hasn't converged yet, with the first hit for the
+/// denoising guides if `guides` is set. The result only depends on the arguments, not on the thread or the order the
+/// rows are rendered in.
+pub fn render_row(scene: &Scene, y: u32, sample_index: u32, statistics: &SampleStatistics, guides: bool) -> Vec<Option<PixelSample>>{
+    let mut sampler = create_sampler(scene.settings.sampler, scene.settings.seed, scene.settings.samples_per_pixel);
+    (0..scene.width).map(|x| {
+        if statistics.is_converged(x, y){
+            return None;
+        }
+        sampler.start_sample((x, y), sample_index);
+        let offset = sampler.next_2d();
+
+        let u = (x as f32 + offset.x) / scene.width as f32;
+        let v = (y as f32 + offset.y) / scene.height as f32;
+
+        let color = match scene.camera.get_ray(u, v, sampler.as_mut()){
+            Some(ray) => trace_camera_ray(ray, scene, sampler.as_mut()),
+            // outside of a fisheye's image circle
+            None => Vec3::zero(),
+        };
+        let aovs = if scene.settings.aovs.is_empty() && !guides {None} else {Some(trace_aovs(scene, sampler.as_mut(), (x, y), sample_index, color, &scene.settings.aovs))};
+        Some(PixelSample { offset, color, aovs })
+    }).collect()
+}
+
+/// Everything the camera samples are accumulated in.
+#[derive(Clone, Debug)]
+pub struct RenderBuffers{
+    pub film: Film,
+    pub statistics: SampleStatistics,
+    pub aovs: AovBuffers,
+    /// Albedo and normal for `image_filters::denoise`, averaged over the same samples as the image.
+    pub guides: Option<AovBuffers>,
+}
+
+impl RenderBuffers{
+    /// The denoising guides are only accumulated if `guides` is set.
+    pub fn new(scene: &Scene, guides: bool) -> RenderBuffers{
+        RenderBuffers {
+            film: Film::new(scene.width, scene.height, scene.settings.filter),
+            statistics: SampleStatistics::new(scene.width, scene.height),
+            aovs: AovBuffers::new(&scene.settings.aovs, scene.width, scene.height),
+            guides: guides.then(|| AovBuffers::new(&[Aov::Albedo, Aov::Normal], scene.width, scene.height)),
+        }
+    }
+
+    /// Adds the samples of row `y`. Samples are splatted into the neighbouring rows and floating point sums depend on
+    /// the order, so rows have to be added in the same order every time for the image to be reproducible.
+    pub fn add_row(&mut self, y: u32, row: &[Option<PixelSample>]){
+        for (x, sample) in row.iter().enumerate(){
+            if let Some(sample) = sample{
+                let x = x as u32;
+                self.statistics.add_sample(x, y, sample.color);
+                self.film.add_sample((x, y), sample.offset, sample.color);
+                if let Some(aovs) = &sample.aovs{
+                    self.aovs.add_sample(x, y, aovs);
+                    if let Some(guides) = &mut self.guides{
+                        guides.add_sample(x, y, aovs);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The albedo and normal guides, if they are accumulated.
+    pub fn guide_images(&self, settings: &RenderSettings) -> Option<(Image, Image)>{
+        let guides = self.guides.as_ref()?;
+        Some((guides.image(Aov::Albedo, settings)?, guides.image(Aov::Normal, settings)?))
+    }
+}
+
+/// Renders sample `sample_index` of every pixel that hasn't converged yet on the rayon thread pool and adds it to
+/// `buffers`. Rows are rendered in parallel but added in order, so the image is the same for any number of threads.
+/// The buffers are only locked while rows are added, so they can be displayed during the pass.
+pub fn render_pass(scene: &Scene, sample_index: u32, buffers: &Mutex<RenderBuffers>){
+    let (statistics, guides) = {
+        let buffers = buffers.lock().unwrap();
+        (buffers.statistics.clone(), buffers.guides.is_some())
+    };
+    let scanlines = (0..scene.height).collect::<Vec<u32>>();
+    for batch in scanlines.chunks(ROWS_PER_BATCH){
+        let rows: Vec<Vec<Option<PixelSample>>> = batch.par_iter().map(|y| {
+            let row = render_row(scene, *y, sample_index, &statistics, guides);
+            render_statistics::flush();
+            row
+        }).collect();
+        let mut buffers = buffers.lock().unwrap();
+        for (y, row) in batch.iter().zip(&rows){
+            buffers.add_row(*y, row);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use crate::{sampler::SamplerType, aov::Aov, image_filters::{ToneMapper, PostProcessSettings}, color_space::ColorSpace, film::Filter, film::FilterType};
+
+/// Settings from the `[render]` section of a scene file.
+#[derive(Clone, Debug)]
+pub struct RenderSettings{
+    /// The most samples a pixel gets. Pixels can stop earlier with a noise threshold.
+    pub samples_per_pixel: usize,
+    pub max_depth: i32,
+    /// Trace wavelengths instead of RGB. Needed for dispersion; the image then contains XYZ until converted.
+    pub spectral: bool,
+    /// Seed for all random numbers. The same seed always gives the same image.
+    pub seed: u64,
+    pub sampler: SamplerType,
+    /// Stop sampling pixels once their relative error is below this (see `adaptive_sampling`).
+    pub noise_threshold: Option<f32>,
+    /// Samples every pixel gets before it can be considered converged.
+    pub min_samples: usize,
+    /// Stop rendering after this many seconds, even if not all samples are done.
+    pub time_limit: Option<f32>,
+    /// Run `image_filters::denoise` on the final image.
+    pub denoise: bool,
+    /// Extra passes to render, see `aov::AovBuffers`.
+    pub aovs: Vec<Aov>,
+    /// Colour space of the rendered colours. Spectral renders always use linear Rec.709 internally and are converted
+    /// to it afterwards.
+    pub working_space: ColorSpace,
+    /// Exposure adjustment in stops, applied before tone mapping.
+    pub exposure: f32,
+    pub tone_mapper: ToneMapper,
+    /// Bloom, glare and lens effects, applied to the displayed image before tone mapping.
+    pub post_process: PostProcessSettings,
+    /// Pixel reconstruction filter used by the `film::Film`.
+    pub filter: Filter,
+    /// Count rays, BVH nodes, primitive tests and path lengths, see `render_statistics`.
+    pub statistics: bool,
+}
+
+impl Default for RenderSettings{
+    fn default() -> RenderSettings{
+        RenderSettings{
+            samples_per_pixel: 1000,
+            max_depth: 50,
+            spectral: false,
+            seed: 0,
+            sampler: SamplerType::Sobol,
+            noise_threshold: None,
+            min_samples: 16,
+            time_limit: None,
+            denoise: false,
+            aovs: Vec::new(),
+            working_space: ColorSpace::LinearRec709,
+            exposure: 0.0,
+            tone_mapper: ToneMapper::Clamp,
+            post_process: PostProcessSettings::default(),
+            filter: Filter::new(FilterType::Box, FilterType::Box.default_radius()),
+            statistics: false,
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::{cell::{Cell, RefCell}, fmt, sync::{atomic::{AtomicBool, Ordering}, Mutex}, time::Duration};
+
+use crate::error::LightError;
+
+/// Counters are only collected while this is set, so they cost a single relaxed load otherwise. Timings and the
+/// geometry size are always recorded, they are cheap.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TOTALS: Mutex<RenderStatistics> = Mutex::new(RenderStatistics::new());
+
+/// Counters of the current thread. Hot loops only touch these, `flush` adds them to the totals.
+struct LocalCounters{
+    rays_traced: Cell<u64>,
+    bvh_nodes_visited: Cell<u64>,
+    primitive_tests: Cell<u64>,
+    /// Segments of the path traced right now.
+    path_length: Cell<u32>,
+    path_lengths: RefCell<Vec<u64>>,
+}
+
+thread_local!{
+    static LOCAL: LocalCounters = const { LocalCounters {
+        rays_traced: Cell::new(0),
+        bvh_nodes_visited: Cell::new(0),
+        primitive_tests: Cell::new(0),
+        path_length: Cell::new(0),
+        path_lengths: RefCell::new(Vec::new()),
+    } };
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Phase{
+    /// Reading the scene and its meshes, including the BVH builds.
+    Load,
+    BvhBuild,
+    Render,
+}
+
+/// What a render did and where the time went. Collected for the whole process, see `report` and `reset`.
+#[derive(Clone, Debug, Default)]
+pub struct RenderStatistics{
+    /// Closest hit queries against the scene.
+    pub rays_traced: u64,
+    pub bvh_nodes_visited: u64,
+    /// Ray-triangle and ray-sphere tests.
+    pub primitive_tests: u64,
+    /// Number of camera paths with a given number of segments, indexed by the segment count.
+    pub path_lengths: Vec<u64>,
+    pub load_time: Duration,
+    pub bvh_build_time: Duration,
+    pub render_time: Duration,
+    /// Approximate size of the geometry and its BVHs in bytes.
+    pub geometry_memory: usize,
+}
+
+pub fn set_enabled(enabled: bool){
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn is_enabled() -> bool{
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[inline]
+fn count(counter: impl Fn(&LocalCounters) -> &Cell<u64>){
+    if is_enabled(){
+        LOCAL.with(|local| {
+            let counter = counter(local);
+            counter.set(counter.get() + 1);
+        });
+    }
+}
+
+#[inline]
+pub fn count_ray(){
+    count(|local| &local.rays_traced);
+}
+
+#[inline]
+pub fn count_bvh_node(){
+    count(|local| &local.bvh_nodes_visited);
+}
+
+#[inline]
+pub fn count_primitive_test(){
+    count(|local| &local.primitive_tests);
+}
+
+/// Starts a new camera path on this thread.
+#[inline]
+pub fn start_path(){
+    if is_enabled(){
+        LOCAL.with(|local| local.path_length.set(0));
+    }
+}
+
+#[inline]
+pub fn count_path_segment(){
+    if is_enabled(){
+        LOCAL.with(|local| local.path_length.set(local.path_length.get() + 1));
+    }
+}
+
+/// Records the length of the path started with `start_path`.
+#[inline]
+pub fn end_path(){
+    if is_enabled(){
+        LOCAL.with(|local| {
+            let length = local.path_length.get() as usize;
+            let mut path_lengths = local.path_lengths.borrow_mut();
+            if path_lengths.len() <= length{
+                path_lengths.resize(length + 1, 0);
+            }
+            path_lengths[length] += 1;
+        });
+    }
+}
+
+/// Adds the counters of the calling thread to the totals. Render threads call this when they finish a piece of work.
+/// Does nothing while statistics are disabled, so the totals are never locked then.
+pub fn flush(){
+    if !is_enabled(){
+        return;
+    }
+    LOCAL.with(|local| {
+        let mut totals = TOTALS.lock().unwrap();
+        totals.rays_traced += local.rays_traced.replace(0);
+        totals.bvh_nodes_visited += local.bvh_nodes_visited.replace(0);
+        totals.primitive_tests += local.primitive_tests.replace(0);
+        let path_lengths = local.path_lengths.take();
+        if totals.path_lengths.len() < path_lengths.len(){
+            totals.path_lengths.resize(path_lengths.len(), 0);
+        }
+        for (total, count) in totals.path_lengths.iter_mut().zip(path_lengths){
+            *total += count;
+        }
+    });
+}
+
+pub fn add_time(phase: Phase, duration: Duration){
+    let mut totals = TOTALS.lock().unwrap();
+    match phase{
+        Phase::Load => totals.load_time += duration,
+        Phase::BvhBuild => totals.bvh_build_time += duration,
+        Phase::Render => totals.render_time += duration,
+    }
+}
+
+pub fn set_geometry_memory(bytes: usize){
+    TOTALS.lock().unwrap().geometry_memory = bytes;
+}
+
+/// The totals so far, including the counters of the calling thread.
+pub fn report() -> RenderStatistics{
+    flush();
+    TOTALS.lock().unwrap().clone()
+}
+
+/// Clears the totals, e.g. before loading the next frame.
+pub fn reset(){
+    flush();
+    *TOTALS.lock().unwrap() = RenderStatistics::new();
+}
+
+impl RenderStatistics{
+    const fn new() -> RenderStatistics{
+        RenderStatistics {
+            rays_traced: 0,
+            bvh_nodes_visited: 0,
+            primitive_tests: 0,
+            path_lengths: Vec::new(),
+            load_time: Duration::ZERO,
+            bvh_build_time: Duration::ZERO,
+            render_time: Duration::ZERO,
+            geometry_memory: 0,
+        }
+    }
+
+    pub fn paths(&self) -> u64{
+        self.path_lengths.iter().sum()
+    }
+
+    pub fn mean_path_length(&self) -> f64{
+        let segments: u64 = self.path_lengths.iter().enumerate().map(|(length, count)| length as u64 * count).sum();
+        segments as f64 / self.paths().max(1) as f64
+    }
+
+    pub fn rays_per_second(&self) -> f64{
+        self.rays_traced as f64 / self.render_time.as_secs_f64()
+    }
+
+    pub fn to_json(&self) -> String{
+        let path_lengths = self.path_lengths.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", ");
+        format!(
+            "{{\n  \"rays_traced\": {},\n  \"bvh_nodes_visited\": {},\n  \"primitive_tests\": {},\n  \"path_length_histogram\": [{}],\n  \
+             \"load_seconds\": {},\n  \"bvh_build_seconds\": {},\n  \"render_seconds\": {},\n  \"geometry_memory_bytes\": {}\n}}\n",
+            self.rays_traced, self.bvh_nodes_visited, self.primitive_tests, path_lengths,
+            self.load_time.as_secs_f64(), self.bvh_build_time.as_secs_f64(), self.render_time.as_secs_f64(), self.geometry_memory
+        )
+    }
+
+    pub fn save_json(&self, filename: &str) -> Result<(), LightError>{
+        std::fs::write(filename, self.to_json()).map_err(|error| LightError::io(filename, error))
+    }
+}
+
+impl fmt::Display for RenderStatistics{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        let per_ray = |x: u64| x as f64 / self.rays_traced.max(1) as f64;
+        writeln!(f, "Load:            {:.2?} (BVH build {:.2?})", self.load_time, self.bvh_build_time)?;
+        writeln!(f, "Render:          {:.2?}", self.render_time)?;
+        writeln!(f, "Geometry:        {:.2} MiB", self.geometry_memory as f64 / (1024.0 * 1024.0))?;
+        if self.rays_traced == 0{
+            return writeln!(f, "No ray statistics, enable them with `statistics = true` in [render].");
+        }
+        writeln!(f, "Rays traced:     {} ({:.2} Mrays/s)", self.rays_traced, self.rays_per_second() / 1e6)?;
+        writeln!(f, "BVH nodes:       {} ({:.1} per ray)", self.bvh_nodes_visited, per_ray(self.bvh_nodes_visited))?;
+        writeln!(f, "Primitive tests: {} ({:.1} per ray)", self.primitive_tests, per_ray(self.primitive_tests))?;
+        writeln!(f, "Paths:           {} (mean length {:.2})", self.paths(), self.mean_path_length())?;
+        let most = self.path_lengths.iter().copied().max().unwrap_or(0).max(1);
+        for (length, count) in self.path_lengths.iter().enumerate(){
+            writeln!(f, "  {:>3} {:>12} {}", length, count, "#".repeat((count * 40).div_ceil(most) as usize))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+use std::sync::OnceLock;
+
+use ultraviolet::Vec2;
+
+/// Source of the random numbers used while rendering. Every number is determined by the pixel, the sample index and
+/// the dimension (how many numbers were requested before it), so renders are reproducible no matter how the work is
+/// split between threads.
+pub trait Sampler{
+    /// Starts a new sample of a pixel. Dimensions restart at zero.
+    fn start_sample(&mut self, pixel: (u32, u32), sample_index: u32);
+    /// Returns the next number in [0, 1).
+    fn next_1d(&mut self) -> f32;
+
+    fn next_2d(&mut self) -> Vec2{
+        Vec2::new(self.next_1d(), self.next_1d())
+    }
+}
+
+/// Independent uniform random numbers, derived by hashing (seed, pixel, sample index, dimension).
+#[derive(Clone, Debug)]
+pub struct IndependentSampler{
+    seed: u64,
+    pixel: (u32, u32),
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl IndependentSampler{
+    pub fn new(seed: u64) -> IndependentSampler{
+        IndependentSampler { seed, pixel: (0, 0), sample_index: 0, dimension: 0 }
+    }
+}
+
+impl Sampler for IndependentSampler{
+    fn start_sample(&mut self, pixel: (u32, u32), sample_index: u32){
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32{
+        let hash = hash_values(&[self.seed, self.pixel.0 as u64, self.pixel.1 as u64, self.sample_index as u64, self.dimension as u64]);
+        self.dimension += 1;
+        to_unit_float(hash)
+    }
+}
+
+/// Stratifies every dimension separately: each of the `samples_per_pixel` samples of a pixel falls into its own
+/// stratum, with the strata shuffled independently per pixel and dimension. Pairs of dimensions use a jittered grid
+/// if the sample count is a square number.
+#[derive(Clone, Debug)]
+pub struct StratifiedSampler{
+    seed: u64,
+    samples_per_pixel: u32,
+    pixel: (u32, u32),
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler{
+    pub fn new(seed: u64, samples_per_pixel: u32) -> StratifiedSampler{
+        StratifiedSampler { seed, samples_per_pixel: samples_per_pixel.max(1), pixel: (0, 0), sample_index: 0, dimension: 0 }
+    }
+
+    /// Hash of everything except the sample index. Samples beyond `samples_per_pixel` start a new, differently
+    /// shuffled round of strata.
+    fn dimension_hash(&self) -> u64{
+        let round = self.sample_index / self.samples_per_pixel;
+        hash_values(&[self.seed, self.pixel.0 as u64, self.pixel.1 as u64, round as u64, self.dimension as u64])
+    }
+}
+
+impl Sampler for StratifiedSampler{
+    fn start_sample(&mut self, pixel: (u32, u32), sample_index: u32){
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32{
+        let hash = self.dimension_hash();
+        let stratum = permute(self.sample_index % self.samples_per_pixel, self.samples_per_pixel, hash as u32);
+        let jitter = to_unit_float(mix_bits(hash ^ self.sample_index as u64));
+        self.dimension += 1;
+        ((stratum as f32 + jitter) / self.samples_per_pixel as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn next_2d(&mut self) -> Vec2{
+        let grid_size = (self.samples_per_pixel as f32).sqrt() as u32;
+        if grid_size * grid_size != self.samples_per_pixel{
+            return Vec2::new(self.next_1d(), self.next_1d());
+        }
+        let hash = self.dimension_hash();
+        let stratum = permute(self.sample_index % self.samples_per_pixel, self.samples_per_pixel, hash as u32);
+        let jitter = Vec2::new(
+            to_unit_float(mix_bits(hash ^ self.sample_index as u64)),
+            to_unit_float(mix_bits(hash.rotate_left(32) ^ self.sample_index as u64)),
+        );
+        self.dimension += 2;
+        let cell = Vec2::new((stratum % grid_size) as f32, (stratum / grid_size) as f32);
+        ((cell + jitter) / grid_size as f32).min_by_component(Vec2::broadcast(ONE_MINUS_EPSILON))
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+/// The Halton sequence, one prime base per dimension. Pixels are decorrelated with a per pixel and dimension
+/// Cranley-Patterson rotation. Dimensions beyond the tabulated primes fall back to independent numbers.
+#[derive(Clone, Debug)]
+pub struct HaltonSampler{
+    seed: u64,
+    pixel: (u32, u32),
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl HaltonSampler{
+    pub fn new(seed: u64) -> HaltonSampler{
+        HaltonSampler { seed, pixel: (0, 0), sample_index: 0, dimension: 0 }
+    }
+}
+
+impl Sampler for HaltonSampler{
+    fn start_sample(&mut self, pixel: (u32, u32), sample_index: u32){
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32{
+        let hash = hash_values(&[self.seed, self.pixel.0 as u64, self.pixel.1 as u64, self.dimension as u64]);
+        let value = match PRIMES.get(self.dimension as usize){
+            Some(base) => {
+                let rotated = radical_inverse(self.sample_index, *base) + to_unit_float(hash);
+                rotated - rotated.floor()
+            }
+            None => to_unit_float(mix_bits(hash ^ self.sample_index as u64)),
+        };
+        self.dimension += 1;
+        value.min(ONE_MINUS_EPSILON)
+    }
+}
+
+fn radical_inverse(mut index: u32, base: u32) -> f32{
+    let inverse_base = 1.0 / base as f64;
+    let mut factor = inverse_base;
+    let mut result = 0.0;
+    while index > 0{
+        result += (index % base) as f64 * factor;
+        index /= base;
+        factor *= inverse_base;
+    }
+    result as f32
+}
+
+/// Owen-scrambled Sobol points, following Burley's "Practical Hash-based Owen Scrambling". Dimensions are used in
+/// groups of four; every group shuffles the sample order differently so the groups don't correlate.
+#[derive(Clone, Debug)]
+pub struct SobolSampler{
+    seed: u64,
+    pixel: (u32, u32),
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler{
+    pub fn new(seed: u64) -> SobolSampler{
+        SobolSampler { seed, pixel: (0, 0), sample_index: 0, dimension: 0 }
+    }
+}
+
+impl Sampler for SobolSampler{
+    fn start_sample(&mut self, pixel: (u32, u32), sample_index: u32){
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32{
+        let group_seed = hash_values(&[self.seed, self.pixel.0 as u64, self.pixel.1 as u64, (self.dimension / 4) as u64]);
+        let component = self.dimension % 4;
+        let index = nested_uniform_scramble(self.sample_index, group_seed as u32);
+        let value = nested_uniform_scramble(sobol(index, component), mix_bits(group_seed ^ component as u64) as u32);
+        self.dimension += 1;
+        (value >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+/// Direction numbers of the first four Sobol dimensions, from the primitive polynomials of Joe and Kuo.
+fn sobol_directions() -> &'static [[u32; 32]; 4]{
+    static DIRECTIONS: OnceLock<[[u32; 32]; 4]> = OnceLock::new();
+    DIRECTIONS.get_or_init(|| {
+        // (degree, coefficients, initial direction numbers)
+        let polynomials: [(usize, u32, &[u32]); 3] = [(1, 0, &[1]), (2, 1, &[1, 3]), (3, 1, &[1, 3, 1])];
+        let mut directions = [[0u32; 32]; 4];
+        for (i, direction) in directions[0].iter_mut().enumerate(){
+            *direction = 1 << (31 - i);
+        }
+        for (dimension, (degree, coefficients, initial)) in polynomials.iter().enumerate(){
+            let v = &mut directions[dimension + 1];
+            for i in 0..32{
+                if i < *degree{
+                    v[i] = initial[i] << (31 - i);
+                    continue;
+                }
+                v[i] = v[i - degree] ^ (v[i - degree] >> degree);
+                for k in 1..*degree{
+                    if (coefficients >> (degree - 1 - k)) & 1 == 1{
+                        v[i] ^= v[i - k];
+                    }
+                }
+            }
+        }
+        directions
+    })
+}
+
+fn sobol(index: u32, dimension: u32) -> u32{
+    let directions = &sobol_directions()[dimension as usize];
+    let mut result = 0;
+    let mut bits = index;
+    let mut bit = 0;
+    while bits != 0{
+        if bits & 1 == 1{
+            result ^= directions[bit];
+        }
+        bits >>= 1;
+        bit += 1;
+    }
+    result
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32{
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32{
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// Kensler's hash based permutation of [0, length): element `i` of the permutation selected by `seed`.
+pub fn permute(mut i: u32, length: u32, seed: u32) -> u32{
+    let mut mask = length.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    loop{
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= mask;
+        i ^= i >> 5;
+        if i < length{
+            break;
+        }
+    }
+    // in 64 bits, wrapping around would break the permutation for lengths that aren't powers of two
+    ((i as u64 + seed as u64) % length as u64) as u32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplerType{
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+pub fn create_sampler(sampler_type: SamplerType, seed: u64, samples_per_pixel: usize) -> Box<dyn Sampler>{
+    match sampler_type{
+        SamplerType::Independent => Box::new(IndependentSampler::new(seed)),
+        SamplerType::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel as u32)),
+        SamplerType::Halton => Box::new(HaltonSampler::new(seed)),
+        SamplerType::Sobol => Box::new(SobolSampler::new(seed)),
+    }
+}
+
+/// The largest float below one.
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// The splitmix64 finalizer.
+pub fn mix_bits(mut x: u64) -> u64{
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58476d1ce4e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+pub fn hash_values(values: &[u64]) -> u64{
+    values.iter().fold(0x9e3779b97f4a7c15, |hash, value| mix_bits(hash ^ value.wrapping_add(0x9e3779b97f4a7c15)))
+}
+
+/// Maps the upper 24 bits to a float in [0, 1).
+pub fn to_unit_float(bits: u64) -> f32{
+    (bits >> 40) as f32 / (1u64 << 24) as f32
+}
@@ -1,4 +1,6 @@
-use crate::{camera::Camera, hittable::Hittable};
+use ultraviolet::Vec3;
+
+use crate::{render_statistics, camera::Camera, hittable::Hittable, medium::Medium, render_settings::RenderSettings, validation::{Diagnostic, SourceLocation}};
 
 #[derive(Default)]
 pub struct Scene{
@@ -6,16 +8,53 @@ pub struct Scene{
     pub bvh: Option<Box<dyn Hittable>>,
     pub width: u32,
     pub height: u32,
+    /// Medium filling the space outside of all objects.
+    pub fog: Option<Medium>,
+    pub settings: RenderSettings,
+    /// Where the `[camera]` section is, `None` if the scene doesn't have one.
+    pub camera_location: Option<SourceLocation>,
+    /// The start of the scene file, for problems of the scene as a whole. `None` if it wasn't parsed from a file.
+    pub location: Option<SourceLocation>,
+}
+
+impl Scene{
+    /// Looks for everything that keeps the scene from rendering correctly, like a camera without a view direction,
+    /// spheres with a negative radius or NaN vertices, and things that are probably mistakes, like triangles without
+    /// area. Errors come first.
+    pub fn validate(&self) -> Vec<Diagnostic>{
+        let mut diagnostics = Vec::new();
+        match &self.camera_location{
+            Some(location) => {
+                self.camera.validate(Some(location), &mut diagnostics);
+                if self.width == 0 || self.height == 0{
+                    diagnostics.push(Diagnostic::error(Some(location), format!("The image size {}x{} is empty.", self.width, self.height)));
+                }
+            }
+            None => diagnostics.push(Diagnostic::error(None, "The scene doesn't contain a camera.")),
+        }
+        match &self.bvh{
+            Some(bvh) => bvh.validate(&mut diagnostics),
+            None => diagnostics.push(Diagnostic::error(self.location.as_ref(), "The scene doesn't contain any objects.")),
+        }
+        // stable, so the order within each severity is kept
+        diagnostics.sort_by_key(|diagnostic| std::cmp::Reverse(diagnostic.severity));
+        diagnostics
+    }
 }
 
 impl Hittable for Scene{
-    fn hit(&self, ray: crate::ray::Ray, hit: &mut crate::hit_result::HitResult, min_distance: f32) -> bool {
-        return self.bvh.as_ref().expect("scene doesn't contain a BVH").hit(ray, hit, min_distance);
+    fn hit<'a>(&'a self, ray: crate::ray::Ray, hit: &mut crate::hit_result::HitResult<'a>, min_distance: f32) -> bool {
+        render_statistics::count_ray();
+        // a scene without a BVH is empty
+        return self.bvh.as_ref().is_some_and(|bvh| bvh.hit(ray, hit, min_distance));
+    }
+    fn get_min_bounds(&self) -> Vec3 {
+        self.bvh.as_ref().map_or(Vec3::zero(), |bvh| bvh.get_min_bounds())
     }
-    fn get_min_bounds(&self) -> ultraviolet::Vec3 {
-        self.bvh.as_ref().expect("scene doesn't contain a BVH").get_min_bounds()
+    fn get_max_bounds(&self) -> Vec3 {
+        self.bvh.as_ref().map_or(Vec3::zero(), |bvh| bvh.get_max_bounds())
     }
-    fn get_max_bounds(&self) -> ultraviolet::Vec3 {
-        self.bvh.as_ref().expect("scene doesn't contain a BVH").get_max_bounds()
+    fn memory_usage(&self) -> usize {
+        size_of_val(self) + self.bvh.as_ref().map_or(0, |bvh| bvh.memory_usage())
     }
 }
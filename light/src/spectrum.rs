@@ -0,0 +1,155 @@
+use std::{ops::{Add, AddAssign, Mul, Div}, sync::OnceLock};
+
+use ultraviolet::{Vec3, Vec4};
+
+use crate::math_utils::component_average;
+
+pub const WAVELENGTH_MIN: f32 = 360.0;
+pub const WAVELENGTH_MAX: f32 = 830.0;
+
+/// Radiance or reflectance carried along a path. Implemented for RGB (`Vec3`) and for four wavelengths (`Vec4`),
+/// so the same integrator can run in both modes.
+pub trait Spectrum: Copy + Add<Output = Self> + AddAssign + Mul<Output = Self> + Mul<f32, Output = Self> + Div<f32, Output = Self>{
+    /// The wavelengths the channels belong to. Nothing for RGB.
+    type Wavelengths: Copy;
+
+    fn from_rgb(rgb: Vec3, wavelengths: &Self::Wavelengths) -> Self;
+    fn splat(value: f32) -> Self;
+    fn map<F: Fn(f32) -> f32>(self, f: F) -> Self;
+    fn channel(&self, index: usize) -> f32;
+    fn channel_count() -> usize;
+    fn average(&self) -> f32;
+
+    /// Wavelength that decides the path at wavelength dependent events, `None` if the colours aren't spectral.
+    fn hero_wavelength(wavelengths: &Self::Wavelengths) -> Option<f32>;
+    /// Drops every wavelength except the hero wavelength, compensating for the samples that were lost.
+    fn only_hero(self) -> Self;
+}
+
+impl Spectrum for Vec3{
+    type Wavelengths = ();
+
+    fn from_rgb(rgb: Vec3, _: &()) -> Vec3{
+        rgb
+    }
+    fn splat(value: f32) -> Vec3{
+        Vec3::broadcast(value)
+    }
+    fn map<F: Fn(f32) -> f32>(self, f: F) -> Vec3{
+        Vec3::new(f(self.x), f(self.y), f(self.z))
+    }
+    fn channel(&self, index: usize) -> f32{
+        self[index]
+    }
+    fn channel_count() -> usize{
+        3
+    }
+    fn average(&self) -> f32{
+        component_average(*self)
+    }
+    fn hero_wavelength(_: &()) -> Option<f32>{
+        None
+    }
+    fn only_hero(self) -> Vec3{
+        self
+    }
+}
+
+impl Spectrum for Vec4{
+    type Wavelengths = Vec4;
+
+    fn from_rgb(rgb: Vec3, wavelengths: &Vec4) -> Vec4{
+        wavelengths.map(|wavelength| rgb_to_spectrum(rgb, wavelength))
+    }
+    fn splat(value: f32) -> Vec4{
+        Vec4::broadcast(value)
+    }
+    fn map<F: Fn(f32) -> f32>(self, f: F) -> Vec4{
+        Vec4::new(f(self.x), f(self.y), f(self.z), f(self.w))
+    }
+    fn channel(&self, index: usize) -> f32{
+        self[index]
+    }
+    fn channel_count() -> usize{
+        4
+    }
+    fn average(&self) -> f32{
+        (self.x + self.y + self.z + self.w) / 4.0
+    }
+    fn hero_wavelength(wavelengths: &Vec4) -> Option<f32>{
+        Some(wavelengths.x)
+    }
+    fn only_hero(self) -> Vec4{
+        Vec4::new(self.x * 4.0, 0.0, 0.0, 0.0)
+    }
+}
+
+/// Hero wavelength sampling: the first wavelength is uniform, the others are rotated by a quarter of the range.
+pub fn sample_wavelengths(sample: f32) -> Vec4{
+    let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+    let hero = sample * range;
+    Vec4::new(0.0, 0.25, 0.5, 0.75).map(|offset| WAVELENGTH_MIN + (hero + offset * range) % range)
+}
+
+fn piecewise_gaussian(wavelength: f32, mean: f32, sigma_below: f32, sigma_above: f32) -> f32{
+    let t = (wavelength - mean) / if wavelength < mean {sigma_below} else {sigma_above};
+    (-0.5 * t * t).exp()
+}
+
+/// CIE 1931 colour matching functions, using the multi-lobe fit by Wyman, Sloan and Shirley.
+pub fn cie_xyz(wavelength: f32) -> Vec3{
+    Vec3::new(
+        1.056 * piecewise_gaussian(wavelength, 599.8, 37.9, 31.0) + 0.362 * piecewise_gaussian(wavelength, 442.0, 16.0, 26.7) - 0.065 * piecewise_gaussian(wavelength, 501.1, 20.4, 26.2),
+        0.821 * piecewise_gaussian(wavelength, 568.8, 46.9, 40.5) + 0.286 * piecewise_gaussian(wavelength, 530.9, 16.3, 31.1),
+        1.217 * piecewise_gaussian(wavelength, 437.0, 11.8, 36.0) + 0.681 * piecewise_gaussian(wavelength, 459.0, 26.0, 13.8),
+    )
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32{
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Upsamples an RGB colour to a spectrum using three smooth basis functions that sum up to one. Reflectances stay
+/// inside [0, 1] and white becomes a flat spectrum.
+pub fn rgb_to_spectrum(rgb: Vec3, wavelength: f32) -> f32{
+    let red = smoothstep(575.0, 605.0, wavelength);
+    let blue = 1.0 - smoothstep(475.0, 505.0, wavelength);
+    let green = 1.0 - red - blue;
+    rgb.x * red + rgb.y * green + rgb.z * blue
+}
+
+/// Integrals of the colour matching functions over the sampled range.
+fn cie_integrals() -> Vec3{
+    static INTEGRALS: OnceLock<Vec3> = OnceLock::new();
+    *INTEGRALS.get_or_init(|| {
+        let steps = ((WAVELENGTH_MAX - WAVELENGTH_MIN) * 4.0) as usize;
+        let step_size = (WAVELENGTH_MAX - WAVELENGTH_MIN) / steps as f32;
+        (0..steps).map(|i| cie_xyz(WAVELENGTH_MIN + (i as f32 + 0.5) * step_size) * step_size).fold(Vec3::zero(), |a, b| a + b)
+    })
+}
+
+/// Monte Carlo estimate of the XYZ colour of one spectral sample, normalized so that a flat spectrum of 1 has Y = 1.
+pub fn spectral_sample_to_xyz(radiance: Vec4, wavelengths: Vec4) -> Vec3{
+    let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+    let mut xyz = Vec3::zero();
+    for i in 0..4{
+        xyz += cie_xyz(wavelengths[i]) * radiance[i];
+    }
+    xyz * range / (4.0 * cie_integrals().y)
+}
+
+fn xyz_to_unbalanced_rgb(xyz: Vec3) -> Vec3{
+    Vec3::new(
+        3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z,
+        -0.969266 * xyz.x + 1.8760108 * xyz.y + 0.041556 * xyz.z,
+        0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z,
+    )
+}
+
+/// Converts XYZ to linear RGB. The result is white balanced so that a flat spectrum ends up as RGB (1, 1, 1), the
+/// same colour it was upsampled from.
+pub fn xyz_to_rgb(xyz: Vec3) -> Vec3{
+    let white = xyz_to_unbalanced_rgb(cie_integrals() / cie_integrals().y);
+    xyz_to_unbalanced_rgb(xyz) / white
+}
@@ -1,15 +1,20 @@
-use ultraviolet::Vec3;
+use ultraviolet::{Vec3, Vec2};
 
-use crate::{hittable::Hittable, ray::Ray, hit_result::HitResult, material::Material};
+use crate::{hittable::Hittable, ray::Ray, hit_result::HitResult, material::Material, render_statistics, math_utils::is_finite, validation::{Diagnostic, SourceLocation}};
 
 pub struct Sphere{
     pub center: Vec3,
     pub radius: f32,
     pub material: Material,
+    pub object_id: u32,
+    pub material_id: u32,
+    /// Where the sphere was defined, for diagnostics.
+    pub location: Option<SourceLocation>,
 }
 
 impl Hittable for Sphere{
-    fn hit(&self, ray: Ray, hit: &mut HitResult, min_distance: f32) -> bool{
+    fn hit<'a>(&'a self, ray: Ray, hit: &mut HitResult<'a>, min_distance: f32) -> bool{
+        render_statistics::count_primitive_test();
         let oc = ray.origin - self.center;
         let a = ray.direction.mag_sq();
         let half_b =  oc.dot(ray.direction);
@@ -33,8 +38,21 @@ impl Hittable for Sphere{
         }
 
         hit.t = t;
-        hit.material = Some(self.material);
-        hit.set_face_normal(ray.direction, (ray.at(t) - self.center) / self.radius);
+        hit.material = Some(&self.material);
+        hit.object_id = self.object_id;
+        hit.material_id = self.material_id;
+        let outward_normal = (ray.at(t) - self.center) / self.radius;
+        hit.set_face_normal(ray.direction, outward_normal);
+
+        // spherical coordinates with the seam at -x and v = 0 at the bottom
+        let theta = (-outward_normal.y).clamp(-1.0, 1.0).acos();
+        let phi = (-outward_normal.z).atan2(outward_normal.x) + std::f32::consts::PI;
+        hit.uv = Vec2::new(phi / (2.0 * std::f32::consts::PI), theta / std::f32::consts::PI);
+
+        // derivative of the position along u; degenerate at the poles
+        let tangent = Vec3::new(outward_normal.z, 0.0, -outward_normal.x);
+        hit.tangent = if tangent.mag_sq() > 0.0 {tangent.normalized()} else {Vec3::unit_x()};
+        hit.bitangent = outward_normal.cross(hit.tangent);
 
         return true;
     }
@@ -44,5 +62,14 @@ impl Hittable for Sphere{
     fn get_max_bounds(&self) -> Vec3 {
         return self.center + Vec3::new(self.radius, self.radius, self.radius) + Vec3::one() * 1e-5;
     }
+    fn validate(&self, diagnostics: &mut Vec<Diagnostic>) {
+        if !(self.radius > 0.0 && self.radius.is_finite()){
+            // a negative radius also turns the bounds inside out, so the BVH misses the sphere
+            diagnostics.push(Diagnostic::error(self.location.as_ref(), format!("The sphere has a radius of {}, it has to be positive.", self.radius)));
+        }
+        if !is_finite(self.center){
+            diagnostics.push(Diagnostic::error(self.location.as_ref(), format!("The sphere has an invalid position {:?}.", self.center)));
+        }
+    }
 }   
 
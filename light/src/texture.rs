@@ -0,0 +1,168 @@
+use std::sync::Arc;
+
+use ultraviolet::{Vec2, Vec3};
+
+use crate::{image::Image, math_utils::lerp, color_space::{ColorSpace, convert, srgb_to_linear}, error::LightError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WrapMode{
+    Repeat,
+    MirroredRepeat,
+    Clamp,
+}
+
+#[derive(Clone, Debug)]
+pub enum Texture{
+    ConstantTexture{color: Vec3},
+    ImageTexture{image: Arc<Image>, wrap_mode: WrapMode},
+    CheckerTexture{even: Vec3, odd: Vec3, scale: f32},
+    NoiseTexture{low: Vec3, high: Vec3, scale: f32},
+}
+
+impl Texture{
+    pub fn constant(color: Vec3) -> Texture{
+        Texture::ConstantTexture { color }
+    }
+
+    /// Loads an image texture and converts it from `color_space` to the `working_space`. sRGB encoded colour data gets
+    /// linearized, data like normal maps has to be loaded as is, with both colour spaces being the same.
+    pub fn from_file(filename: &str, wrap_mode: WrapMode, is_srgb_encoded: bool, color_space: ColorSpace, working_space: ColorSpace) -> Result<Texture, LightError>{
+        let mut image = Image::load_from_file(filename)?;
+        if is_srgb_encoded{
+            image.apply_filter(srgb_to_linear);
+        }
+        if color_space != working_space{
+            image.apply_filter(|x| convert(x, color_space, working_space));
+        }
+        Ok(Texture::ImageTexture { image: Arc::new(image), wrap_mode })
+    }
+
+    /// The texture with `f` applied to every colour it can return, e.g. to change its colour space. Images are copied.
+    pub fn map_colors<F>(&self, f: F) -> Texture
+    where F: Fn(Vec3) -> Vec3{
+        match self{
+            Texture::ConstantTexture { color } => Texture::ConstantTexture { color: f(*color) },
+            Texture::ImageTexture { image, wrap_mode } => {
+                let mut image = image.as_ref().clone();
+                image.apply_filter(f);
+                Texture::ImageTexture { image: Arc::new(image), wrap_mode: *wrap_mode }
+            }
+            Texture::CheckerTexture { even, odd, scale } => Texture::CheckerTexture { even: f(*even), odd: f(*odd), scale: *scale },
+            Texture::NoiseTexture { low, high, scale } => Texture::NoiseTexture { low: f(*low), high: f(*high), scale: *scale },
+        }
+    }
+
+    pub fn sample(&self, uv: Vec2, point: Vec3) -> Vec3{
+        match self{
+            Texture::ConstantTexture { color } => *color,
+            Texture::ImageTexture { image, wrap_mode } => sample_bilinear(image, uv, *wrap_mode),
+            Texture::CheckerTexture { even, odd, scale } => {
+                let parity = (uv.x * scale).floor() as i64 + (uv.y * scale).floor() as i64;
+                if parity.rem_euclid(2) == 0 {*even} else {*odd}
+            }
+            Texture::NoiseTexture { low, high, scale } => {
+                lerp(turbulence(point * *scale, 5).clamp(0.0, 1.0), *low, *high)
+            }
+        }
+    }
+
+    /// Offset in UV space used for finite differences, about one texel for image textures.
+    pub fn derivative_step(&self) -> Vec2{
+        match self{
+            Texture::ImageTexture { image, .. } => Vec2::new(1.0 / image.width() as f32, 1.0 / image.height() as f32),
+            _ => Vec2::new(1e-3, 1e-3),
+        }
+    }
+
+    /// Samples the texture as a single channel, e.g. for roughness.
+    pub fn sample_scalar(&self, uv: Vec2, point: Vec3) -> f32{
+        self.sample(uv, point).x
+    }
+}
+
+fn wrap_coordinate(coordinate: i64, size: u32, wrap_mode: WrapMode) -> u32{
+    let size = size as i64;
+    let wrapped = match wrap_mode{
+        WrapMode::Repeat => coordinate.rem_euclid(size),
+        WrapMode::MirroredRepeat => {
+            let period = coordinate.rem_euclid(2 * size);
+            if period < size {period} else {2 * size - 1 - period}
+        }
+        WrapMode::Clamp => coordinate.clamp(0, size - 1),
+    };
+    wrapped as u32
+}
+
+fn sample_bilinear(image: &Image, uv: Vec2, wrap_mode: WrapMode) -> Vec3{
+    // pixel centers sit at half-integer coordinates
+    let x = uv.x * image.width() as f32 - 0.5;
+    let y = uv.y * image.height() as f32 - 0.5;
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let tx = x - x0;
+    let ty = y - y0;
+
+    let pixel = |dx: i64, dy: i64| {
+        image[(
+            wrap_coordinate(x0 as i64 + dx, image.width(), wrap_mode),
+            wrap_coordinate(y0 as i64 + dy, image.height(), wrap_mode),
+        )]
+    };
+
+    let bottom = lerp(tx, pixel(0, 0), pixel(1, 0));
+    let top = lerp(tx, pixel(0, 1), pixel(1, 1));
+    lerp(ty, bottom, top)
+}
+
+fn hash(x: i32, y: i32, z: i32) -> u32{
+    let mut h = (x as u32).wrapping_mul(0x8da6b343) ^ (y as u32).wrapping_mul(0xd8163841) ^ (z as u32).wrapping_mul(0xcb1ab31f);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846ca68b);
+    h ^ (h >> 16)
+}
+
+fn gradient(x: i32, y: i32, z: i32) -> Vec3{
+    // the 12 edge directions of a cube, as in improved Perlin noise
+    const GRADIENTS: [Vec3; 12] = [
+        Vec3::new(1.0, 1.0, 0.0), Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0),
+        Vec3::new(1.0, 0.0, 1.0), Vec3::new(-1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, -1.0), Vec3::new(-1.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.0, -1.0, 1.0), Vec3::new(0.0, 1.0, -1.0), Vec3::new(0.0, -1.0, -1.0),
+    ];
+    GRADIENTS[(hash(x, y, z) % 12) as usize]
+}
+
+/// Gradient noise in the range [-1, 1].
+pub fn perlin_noise(point: Vec3) -> f32{
+    let cell = Vec3::new(point.x.floor(), point.y.floor(), point.z.floor());
+    let local = point - cell;
+    let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let weights = Vec3::new(fade(local.x), fade(local.y), fade(local.z));
+
+    let mut corners = [0.0f32; 8];
+    for (i, corner) in corners.iter_mut().enumerate(){
+        let offset = Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32);
+        let g = gradient(cell.x as i32 + (i & 1) as i32, cell.y as i32 + ((i >> 1) & 1) as i32, cell.z as i32 + ((i >> 2) & 1) as i32);
+        *corner = g.dot(local - offset);
+    }
+
+    let x00 = lerp(weights.x, corners[0], corners[1]);
+    let x10 = lerp(weights.x, corners[2], corners[3]);
+    let x01 = lerp(weights.x, corners[4], corners[5]);
+    let x11 = lerp(weights.x, corners[6], corners[7]);
+    lerp(weights.z, lerp(weights.y, x00, x10), lerp(weights.y, x01, x11))
+}
+
+/// Sum of several octaves of absolute noise, roughly in the range [0, 1].
+pub fn turbulence(point: Vec3, octaves: u32) -> f32{
+    let mut sum = 0.0;
+    let mut weight = 1.0;
+    let mut p = point;
+    for _ in 0..octaves{
+        sum += weight * perlin_noise(p).abs();
+        weight *= 0.5;
+        p *= 2.0;
+    }
+    sum
+}
@@ -1,6 +1,6 @@
-use ultraviolet::Vec3;
+use ultraviolet::{Vec3, Vec4};
 
-use crate::{ray::Ray, hittable::Hittable, hit_result::HitResult, material::Material, random::{random_on_unit_sphere, random_in_unit_sphere}, math_utils::lerp};
+use crate::{ray::Ray, hittable::Hittable, hit_result::HitResult, material::Material, random::{random_on_unit_sphere, random_in_unit_sphere}, math_utils::lerp, scene::Scene, medium::{MediumInteraction, MediumStack}, spectrum::{Spectrum, sample_wavelengths, spectral_sample_to_xyz}, sampler::Sampler, render_statistics};
 
 fn sample_background_gradient(ray: Ray) -> Vec3{
     let t: f32 = 0.5*(ray.direction.y + 1.0);
@@ -14,6 +14,12 @@ fn refract(entering_vector: Vec3, normal: Vec3, ior_quotient: f32) -> Vec3{
     return r_out_perp + r_out_parallel;
 }
 
+/// Moves the origin of a ray leaving a surface slightly to the side it leaves to. Otherwise rounding errors can put
+/// the origin behind the surface, and grazing rays hit it again from the wrong side.
+fn offset_origin(point: Vec3, normal: Vec3, direction: Vec3) -> Vec3{
+    if direction.dot(normal) >= 0.0 {point + normal * 1e-4} else {point - normal * 1e-4}
+}
+
 fn schlick_reflectance(cos_theta: f32, ior_current: f32, ior_new: f32) -> f32 {
     // Use Schlick's approximation for reflectance.
     let mut r0 = (ior_current - ior_new) / (ior_current + ior_new);
@@ -21,70 +27,203 @@ fn schlick_reflectance(cos_theta: f32, ior_current: f32, ior_new: f32) -> f32 {
     return r0 + (1.0 - r0) * (1.0 - cos_theta).powi(5);
 }
 
-pub fn trace_ray(ray: Ray, scene: &Box<dyn Hittable>, depth: i32) -> Vec3{
-    if depth == 0{
-        return Vec3::new(0.0, 0.0, 0.0);
+pub fn trace_ray(ray: Ray, scene: &Scene, sampler: &mut dyn Sampler, depth: i32) -> Vec3{
+    // the camera is assumed to be outside of all objects
+    trace_path::<Vec3>(ray, scene, &MediumStack::new(), &(), sampler, depth)
+}
+
+/// Traces the radiance of four wavelengths (in nm) at once. The first one is the hero wavelength.
+pub fn trace_ray_spectral(ray: Ray, scene: &Scene, wavelengths: Vec4, sampler: &mut dyn Sampler, depth: i32) -> Vec4{
+    trace_path::<Vec4>(ray, scene, &MediumStack::new(), &wavelengths, sampler, depth)
+}
+
+/// Traces a camera ray in the mode selected by the render settings.
+/// Returns linear RGB, or XYZ when rendering spectrally (see `image_filters::xyz_to_rgb`).
+pub fn trace_camera_ray(ray: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Vec3{
+    trace_camera_ray_to_depth(ray, scene, sampler, scene.settings.max_depth)
+}
+
+/// Like `trace_camera_ray`, but with a different maximum path depth.
+pub fn trace_camera_ray_to_depth(ray: Ray, scene: &Scene, sampler: &mut dyn Sampler, max_depth: i32) -> Vec3{
+    render_statistics::start_path();
+    let radiance = if scene.settings.spectral{
+        let wavelengths = sample_wavelengths(sampler.next_1d());
+        spectral_sample_to_xyz(trace_ray_spectral(ray, scene, wavelengths, sampler, max_depth), wavelengths)
     }
+    else{
+        trace_ray(ray, scene, sampler, max_depth)
+    };
+    render_statistics::end_path();
+    radiance
+}
+
+/// What a camera ray sees at the first surface it hits.
+#[derive(Clone, Copy, Debug)]
+pub struct FirstHit{
+    /// White for emitters and the background.
+    pub albedo: Vec3,
+    /// Shading normal, zero for emitters and the background.
+    pub normal: Vec3,
+    /// Distance along the ray, zero for the background.
+    pub depth: f32,
+    pub position: Vec3,
+    /// 0 for the background.
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+pub fn trace_first_hit(ray: Ray, scene: &Scene) -> FirstHit{
     let mut hit: HitResult = HitResult::default();
-    
-    scene.hit(ray, &mut hit, 1e-4);
-
-    match hit.material {
-        Some(mat) => {
-            match mat {
-                Material::NormalMaterial() => hit.normal*0.5 + Vec3::new(0.5, 0.5, 0.5),
-                Material::DiffuseMaterial { albedo } => {
-                    let target = hit.normal + random_on_unit_sphere();
-                    let new_ray: Ray = Ray{
-                        origin: ray.at(hit.t),
-                        direction: target.normalized(),
+    let background = FirstHit { albedo: Vec3::one(), normal: Vec3::zero(), depth: 0.0, position: Vec3::zero(), object_id: 0, material_id: 0 };
+    if !scene.hit(ray, &mut hit, 1e-4){
+        return background;
+    }
+    let material = match hit.material{
+        Some(material) => material,
+        None => return background,
+    };
+    let hit_point = ray.at(hit.t);
+    if let Some(detail) = material.surface_detail(){
+        detail.apply(&mut hit, hit_point);
+    }
+    let (albedo, normal) = match material{
+        Material::NormalMaterial() => (hit.normal*0.5 + Vec3::new(0.5, 0.5, 0.5), hit.normal),
+        Material::DiffuseMaterial { albedo, .. } | Material::MetallicMaterial { albedo, .. } | Material::DielectricMaterial { albedo, .. } => (albedo.sample(hit.uv, hit_point), hit.normal),
+        Material::EmissiveMaterial { .. } => (Vec3::one(), Vec3::zero()),
+        Material::VolumeMaterial { .. } => (Vec3::one(), hit.normal),
+    };
+    FirstHit {
+        albedo,
+        normal,
+        depth: hit.t * ray.direction.mag(),
+        position: hit_point,
+        object_id: hit.object_id,
+        material_id: hit.material_id,
+    }
+}
+
+/// Traces a ray that starts inside of the objects on `stack`. Surfaces that only switch the medium are crossed in a
+/// loop without using up `depth`, so the call stack doesn't grow with the number of crossed surfaces.
+fn trace_path<'a, S: Spectrum>(mut ray: Ray, scene: &'a Scene, stack: &MediumStack<'a>, wavelengths: &S::Wavelengths, sampler: &mut dyn Sampler, depth: i32) -> S{
+    if depth == 0{
+        return S::splat(0.0);
+    }
+    let mut stack = stack.clone();
+    let mut throughput = S::splat(1.0);
+    loop{
+        render_statistics::count_path_segment();
+        let mut hit: HitResult = HitResult::default();
+        
+        scene.hit(ray, &mut hit, 1e-4);
+
+        if let Some(medium) = stack.medium(scene.fog.as_ref()){
+            match medium.sample_interaction::<S>(hit.t, sampler.next_1d(), sampler.next_1d(), wavelengths){
+                MediumInteraction::Scattered { distance, weight } => {
+                    let new_ray = Ray{
+                        origin: ray.at(distance),
+                        direction: medium.sample_phase(ray.direction, sampler.next_1d(), sampler.next_1d()),
                     };
-                    return albedo * trace_ray(new_ray, scene, depth-1)
+                    return throughput * weight * trace_path::<S>(new_ray, scene, &stack, wavelengths, sampler, depth-1);
                 }
-                Material::MetallicMaterial { albedo, roughness } => {
-                    let mut direction = ray.direction.reflected(hit.normal) + roughness * random_in_unit_sphere(); 
-                    if direction.dot(hit.normal) <= 0.0{
-                        // the ray got reflected back into the object
-                        return Vec3::new(0.0, 0.0, 0.0);
-                    }
-                    direction.normalize();
-                    let new_ray: Ray = Ray{
-                        origin: ray.at(hit.t),
-                        direction,
-                    };
-                    return albedo * trace_ray(new_ray, scene, depth-1)
+                MediumInteraction::Passed { weight } => {
+                    throughput = throughput * weight;
                 }
-                Material::DielectricMaterial { albedo, ior } => {
-                    // assume the other material is always air
-                    let ior_air = 1.0;
-
-                    let ior_current = if hit.is_front_face {ior_air} else {ior};
-                    let ior_new = if hit.is_front_face {ior} else {ior_air};
-                    let ior_quotient = ior_current/ior_new;
-                    
-                    let cos_theta = hit.normal.dot(-ray.direction).min(1.0);
-                    let sin_theta = (1.0 - cos_theta*cos_theta).sqrt();
-
-                    let cannot_refract = ior_quotient * sin_theta > 1.0;
-                    let direction = if cannot_refract || schlick_reflectance(cos_theta, ior_current, ior_new) > rand::random::<f32>(){
-                        ray.direction.reflected(hit.normal) 
-                    }
-                    else{
-                        refract(ray.direction, hit.normal, ior_quotient)
-                    };
+            }
+        }
 
-                    let new_ray: Ray = Ray{
-                        origin: ray.at(hit.t),
-                        direction,
+        return throughput * match hit.material {
+            Some(mat) => {
+                let hit_point = ray.at(hit.t);
+                if mat.priority().is_some() && !stack.is_real_interface(mat, hit.is_front_face){
+                    // the surface lies inside of an object with higher priority, so it doesn't exist for this path
+                    stack = if hit.is_front_face {stack.entered(mat)} else {stack.left(mat)};
+                    ray = Ray{
+                        origin: hit_point,
+                        direction: ray.direction,
                     };
-                    return albedo * trace_ray(new_ray, scene, depth-1);
+                    continue;
                 }
-                Material::EmissiveMaterial { emission_color, strength } => {
-                    return emission_color * strength;
+                if let Some(detail) = mat.surface_detail(){
+                    detail.apply(&mut hit, hit_point);
+                }
+                match mat {
+                    Material::NormalMaterial() => S::from_rgb(hit.normal*0.5 + Vec3::new(0.5, 0.5, 0.5), wavelengths),
+                    Material::DiffuseMaterial { albedo, .. } => {
+                        let albedo = S::from_rgb(albedo.sample(hit.uv, hit_point), wavelengths);
+                        let target = hit.normal + random_on_unit_sphere(sampler);
+                        // the sample can cancel out the normal, which would give a NaN direction
+                        let direction = if target.mag_sq() < 1e-8 {hit.normal} else {target.normalized()};
+                        let new_ray: Ray = Ray{
+                            origin: offset_origin(hit_point, hit.normal, direction),
+                            direction,
+                        };
+                        albedo * trace_path::<S>(new_ray, scene, &stack, wavelengths, sampler, depth-1)
+                    }
+                    Material::MetallicMaterial { albedo, roughness, .. } => {
+                        let albedo = S::from_rgb(albedo.sample(hit.uv, hit_point), wavelengths);
+                        let roughness = roughness.sample_scalar(hit.uv, hit_point);
+                        let mut direction = ray.direction.reflected(hit.normal) + roughness * random_in_unit_sphere(sampler);
+                        let below_surface = direction.dot(hit.normal);
+                        if below_surface <= 0.0{
+                            // mirror directions that point into the object back out instead of dropping their energy
+                            direction -= (2.0 * below_surface - 1e-4) * hit.normal;
+                        }
+                        direction.normalize();
+                        let new_ray: Ray = Ray{
+                            origin: offset_origin(hit_point, hit.normal, direction),
+                            direction,
+                        };
+                        albedo * trace_path::<S>(new_ray, scene, &stack, wavelengths, sampler, depth-1)
+                    }
+                    Material::DielectricMaterial { albedo, .. } => {
+                        let albedo = S::from_rgb(albedo.sample(hit.uv, hit_point), wavelengths);
+                        // the path follows the hero wavelength through dispersive interfaces
+                        let hero_wavelength = S::hero_wavelength(wavelengths);
+                        let ior = mat.ior(hero_wavelength).unwrap();
+                        let (ior_current, ior_new, stack_behind_surface) = if hit.is_front_face{
+                            (stack.ior(hero_wavelength), ior, stack.entered(mat))
+                        }
+                        else{
+                            let stack_behind_surface = stack.left(mat);
+                            (ior, stack_behind_surface.ior(hero_wavelength), stack_behind_surface)
+                        };
+                        let is_dispersive = hero_wavelength.is_some() && (mat.is_dispersive() || stack.is_dispersive() || stack_behind_surface.is_dispersive());
+                        let ior_quotient = ior_current/ior_new;
+                        
+                        let cos_theta = hit.normal.dot(-ray.direction).min(1.0);
+                        let sin_theta = (1.0 - cos_theta*cos_theta).sqrt();
+
+                        let cannot_refract = ior_quotient * sin_theta > 1.0;
+                        let (direction, new_stack) = if cannot_refract || schlick_reflectance(cos_theta, ior_current, ior_new) > sampler.next_1d(){
+                            (ray.direction.reflected(hit.normal), stack.clone())
+                        }
+                        else{
+                            (refract(ray.direction, hit.normal, ior_quotient), stack_behind_surface)
+                        };
+
+                        let new_ray: Ray = Ray{
+                            origin: offset_origin(hit_point, hit.normal, direction),
+                            direction,
+                        };
+                        let radiance: S = albedo * trace_path::<S>(new_ray, scene, &new_stack, wavelengths, sampler, depth-1);
+                        if is_dispersive {radiance.only_hero()} else {radiance}
+                    }
+                    Material::EmissiveMaterial { emission_color, strength } => {
+                        S::from_rgb(emission_color.sample(hit.uv, hit_point) * *strength, wavelengths)
+                    }
+                    Material::VolumeMaterial { .. } => {
+                        // continue straight through the boundary and only switch the medium
+                        stack = if hit.is_front_face {stack.entered(mat)} else {stack.left(mat)};
+                        ray = Ray{
+                            origin: hit_point,
+                            direction: ray.direction,
+                        };
+                        continue;
+                    }
                 }
-            }
 
-        },
-        None => return 0.0*sample_background_gradient(ray),
+            },
+            None => S::from_rgb(0.0*sample_background_gradient(ray), wavelengths),
+        };
     }
 }
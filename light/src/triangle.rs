@@ -1,24 +1,31 @@
-use ultraviolet::{Vec3, Vec2};
+use ultraviolet::{Vec3, Vec2, Vec4};
 
-use crate::{hittable::Hittable, ray::Ray, hit_result::HitResult};
+use crate::{hittable::Hittable, ray::Ray, hit_result::HitResult, render_statistics};
 
 #[derive(Default, Clone, Copy)]
 pub struct Triangle{
     pub vertices: [Vec3; 3], 
     pub normals: [Vec3; 3],
     pub uv_coordinates: [Vec2; 3],
+    /// Per vertex tangents. `w` holds the handedness of the bitangent.
+    pub tangents: [Vec4; 3],
 }
 
 impl Hittable for Triangle{
     // https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm
-    fn hit(&self, ray: Ray, hit: &mut HitResult, min_distance: f32) -> bool{
+    fn hit<'a>(&'a self, ray: Ray, hit: &mut HitResult<'a>, min_distance: f32) -> bool{
         const EPSILON: f32 = 1e-8;
+        // barycentric coordinates are rounded independently for each triangle, so rays through a shared edge could
+        // miss both without a bit of overlap
+        const EDGE_TOLERANCE: f32 = 1e-5;
+        render_statistics::count_primitive_test();
         let edge1 = self.vertices[1] - self.vertices[2];
         let edge2 = self.vertices[0] - self.vertices[2];
         let h = ray.direction.cross(edge2);
         let a = edge1.dot(h);
 
-        if a > -EPSILON && a < EPSILON{
+        // relative to the lengths, otherwise small triangles look parallel to every ray
+        if a * a <= EPSILON * EPSILON * edge1.mag_sq() * edge2.mag_sq() * ray.direction.mag_sq(){
             return false;    // This ray is parallel to this triangle.
         }
 
@@ -26,14 +33,14 @@ impl Hittable for Triangle{
         let s = ray.origin - self.vertices[2];
         let u = f * s.dot(h);
 
-        if u < 0.0 || u > 1.0{
+        if !(-EDGE_TOLERANCE..=1.0 + EDGE_TOLERANCE).contains(&u){
             return false;
         }
 
         let q = s.cross(edge1);
         let v = f * ray.direction.dot(q);
 
-        if v < 0.0 || u + v > 1.0{
+        if !(-EDGE_TOLERANCE..=1.0 + EDGE_TOLERANCE - u).contains(&v){
             return false;
         }
 
@@ -43,18 +50,16 @@ impl Hittable for Triangle{
         if t > min_distance && t < hit.t { // ray intersection
             hit.t = t;
             
-            let hit_point = ray.at(t);
-            let face_normal = edge2.cross(edge1);
+            // u and v are the barycentric coordinates of the second and first vertex
+            let interpolation_vec = Vec3::new(v, u, 1.0 - u - v);
 
-            let mut interpolation_vec = Vec3{
-                x: face_normal.dot(edge1.cross(self.vertices[1] - hit_point)),
-                y: face_normal.dot(edge2.cross(hit_point - self.vertices[2])),
-                z: 0.0,
-            };
-            interpolation_vec /= face_normal.mag_sq();
-            interpolation_vec.z = 1.0 - interpolation_vec.x - interpolation_vec.y;
+            let outward_normal = (self.normals[0] * interpolation_vec.x + self.normals[1] * interpolation_vec.y + self.normals[2] * interpolation_vec.z).normalized();
+            hit.set_face_normal(ray.direction, outward_normal);
 
-            hit.set_face_normal(ray.direction, (self.normals[0] * interpolation_vec.x + self.normals[1] * interpolation_vec.y + self.normals[2] * interpolation_vec.z).normalized());
+            let tangent = (self.tangents[0] * interpolation_vec.x + self.tangents[1] * interpolation_vec.y + self.tangents[2] * interpolation_vec.z).truncated();
+            hit.tangent = (tangent - outward_normal * outward_normal.dot(tangent)).normalized();
+            hit.bitangent = self.tangents[0].w * outward_normal.cross(hit.tangent);
+            hit.uv = self.uv_coordinates[0] * interpolation_vec.x + self.uv_coordinates[1] * interpolation_vec.y + self.uv_coordinates[2] * interpolation_vec.z;
             return true;
         }
         else{ // This means that there is a line intersection but not a ray intersection.
@@ -0,0 +1,53 @@
+use std::fmt;
+
+/// How bad a problem found by `Scene::validate` is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity{
+    /// The scene renders, but probably not as intended.
+    Warning,
+    /// The scene can't be rendered correctly.
+    Error,
+}
+
+/// The line of a scene or OBJ file something was defined on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation{
+    pub filename: String,
+    pub line: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic{
+    pub severity: Severity,
+    pub location: Option<SourceLocation>,
+    pub message: String,
+}
+
+impl SourceLocation{
+    pub fn new(filename: &str, line: usize) -> SourceLocation{
+        SourceLocation { filename: filename.to_owned(), line }
+    }
+}
+
+impl Diagnostic{
+    pub fn error(location: Option<&SourceLocation>, message: impl Into<String>) -> Diagnostic{
+        Diagnostic { severity: Severity::Error, location: location.cloned(), message: message.into() }
+    }
+
+    pub fn warning(location: Option<&SourceLocation>, message: impl Into<String>) -> Diagnostic{
+        Diagnostic { severity: Severity::Warning, location: location.cloned(), message: message.into() }
+    }
+}
+
+impl fmt::Display for Diagnostic{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        if let Some(location) = &self.location{
+            write!(f, "{}:{}  ", location.filename, location.line)?;
+        }
+        let severity = match self.severity{
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}", severity, self.message)
+    }
+}
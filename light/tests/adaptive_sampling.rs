@@ -0,0 +1,121 @@
+//! Per pixel statistics and the convergence test of adaptive sampling.
+
+use light::adaptive_sampling::{PixelStatistics, SampleStatistics};
+use ultraviolet::Vec3;
+
+fn assert_close(a: f32, b: f32, tolerance: f32){
+    assert!((a - b).abs() <= tolerance * b.abs().max(1.0), "{} != {}", a, b);
+}
+
+/// Adds the same alternating samples to every pixel of `statistics`: `count` samples of `mean ± spread`.
+fn add_noisy_samples(statistics: &mut SampleStatistics, x: u32, y: u32, count: u32, mean: f32, spread: f32){
+    for i in 0..count{
+        let sign = if i % 2 == 0 {1.0} else {-1.0};
+        statistics.add_sample(x, y, Vec3::broadcast(mean + sign * spread));
+    }
+}
+
+/// Two pass mean and sample variance.
+fn mean_and_variance(values: &[f32]) -> (f32, f32){
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / (values.len() - 1) as f32;
+    (mean, variance)
+}
+
+#[test]
+fn welford_variance(){
+    let samples = [
+        Vec3::new(1.0, 2.0, 0.5),
+        Vec3::new(2.0, 4.0, 0.5),
+        Vec3::new(4.0, 8.0, 0.5),
+        Vec3::new(7.0, 14.0, 0.5),
+        Vec3::new(11.0, 22.0, 3.0),
+    ];
+    let mut pixel = PixelStatistics::default();
+    assert_eq!(pixel.error(), f32::INFINITY);
+    pixel.add_sample(samples[0]);
+    assert_eq!(pixel.error(), f32::INFINITY);
+    for sample in &samples[1..]{
+        pixel.add_sample(*sample);
+    }
+    assert_eq!(pixel.sample_count, 5);
+
+    let mut standard_error = 0.0;
+    let mut brightness = 0.0;
+    for channel in 0..3{
+        let (mean, variance) = mean_and_variance(&samples.map(|x| x[channel]));
+        assert_close(pixel.mean[channel], mean, 1e-6);
+        standard_error += variance.sqrt() / 5.0f32.sqrt() / 3.0;
+        brightness += mean / 3.0;
+    }
+    assert_close(pixel.error(), standard_error / brightness.sqrt(), 1e-5);
+
+    // a large offset doesn't cancel the variance
+    let mut offset = PixelStatistics::default();
+    for sample in samples{
+        offset.add_sample(Vec3::broadcast(1e4 + sample.x));
+    }
+    let (mean, variance) = mean_and_variance(&samples.map(|x| x.x));
+    assert_close(offset.error() * (1e4 + mean).sqrt(), variance.sqrt() / 5.0f32.sqrt(), 1e-3);
+
+    // constant samples have no error
+    let mut constant = PixelStatistics::default();
+    for _ in 0..10{
+        constant.add_sample(Vec3::broadcast(0.3));
+    }
+    assert_eq!(constant.error(), 0.0);
+}
+
+#[test]
+fn update_convergence_respects_min_samples(){
+    let mut statistics = SampleStatistics::new(5, 3);
+    for y in 0..3{
+        for x in 0..5{
+            add_noisy_samples(&mut statistics, x, y, 4, 1.0, 0.0);
+        }
+    }
+    // no error, but not enough samples yet
+    statistics.update_convergence(0.01, 8);
+    assert_eq!(statistics.converged_fraction(), 0.0);
+    statistics.update_convergence(0.01, 4);
+    assert_eq!(statistics.converged_fraction(), 1.0);
+
+    // a noisy pixel keeps its neighbours from converging
+    add_noisy_samples(&mut statistics, 0, 0, 4, 1.0, 0.5);
+    statistics.update_convergence(0.01, 4);
+    for y in 0..3{
+        for x in 0..5{
+            assert_eq!(statistics.is_converged(x, y), x > 1 || y > 1, "({}, {})", x, y);
+        }
+    }
+    assert_close(statistics.converged_fraction(), 11.0 / 15.0, 1e-6);
+
+    // pixels that were converged become unconverged again if they get new, noisy samples
+    add_noisy_samples(&mut statistics, 4, 2, 2, 1.0, 1.0);
+    statistics.update_convergence(0.01, 4);
+    assert!(!statistics.is_converged(3, 1));
+    assert!(statistics.is_converged(2, 1));
+}
+
+#[test]
+fn noise_image(){
+    let mut statistics = SampleStatistics::new(3, 1);
+    add_noisy_samples(&mut statistics, 0, 0, 16, 4.0, 0.0);
+    add_noisy_samples(&mut statistics, 1, 0, 16, 4.0, 1.0);
+    statistics.add_sample(2, 0, Vec3::one());
+
+    let threshold = 0.05;
+    let noise = statistics.noise_image(threshold);
+    assert_eq!((noise.width(), noise.height()), (3, 1));
+    assert_eq!(noise[(0, 0)], Vec3::zero());
+    let error = statistics.pixel(1, 0).error();
+    assert!(error > 0.0);
+    assert_close(noise[(1, 0)].x, error / threshold, 1e-6);
+    // pixels with too few samples for an error estimate are bright, but finite
+    assert!(noise[(2, 0)].x.is_finite() && noise[(2, 0)].x > 1.0);
+
+    let counts = statistics.sample_count_image(16);
+    assert_eq!(counts[(0, 0)], Vec3::one());
+    assert_eq!(counts[(2, 0)], Vec3::broadcast(1.0 / 16.0));
+    assert_eq!(statistics.mean_image()[(1, 0)], Vec3::broadcast(4.0));
+}
@@ -0,0 +1,64 @@
+//! The content of the AOVs rendered alongside the image.
+
+use std::sync::Mutex;
+
+use light::{aov::Aov, film::{Filter, FilterType}, image::Image, importing::parse_scene, render::{render_pass, RenderBuffers}};
+use ultraviolet::Vec3;
+
+const SIZE: u32 = 17;
+const SAMPLES: u32 = 8;
+/// A diffuse unit sphere at the origin seen from 5 units away, lit by a large emitter behind the camera.
+const SCENE: &str = "[camera]\nwidth = 17\nheight = 17\nposition = 0;0;5\ntarget = 0;0;0\nfov = 40\n\
+    [sphere]\nradius = 1\npos = 0;0;0\nmaterial_type = diffuse_material\nalbedo = 0.8;0.4;0.2\n\
+    [sphere]\nradius = 3\npos = 0;0;10\nmaterial_type = emissive_material\nemission_color = 1;1;1\nstrength = 4\n";
+const AOVS: [Aov; 9] = [Aov::Albedo, Aov::Normal, Aov::Depth, Aov::Position, Aov::ObjectId, Aov::MaterialId, Aov::Direct, Aov::Indirect, Aov::SampleCount];
+
+fn assert_close(a: Vec3, b: Vec3, tolerance: f32){
+    assert!((a - b).mag() < tolerance, "{:?} != {:?}", a, b);
+}
+
+#[test]
+fn aov_content(){
+    let mut scene = parse_scene(SCENE, "aovs.toml").ok().unwrap();
+    scene.settings.aovs = AOVS.to_vec();
+    // every sample stays in its pixel, so the image is the plain mean like the AOVs
+    scene.settings.filter = Filter::new(FilterType::Box, 0.5);
+    let buffers = Mutex::new(RenderBuffers::new(&scene, false));
+    for sample in 0..SAMPLES{
+        render_pass(&scene, sample, &buffers);
+    }
+    let buffers = buffers.into_inner().unwrap();
+    let aov = |aov: Aov| -> Image { buffers.aovs.image(aov, &scene.settings).unwrap() };
+
+    // the center of the sphere faces the camera
+    let center = (SIZE / 2, SIZE / 2);
+    assert_close(aov(Aov::Albedo)[center], Vec3::new(0.8, 0.4, 0.2), 1e-5);
+    assert_close(aov(Aov::Normal)[center], Vec3::unit_z(), 0.05);
+    assert!((aov(Aov::Depth)[center].x - 4.0).abs() < 0.02, "{:?}", aov(Aov::Depth)[center]);
+    assert_close(aov(Aov::Position)[center], Vec3::unit_z(), 0.2);
+    assert_eq!(aov(Aov::ObjectId)[center], Vec3::one());
+    assert_eq!(aov(Aov::MaterialId)[center], Vec3::one());
+
+    // the corners only see the black background
+    for corner in [(0, 0), (SIZE - 1, 0), (0, SIZE - 1), (SIZE - 1, SIZE - 1)]{
+        assert_eq!(aov(Aov::Albedo)[corner], Vec3::one());
+        assert_eq!(aov(Aov::Normal)[corner], Vec3::zero());
+        assert_eq!(aov(Aov::Depth)[corner], Vec3::zero());
+        assert_eq!(aov(Aov::ObjectId)[corner], Vec3::zero());
+        assert_eq!(aov(Aov::MaterialId)[corner], Vec3::zero());
+    }
+
+    // the light splits into the direct and the indirect part
+    let image = buffers.film.image();
+    let (direct, indirect) = (aov(Aov::Direct), aov(Aov::Indirect));
+    let mut direct_sum = Vec3::zero();
+    for y in 0..SIZE{
+        for x in 0..SIZE{
+            assert_close(direct[(x, y)] + indirect[(x, y)], image[(x, y)], 1e-4);
+            assert!(direct[(x, y)].component_min() >= 0.0);
+            direct_sum += direct[(x, y)];
+            assert_eq!(aov(Aov::SampleCount)[(x, y)], Vec3::broadcast(SAMPLES as f32));
+        }
+    }
+    assert!(direct_sum.component_min() > 0.0);
+}
@@ -0,0 +1,202 @@
+//! Camera orientation, projections, lens shift and depth of field.
+
+use light::{importing::parse_scene, random::random_in_regular_polygon, ray::Ray, sampler::{IndependentSampler, Sampler}, scene::Scene};
+use ultraviolet::{Vec2, Vec3};
+
+const SPHERE: &str = "[sphere]\nradius = 1\npos = 0;0;-5\nmaterial_type = diffuse_material\n";
+
+fn scene(camera: &str) -> Scene{
+    let content = format!("{}[camera]\nwidth = 16\nheight = 8\nfov = 40\n{}", SPHERE, camera);
+    match parse_scene(&content, "camera.toml"){
+        Ok(scene) => scene,
+        Err(error) => panic!("{}", error),
+    }
+}
+
+fn ray(scene: &Scene, x: f32, y: f32) -> Ray{
+    scene.camera.get_ray(x, y, &mut IndependentSampler::new(0)).unwrap()
+}
+
+fn assert_close(a: Vec3, b: Vec3){
+    assert!((a - b).mag() < 1e-4, "{:?} != {:?}", a, b);
+}
+
+#[test]
+fn roll_and_up_vector(){
+    let level = scene("position = 0;0;0\ntarget = 0;0;-1\n");
+    let rolled = scene("position = 0;0;0\ntarget = 0;0;-1\nroll = 90\n");
+    assert_close(ray(&rolled, 0.5, 0.5).direction, -Vec3::unit_z());
+    // the right side of the image now looks up, like the top did before, just further out
+    let right = ray(&rolled, 1.0, 0.5).direction;
+    assert!(right.y > 0.0 && right.x.abs() < 1e-5, "{:?}", right);
+    let top = ray(&rolled, 0.5, 1.0).direction;
+    assert!(top.x < 0.0 && top.y.abs() < 1e-5, "{:?}", top);
+    // the image is twice as wide as high, so a quarter width to the right is the top of the unrolled image
+    assert_close(ray(&rolled, 0.75, 0.5).direction, ray(&level, 0.5, 1.0).direction);
+
+    // looking straight down needs a different up vector
+    let down = "position = 0;5;0\ntarget = 0;0;0\n";
+    assert!(scene(down).validate().iter().any(|x| x.message.contains("'up'")));
+    let down = scene(&format!("{}up = 0;0;-1\n", down));
+    assert!(down.validate().is_empty(), "{:?}", down.validate());
+    assert_close(ray(&down, 0.5, 0.5).direction, -Vec3::unit_y());
+    assert!(ray(&down, 0.5, 1.0).direction.z < 0.0);
+}
+
+#[test]
+fn matrix_matches_look_at(){
+    let look_at = scene("position = 1;2;3\ntarget = 0;0;-5\nup = 1;1;0\n");
+    // the same camera as a camera to world matrix, with some scale that has to be ignored
+    let w = Vec3::new(1.0, 2.0, 8.0).normalized();
+    let u = Vec3::new(1.0, 1.0, 0.0).cross(w).normalized();
+    let v = w.cross(u);
+    let (u, v, w) = (u * 2.0, v * 2.0, w * 2.0);
+    let matrix = format!("matrix = {};{};{};1;{};{};{};2;{};{};{};3;0;0;0;1\n", u.x, v.x, w.x, u.y, v.y, w.y, u.z, v.z, w.z);
+    let from_matrix = scene(&matrix);
+    for (x, y) in [(0.0, 0.0), (0.5, 0.5), (1.0, 0.25)]{
+        assert_close(ray(&from_matrix, x, y).origin, ray(&look_at, x, y).origin);
+        assert_close(ray(&from_matrix, x, y).direction, ray(&look_at, x, y).direction);
+    }
+
+    let broken = parse_scene(&format!("{}[camera]\nmatrix = 1;0;0\n", SPHERE), "camera.toml");
+    assert!(broken.err().unwrap().to_string().contains("16 values"));
+}
+
+#[test]
+fn orthographic(){
+    let ortho = scene("position = 0;0;0\ntarget = 0;0;-1\nprojection = orthographic\northo_scale = 4\n");
+    assert!(ortho.validate().is_empty());
+    // parallel rays, the longer side is `ortho_scale` wide
+    for (x, y) in [(0.0, 0.0), (0.5, 0.5), (1.0, 1.0)]{
+        assert_close(ray(&ortho, x, y).direction, -Vec3::unit_z());
+    }
+    assert_close(ray(&ortho, 0.0, 0.0).origin, Vec3::new(-2.0, -1.0, 0.0));
+    assert_close(ray(&ortho, 1.0, 1.0).origin, Vec3::new(2.0, 1.0, 0.0));
+
+    let shifted = scene("position = 0;0;0\ntarget = 0;0;-1\nprojection = orthographic\northo_scale = 4\nshift_x = 0.5\nshift_y = -0.25\n");
+    assert_close(ray(&shifted, 0.5, 0.5).origin, Vec3::new(2.0, -1.0, 0.0));
+    assert_close(ray(&shifted, 0.5, 0.5).direction, -Vec3::unit_z());
+
+    let flat = scene("position = 0;0;0\ntarget = 0;0;-1\nprojection = orthographic\northo_scale = 0\n");
+    assert!(flat.validate()[0].message.contains("orthographic scale"));
+}
+
+#[test]
+fn perspective_shift(){
+    let centered = scene("position = 0;0;0\ntarget = 0;0;-1\n");
+    let shifted = scene("position = 0;0;0\ntarget = 0;0;-1\nshift_x = 0.5\n");
+    // a shift of half the image width moves the center to where the right edge was
+    assert_close(ray(&shifted, 0.5, 0.5).direction, ray(&centered, 1.0, 0.5).direction);
+    assert_close(ray(&shifted, 0.5, 0.5).origin, Vec3::zero());
+}
+
+#[test]
+fn focal_length(){
+    let camera = scene("position = 0;0;0\ntarget = 0;0;-1\nfocal_length = 50\nsensor_width = 36\n");
+    // the sensor width belongs to the longer image side
+    let right = ray(&camera, 1.0, 0.5).direction;
+    assert!((right.x / -right.z - 18.0 / 50.0).abs() < 1e-5, "{:?}", right);
+    let top = ray(&camera, 0.5, 1.0).direction;
+    assert!((top.y / -top.z - 9.0 / 50.0).abs() < 1e-5, "{:?}", top);
+}
+
+#[test]
+fn depth_of_field(){
+    // 50mm at f/2 is a lens 25mm wide
+    let camera = scene("position = 0;0;0\ntarget = 0;0;-1\nfocal_length = 50\nf_stop = 2\nfocus_distance = 3\n");
+    let mut sampler = IndependentSampler::new(3);
+    let mut max_radius: f32 = 0.0;
+    let center = ray(&camera, 0.25, 0.75);
+    let focus_point = center.at(-3.0 / center.direction.z);
+    for i in 0..1000{
+        sampler.start_sample((i, 0), 0);
+        let lens_ray = camera.camera.get_ray(0.25, 0.75, &mut sampler).unwrap();
+        max_radius = max_radius.max(lens_ray.origin.mag());
+        // every ray through a pixel meets at the focus distance
+        assert_close(lens_ray.at(-3.0 / lens_ray.direction.z), focus_point);
+    }
+    assert!(max_radius <= 0.0125 + 1e-6 && max_radius > 0.012, "{}", max_radius);
+
+    let broken = scene("position = 0;0;0\ntarget = 0;0;-1\nfocus_distance = 0\naperture_blades = 2\naperture_ratio = 0\n").validate();
+    assert_eq!(broken.len(), 3, "{:?}", broken);
+}
+
+#[test]
+fn aperture_shapes(){
+    let mut sampler = IndependentSampler::new(5);
+    let rotation = 10f32.to_radians();
+    let mut sum = Vec2::zero();
+    let mut max_distance: f32 = 0.0;
+    for i in 0..10000{
+        sampler.start_sample((i, 0), 0);
+        let point = random_in_regular_polygon(6, rotation, &mut sampler);
+        // inside of every edge, the edges are at cos(30°) from the center
+        for side in 0..6{
+            let normal_angle = rotation + (side as f32 + 0.5) * std::f32::consts::PI / 3.0;
+            assert!(point.dot(Vec2::new(normal_angle.cos(), normal_angle.sin())) <= 0.8661, "{:?}", point);
+        }
+        sum += point;
+        max_distance = max_distance.max(point.mag());
+    }
+    assert!((sum / 10000.0).mag() < 0.02, "{:?}", sum / 10000.0);
+    assert!(max_distance > 0.95);
+
+    // anamorphic bokeh is twice as high as wide
+    let camera = scene("position = 0;0;0\ntarget = 0;0;-1\naperture_size = 2\naperture_ratio = 2\naperture_blades = 4\n");
+    let mut extent = Vec2::zero();
+    for i in 0..1000{
+        sampler.start_sample((i, 0), 0);
+        let origin = camera.camera.get_ray(0.5, 0.5, &mut sampler).unwrap().origin;
+        extent = extent.max_by_component(Vec2::new(origin.x.abs(), origin.y.abs()));
+    }
+    assert!(extent.x <= 0.5 + 1e-5 && extent.x > 0.45 && extent.y > 0.9, "{:?}", extent);
+}
+
+fn direction(scene: &Scene, x: f32, y: f32) -> Option<Vec3>{
+    scene.camera.get_ray(x, y, &mut IndependentSampler::new(0)).map(|ray| ray.direction)
+}
+
+/// Angle between the view direction and `direction`, in degrees.
+fn angle_from_forward(direction: Vec3) -> f32{
+    direction.dot(-Vec3::unit_z()).clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+#[test]
+fn panoramas(){
+    let equirectangular = scene("position = 0;0;0\ntarget = 0;0;-1\nprojection = equirectangular\n");
+    for ((x, y), expected) in [((0.5, 0.5), -Vec3::unit_z()), ((0.75, 0.5), Vec3::unit_x()), ((0.0, 0.5), Vec3::unit_z()), ((0.5, 1.0), Vec3::unit_y())]{
+        assert_close(direction(&equirectangular, x, y).unwrap(), expected);
+    }
+    assert_eq!(equirectangular.validate().len(), 0);
+
+    // a 180° image circle as wide as the image
+    let equidistant = scene("position = 0;0;0\ntarget = 0;0;-1\nprojection = fisheye_equidistant\nfisheye_fov = 180\n");
+    assert_close(direction(&equidistant, 0.5, 0.5).unwrap(), -Vec3::unit_z());
+    assert_close(direction(&equidistant, 1.0, 0.5).unwrap(), Vec3::unit_x());
+    assert!((angle_from_forward(direction(&equidistant, 0.75, 0.5).unwrap()) - 45.0).abs() < 1e-3);
+    assert!((angle_from_forward(direction(&equidistant, 0.5, 1.0).unwrap()) - 45.0).abs() < 1e-3);
+    assert!(direction(&equidistant, 1.0, 1.0).is_none());
+
+    let equisolid = scene("position = 0;0;0\ntarget = 0;0;-1\nprojection = fisheye_equisolid\nfisheye_lens = 8\nfisheye_fov = 360\n");
+    let expected = 2.0 * (0.25f32 * 36.0 / 16.0).asin().to_degrees();
+    assert!((angle_from_forward(direction(&equisolid, 0.75, 0.5).unwrap()) - expected).abs() < 1e-3);
+    // beyond what the lens can project
+    assert!(direction(&equisolid, 1.0, 1.0).is_none());
+
+    let cube_map = scene("position = 0;0;0\ntarget = 0;0;-1\nprojection = cube_map\n");
+    let faces = [Vec3::unit_x(), -Vec3::unit_x(), Vec3::unit_y(), -Vec3::unit_y(), Vec3::unit_z(), -Vec3::unit_z()];
+    for (i, face) in faces.into_iter().enumerate(){
+        let (x, y) = (((i % 3) as f32 + 0.5) / 3.0, if i < 3 {0.75} else {0.25});
+        assert_close(direction(&cube_map, x, y).unwrap(), face);
+    }
+    // the up face is right above the front face, and they meet
+    let front_top = direction(&cube_map, 2.5 / 3.0, 0.49999).unwrap();
+    let up_bottom = direction(&cube_map, 2.5 / 3.0, 0.50001).unwrap();
+    assert_close(front_top, Vec3::new(0.0, 1.0, -1.0).normalized());
+    assert_close(up_bottom, front_top);
+    // 16x8 isn't 3:2
+    assert_eq!(cube_map.validate()[0].severity, light::validation::Severity::Warning);
+
+    let broken = scene("position = 0;0;0\ntarget = 0;0;-1\nprojection = fisheye_equisolid\nfisheye_fov = 0\nfisheye_lens = -1\n");
+    assert_eq!(broken.validate().len(), 2, "{:?}", broken.validate());
+}
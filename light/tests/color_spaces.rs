@@ -0,0 +1,83 @@
+//! Scene colours end up in the working space, data doesn't.
+
+use std::{fs::File, io::BufWriter, sync::Arc};
+
+use light::{color_space::{convert, ColorSpace}, hit_result::HitResult, importing::load_from_blender, material::Material, medium::Medium, ray::Ray, scene::Scene, texture::Texture};
+use ultraviolet::{Vec2, Vec3};
+
+/// The working space is only set at the end, after all colours.
+const SCENE: &str = "[camera]\nwidth = 16\nheight = 8\nposition = 0;0;5\ntarget = 0;0;0\nfov = 40\n\
+    [texture]\nname = albedo\ntexture_type = image_texture\nfile = albedo.png\ncolor_space = linear\n\
+    [texture]\nname = normals\ntexture_type = image_texture\nfile = albedo.png\ncolor_space = non_color\n\
+    [texture]\nname = checker\ntexture_type = checker_texture\neven = 0;1;0\nodd = 0;0;1\n\
+    [sphere]\nradius = 1\npos = -3;0;0\nmaterial_type = diffuse_material\nalbedo = @albedo\nnormal_map = @normals\n\
+    [sphere]\nradius = 1\npos = 0;0;0\nmaterial_type = metallic_material\nalbedo = @albedo\n\
+    [sphere]\nradius = 1\npos = 3;0;0\nmaterial_type = diffuse_material\nalbedo = 1;0;0\n\
+    [sphere]\nradius = 1\npos = 6;0;0\nmaterial_type = dielectric_material\nalbedo = @checker\nabsorption = 0;0;2\nscattering = 1;0;0\n\
+    [fog]\nabsorption = 0.5;0;0\nscattering = 0;0.1;0\n\
+    [render]\nworking_space = acescg\n";
+
+fn to_acescg(color: Vec3) -> Vec3{
+    convert(color, ColorSpace::LinearRec709, ColorSpace::AcesCg)
+}
+
+fn assert_close(a: Vec3, b: Vec3){
+    assert!((a - b).mag() < 1e-5, "{:?} != {:?}", a, b);
+}
+
+fn material_at(scene: &Scene, x: f32) -> &Material{
+    let mut hit = HitResult::default();
+    let ray = Ray { origin: Vec3::new(x, 0.0, 5.0), direction: -Vec3::unit_z() };
+    assert!(scene.bvh.as_ref().unwrap().hit(ray, &mut hit, 1e-4));
+    hit.material.unwrap()
+}
+
+fn load_scene() -> Scene{
+    let directory = std::env::temp_dir().join("light_color_spaces");
+    std::fs::create_dir_all(&directory).unwrap();
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(directory.join("albedo.png")).unwrap()), 1, 1);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.write_header().unwrap().write_image_data(&[255, 0, 0]).unwrap();
+    let scene_file = directory.join("scene.toml");
+    std::fs::write(&scene_file, SCENE).unwrap();
+    load_from_blender(scene_file.to_str().unwrap()).ok().unwrap()
+}
+
+#[test]
+fn colours_are_converted_once(){
+    let scene = load_scene();
+    let red = Vec3::new(1.0, 0.0, 0.0);
+    let sample = |texture: &Texture| texture.sample(Vec2::broadcast(0.5), Vec3::zero());
+
+    let (first_albedo, normal_map) = match material_at(&scene, -3.0){
+        Material::DiffuseMaterial { albedo, detail } => (albedo.clone(), detail.normal_map.clone().unwrap()),
+        _ => panic!("wrong material"),
+    };
+    let second_albedo = match material_at(&scene, 0.0){
+        Material::MetallicMaterial { albedo, .. } => albedo.clone(),
+        _ => panic!("wrong material"),
+    };
+    assert_close(sample(&first_albedo), to_acescg(red));
+    // every material uses the same converted image
+    match (&first_albedo, &second_albedo){
+        (Texture::ImageTexture { image: first, .. }, Texture::ImageTexture { image: second, .. }) => assert!(Arc::ptr_eq(first, second)),
+        _ => panic!("not an image texture"),
+    }
+    // data stays as it is
+    assert_eq!(sample(&normal_map), red);
+
+    match material_at(&scene, 3.0){
+        Material::DiffuseMaterial { albedo, .. } => assert_close(sample(albedo), to_acescg(red)),
+        _ => panic!("wrong material"),
+    }
+    match material_at(&scene, 6.0){
+        Material::DielectricMaterial { albedo, medium, .. } => {
+            assert_close(albedo.sample(Vec2::new(0.01, 0.01), Vec3::zero()), to_acescg(Vec3::new(0.0, 1.0, 0.0)));
+            assert_close(albedo.sample(Vec2::new(0.11, 0.01), Vec3::zero()), to_acescg(Vec3::new(0.0, 0.0, 1.0)));
+            // absorption and scattering are coefficients, not colours
+            assert_eq!(*medium, Some(Medium { absorption: Vec3::new(0.0, 0.0, 2.0), scattering: Vec3::new(1.0, 0.0, 0.0), anisotropy: 0.0 }));
+        }
+        _ => panic!("wrong material"),
+    }
+    assert_eq!(scene.fog, Some(Medium { absorption: Vec3::new(0.5, 0.0, 0.0), scattering: Vec3::new(0.0, 0.1, 0.0), anisotropy: 0.0 }));
+}
@@ -0,0 +1,171 @@
+//! Scenes with analytically known radiance.
+//!
+//! In a white furnace every object sits inside a uniform emitter with a radiance of 1. Materials that neither absorb
+//! nor create energy are invisible there: every path that escapes returns exactly 1, and objects with an albedo of
+//! `a` return `a`. Losses show up as dark pixels, energy gain as values above 1.
+//!
+//! Rough metal fuzzes the mirror direction, which conserves energy but isn't reciprocal, so the reciprocity test
+//! only covers the diffuse material.
+
+use light::{importing::load_from_blender, ray::Ray, sampler::{Sampler, IndependentSampler}, scene::Scene, trace_ray::trace_ray};
+use ultraviolet::{Vec2, Vec3};
+
+const FURNACE: &str = "[sphere]
+radius = 100
+pos = 0;0;0
+material_type = emissive_material
+emission_color = 1;1;1
+strength = 1
+";
+
+fn load_scene(name: &str, content: &str) -> Scene{
+    let path = std::env::temp_dir().join(format!("light_energy_conservation_{}.toml", name));
+    std::fs::write(&path, content).unwrap();
+    load_from_blender(path.to_str().unwrap()).unwrap()
+}
+
+/// A unit sphere at the origin with the given material lines, inside the furnace.
+fn furnace_with_sphere(name: &str, material: &str) -> Scene{
+    load_scene(name, format!("{}[sphere]\nradius = 1\npos = 0;0;0\n{}\n", FURNACE, material).as_str())
+}
+
+/// Radiance of `count` rays from `origin` aimed at random points of the square [-1, 1]^2 around the origin.
+fn trace_towards_origin(scene: &Scene, origin: Vec3, count: u32, max_depth: i32) -> Vec<Vec3>{
+    let mut sampler = IndependentSampler::new(0);
+    let forward = -origin.normalized();
+    let right = forward.cross(Vec3::unit_y()).normalized();
+    let up = right.cross(forward);
+    (0..count).map(|i| {
+        sampler.start_sample((i, 0), 0);
+        let target = sampler.next_2d() * 2.0 - Vec2::one();
+        let ray = Ray { origin, direction: (right * target.x + up * target.y - origin).normalized() };
+        trace_ray(ray, scene, &mut sampler, max_depth)
+    }).collect()
+}
+
+fn mean(radiances: &[Vec3]) -> Vec3{
+    radiances.iter().fold(Vec3::zero(), |sum, x| sum + *x) / radiances.len() as f32
+}
+
+/// Checks that every single path returns `expected`, which holds when nothing can be hit twice.
+fn assert_every_path(radiances: &[Vec3], expected: Vec3){
+    for (i, radiance) in radiances.iter().enumerate(){
+        assert!((*radiance - expected).abs().component_max() < 1e-4, "path {} returned {:?} instead of {:?}", i, radiance, expected);
+    }
+}
+
+#[test]
+fn white_diffuse_furnace(){
+    let scene = furnace_with_sphere("white_diffuse", "material_type = diffuse_material\nalbedo = 1;1;1");
+    assert_every_path(&trace_towards_origin(&scene, Vec3::new(0.0, 0.0, 5.0), 20000, 8), Vec3::one());
+}
+
+#[test]
+fn diffuse_albedo_furnace(){
+    let scene = furnace_with_sphere("diffuse_albedo", "material_type = diffuse_material\nalbedo = 0.25;0.5;0.75");
+    let radiances = trace_towards_origin(&scene, Vec3::new(0.0, 0.0, 5.0), 20000, 8);
+    // rays that miss the sphere see the furnace itself
+    for radiance in radiances{
+        assert!((radiance - Vec3::new(0.25, 0.5, 0.75)).abs().component_max() < 1e-4 || (radiance - Vec3::one()).abs().component_max() < 1e-4,
+            "unexpected radiance {:?}", radiance);
+    }
+}
+
+#[test]
+fn smooth_metal_furnace(){
+    let scene = furnace_with_sphere("smooth_metal", "material_type = metallic_material\nalbedo = 1;1;1\nroughness = 0");
+    assert_every_path(&trace_towards_origin(&scene, Vec3::new(0.0, 0.0, 5.0), 20000, 8), Vec3::one());
+}
+
+#[test]
+fn rough_metal_furnace(){
+    for roughness in [0.3, 0.7, 1.0]{
+        let scene = furnace_with_sphere("rough_metal", format!("material_type = metallic_material\nalbedo = 1;1;1\nroughness = {}", roughness).as_str());
+        assert_every_path(&trace_towards_origin(&scene, Vec3::new(0.0, 0.0, 5.0), 20000, 8), Vec3::one());
+    }
+}
+
+#[test]
+fn dielectric_furnace(){
+    let scene = furnace_with_sphere("dielectric", "material_type = dielectric_material\nior = 1.5");
+    let radiances = trace_towards_origin(&scene, Vec3::new(0.0, 0.0, 5.0), 20000, 64);
+    // paths only end early when they run out of depth, which is very rare in a sphere
+    assert!(radiances.iter().all(|x| x.component_max() <= 1.0 + 1e-4), "a path gained energy");
+    assert!(mean(&radiances).component_min() > 0.999, "mean radiance {:?}", mean(&radiances));
+}
+
+#[test]
+fn scattering_volume_furnace(){
+    let scene = furnace_with_sphere("scattering_volume", "material_type = volume_material\nscattering = 2;2;2\nabsorption = 0;0;0\nanisotropy = 0.5");
+    let radiances = trace_towards_origin(&scene, Vec3::new(0.0, 0.0, 5.0), 20000, 256);
+    assert!(radiances.iter().all(|x| x.component_max() <= 1.0 + 1e-4), "a path gained energy");
+    assert!(mean(&radiances).component_min() > 0.999, "mean radiance {:?}", mean(&radiances));
+}
+
+/// A thousand nested clear volumes: crossing their boundaries neither uses up the depth nor the call stack.
+#[test]
+fn many_volume_boundaries(){
+    let mut content = FURNACE.to_string();
+    for i in 0..1000{
+        content += &format!("[sphere]\nradius = {}\npos = 0;0;0\nmaterial_type = volume_material\nabsorption = 0;0;0\nscattering = 0;0;0\n", 0.2 + 0.0008 * i as f32);
+    }
+    let scene = load_scene("many_volume_boundaries", &content);
+    assert_every_path(&trace_towards_origin(&scene, Vec3::new(0.0, 0.0, 5.0), 100, 2), Vec3::one());
+}
+
+/// A white room: the Cornell box with white walls and an open front in the furnace. Light bounces around a lot, but
+/// nothing is absorbed, so everything still has a radiance of 1.
+#[test]
+fn white_room(){
+    let mut content = FURNACE.to_string();
+    for wall in ["floor", "ceiling", "back", "left", "right"]{
+        content += format!("[mesh]\nmesh_file = {}/tests/scenes/cornell_{}.obj\nmaterial_type = diffuse_material\nalbedo = 1;1;1\n", env!("CARGO_MANIFEST_DIR"), wall).as_str();
+    }
+    let scene = load_scene("white_room", &content);
+    let radiances = trace_towards_origin(&scene, Vec3::new(0.0, 0.0, 3.4), 20000, 128);
+    assert!(radiances.iter().all(|x| x.component_max() <= 1.0 + 1e-4), "a path gained energy");
+    assert!(mean(&radiances).component_min() > 0.995, "mean radiance {:?}", mean(&radiances));
+}
+
+/// The sample on the unit sphere can be exactly the negated normal. With a sampler that only returns zeros, a ray
+/// hitting the sphere at (0, 0, -1) samples the direction (0, 0, 1), which cancels out the normal.
+#[test]
+fn diffuse_degenerate_direction(){
+    struct ZeroSampler;
+    impl Sampler for ZeroSampler{
+        fn start_sample(&mut self, _pixel: (u32, u32), _sample_index: u32){}
+        fn next_1d(&mut self) -> f32{
+            0.0
+        }
+    }
+    let scene = furnace_with_sphere("degenerate_diffuse", "material_type = diffuse_material\nalbedo = 1;1;1");
+    let ray = Ray { origin: Vec3::new(0.0, 0.0, -5.0), direction: Vec3::unit_z() };
+    let radiance = trace_ray(ray, &scene, &mut ZeroSampler, 8);
+    assert!((radiance - Vec3::one()).abs().component_max() < 1e-4, "radiance {:?}", radiance);
+}
+
+/// Helmholtz reciprocity: swapping the camera and a small light changes the measured radiance only by the cosine at
+/// the light's side.
+#[test]
+fn diffuse_reciprocity(){
+    let light_direction = Vec3::new(0.6, 0.8, 0.0);
+    let view_direction = Vec3::new(-0.2, 0.4, 0.9).normalized();
+    let measure = |light: Vec3, view: Vec3| {
+        let light_position = light * 10.0;
+        let scene = load_scene("reciprocity", format!(
+            "[sphere]\nradius = 1000\npos = 0;-1000;0\nmaterial_type = diffuse_material\nalbedo = 0.8;0.8;0.8\n\
+             [sphere]\nradius = 1\npos = {};{};{}\nmaterial_type = emissive_material\nemission_color = 1;1;1\nstrength = 1\n",
+            light_position.x, light_position.y, light_position.z).as_str());
+        let mut sampler = IndependentSampler::new(1);
+        let count = 1000000;
+        let mut sum = Vec3::zero();
+        for i in 0..count{
+            sampler.start_sample((i, 0), 0);
+            sum += trace_ray(Ray { origin: view * 5.0, direction: -view }, &scene, &mut sampler, 2);
+        }
+        sum.x / count as f32 / light.y
+    };
+    let forward = measure(light_direction, view_direction);
+    let backward = measure(view_direction, light_direction);
+    assert!((forward - backward).abs() < 0.05 * forward, "{} and {} differ", forward, backward);
+}
@@ -0,0 +1,78 @@
+//! Bad input has to end up as the right `LightError`, pointing at the right place.
+
+use light::{bounding_box::BVH, color_space::ColorSpace, error::LightError, exr::{load_exr, save_exr}, hit_result::HitResult, hittable::Hittable, image::Image, importing::{load_from_blender, parse_scene}, mesh::Mesh, ray::Ray, scene::Scene};
+use ultraviolet::Vec3;
+
+const SPHERE: &str = "[sphere]\nradius = 1\npos = 0;0;0\nmaterial_type = diffuse_material\nalbedo = 1;1;1\n";
+
+fn parse_error_position(error: LightError) -> (usize, usize){
+    match error{
+        LightError::Parse { line, column, .. } => (line, column),
+        error => panic!("expected a parse error, got {:?}", error),
+    }
+}
+
+#[test]
+fn scene_parse_errors_point_at_the_value(){
+    let cases = [
+        // a broken number in the second line of the sphere
+        (format!("{}[sphere]\nradius = one\n", SPHERE), (7, 10)),
+        // the second component of a vector
+        (format!("[camera]\nposition = 1; x;3\n{}", SPHERE), (2, 15)),
+        // vectors need three components
+        (format!("[camera]\ntarget = 1;2\n{}", SPHERE), (2, 10)),
+        // unknown keys point at the key
+        (format!("{}  colour = 1;1;1\n", SPHERE), (6, 3)),
+        (format!("[render]\nsampler = random\n{}", SPHERE), (2, 11)),
+        (format!("{}[lamp]\n", SPHERE), (6, 1)),
+    ];
+    for (content, position) in cases{
+        let error = parse_scene(&content, "broken.toml").err().unwrap();
+        assert!(error.to_string().starts_with("broken.toml:"), "{}", error);
+        assert_eq!(parse_error_position(error), position, "for\n{}", content);
+    }
+}
+
+#[test]
+fn obj_parse_errors_point_at_the_value(){
+    let cases = [
+        ("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nf 1/1 2/1 4/1\n", (5, 11)),
+        ("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nf 1/1 2/2 3/1\n", (5, 9)),
+        ("v 0 0 0\nv 1 0 0\nv 0 x 0\n", (3, 5)),
+        ("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nf 1/1 2/1\n", (5, 10)),
+        ("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nf 1/1 0/1 3/1\n", (5, 7)),
+    ];
+    for (content, position) in cases{
+        assert_eq!(parse_error_position(Mesh::parse_obj(content, "broken.obj").err().unwrap()), position, "for\n{}", content);
+    }
+}
+
+#[test]
+fn error_kinds(){
+    assert!(matches!(load_from_blender("tests/scenes/does_not_exist.toml"), Err(LightError::Io { .. })));
+    assert!(matches!(Mesh::from_obj("tests/scenes/does_not_exist.obj"), Err(LightError::Io { .. })));
+    assert!(matches!(save_exr("empty.exr", &[], ColorSpace::LinearRec709), Err(LightError::Validation(_))));
+
+    let path = std::env::temp_dir().join("light_errors_unsupported.ppm");
+    std::fs::write(&path, b"P3\n1 1\n255\n0 0 0\n").unwrap();
+    assert!(matches!(Image::load_from_file(path.to_str().unwrap()), Err(LightError::UnsupportedFeature(_))));
+    // a header promising a huge image must not allocate it
+    std::fs::write(&path, b"P6\n100000 100000\n255\n").unwrap();
+    assert!(matches!(Image::load_from_file(path.to_str().unwrap()), Err(LightError::Parse { .. })));
+
+    let mut exr = 20000630u32.to_le_bytes().to_vec();
+    exr.extend_from_slice(&(2u32 | 0x200).to_le_bytes());
+    std::fs::write(&path, &exr).unwrap();
+    assert!(matches!(load_exr(path.to_str().unwrap()), Err(LightError::UnsupportedFeature(_))));
+}
+
+#[test]
+fn empty_geometry_is_not_a_crash(){
+    let ray = Ray { origin: Vec3::zero(), direction: Vec3::unit_z() };
+    let scene = Scene::default();
+    assert!(!scene.hit(ray, &mut HitResult::default(), 1e-4));
+    assert_eq!(scene.get_min_bounds(), Vec3::zero());
+
+    let bvh = BVH::<dyn Hittable>::build_recursive(Vec::new());
+    assert!(!bvh.hit(ray, &mut HitResult::default(), 1e-4));
+}
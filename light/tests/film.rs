@@ -0,0 +1,99 @@
+//! Reconstruction filters and how the film splats samples into pixels.
+
+use light::film::{Film, Filter, FilterType};
+use ultraviolet::{Vec2, Vec3};
+
+const FILTER_TYPES: [FilterType; 5] = [FilterType::Box, FilterType::Tent, FilterType::Gaussian, FilterType::Mitchell, FilterType::BlackmanHarris];
+
+/// The pixels of the reconstructed image that aren't black.
+fn lit_pixels(film: &Film, width: u32, height: u32) -> Vec<(u32, u32)>{
+    let image = film.image();
+    let mut pixels = Vec::new();
+    for y in 0..height{
+        for x in 0..width{
+            if image[(x, y)] != Vec3::zero(){
+                pixels.push((x, y));
+            }
+        }
+    }
+    pixels
+}
+
+#[test]
+fn box_filter_keeps_samples_in_their_pixel(){
+    for offset in [Vec2::new(0.0, 0.0), Vec2::new(0.5, 0.5), Vec2::new(0.999, 0.0), Vec2::new(0.0, 0.999), Vec2::new(0.999, 0.999)]{
+        let mut film = Film::new(5, 5, Filter::new(FilterType::Box, 0.5));
+        film.add_sample((2, 2), offset, Vec3::one());
+        assert_eq!(lit_pixels(&film, 5, 5), vec![(2, 2)], "offset {:?}", offset);
+        assert_eq!(film.image()[(2, 2)], Vec3::one());
+    }
+}
+
+#[test]
+fn footprint(){
+    // a tent of radius 1 reaches the neighbours only once the sample is off center towards them
+    let mut film = Film::new(5, 5, Filter::new(FilterType::Tent, 1.0));
+    film.add_sample((2, 2), Vec2::new(0.5, 0.5), Vec3::one());
+    assert_eq!(lit_pixels(&film, 5, 5), vec![(2, 2)]);
+    let mut film = Film::new(5, 5, Filter::new(FilterType::Tent, 1.0));
+    film.add_sample((2, 2), Vec2::new(0.9, 0.5), Vec3::one());
+    assert_eq!(lit_pixels(&film, 5, 5), vec![(2, 2), (3, 2)]);
+    let mut film = Film::new(5, 5, Filter::new(FilterType::Tent, 1.0));
+    film.add_sample((2, 2), Vec2::new(0.1, 0.9), Vec3::one());
+    assert_eq!(lit_pixels(&film, 5, 5), vec![(1, 2), (2, 2), (1, 3), (2, 3)]);
+
+    // samples next to the border only reach the pixels inside of the image
+    let mut film = Film::new(5, 5, Filter::new(FilterType::Gaussian, 1.5));
+    film.add_sample((0, 4), Vec2::new(0.5, 0.5), Vec3::one());
+    assert_eq!(lit_pixels(&film, 5, 5), vec![(0, 3), (1, 3), (0, 4), (1, 4)]);
+}
+
+#[test]
+fn constant_colors_are_reconstructed(){
+    let color = Vec3::new(0.2, 0.5, 1.5);
+    for filter_type in FILTER_TYPES{
+        for radius in [filter_type.default_radius(), 1.7]{
+            let mut film = Film::new(12, 12, Filter::new(filter_type, radius));
+            for y in 0..12{
+                for x in 0..12{
+                    // stratified offsets
+                    for i in 0..4{
+                        for j in 0..4{
+                            film.add_sample((x, y), Vec2::new((i as f32 + 0.5) / 4.0, (j as f32 + 0.5) / 4.0), color);
+                        }
+                    }
+                }
+            }
+            let image = film.image();
+            let border = radius.ceil() as u32;
+            for y in border..12 - border{
+                for x in border..12 - border{
+                    assert!((image[(x, y)] - color).mag() < 1e-4, "{:?} radius {}: {:?}", filter_type, radius, image[(x, y)]);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn filters_end_at_their_radius(){
+    for filter_type in FILTER_TYPES{
+        for radius in [0.5, filter_type.default_radius(), 3.0]{
+            let filter = Filter::new(filter_type, radius);
+            assert!(filter.evaluate(Vec2::zero()) > 0.0);
+            for distance in [radius * 1.001, radius * 1.5, radius * 4.0]{
+                for offset in [Vec2::new(distance, 0.0), Vec2::new(-distance, 0.0), Vec2::new(0.0, distance), Vec2::new(0.0, -distance)]{
+                    assert_eq!(filter.evaluate(offset), 0.0, "{:?} radius {} at {:?}", filter_type, radius, offset);
+                }
+            }
+            let at_radius = [filter.evaluate(Vec2::new(radius, 0.0)), filter.evaluate(Vec2::new(-radius, 0.0))];
+            if filter_type == FilterType::Box{
+                // half open, so a sample exactly between two pixels counts for one of them
+                assert_eq!(at_radius, [1.0, 0.0]);
+            }
+            else{
+                assert_eq!(at_radius, [0.0, 0.0], "{:?} radius {}", filter_type, radius);
+            }
+        }
+    }
+}
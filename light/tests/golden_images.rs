@@ -0,0 +1,127 @@
+//! Renders small canonical scenes and compares them to stored references.
+//!
+//! The references in `tests/references` are converged renders of the scenes in `tests/scenes`, along with the
+//! variance of a single sample of every pixel. The tests only take a few samples per pixel, so instead of comparing
+//! exactly they check that the difference to the reference can be explained by noise: the error of every 8x8 block is
+//! divided by its standard error, and the mean of these squared z-scores stays around 1 unless the expected image
+//! changed. Different random numbers are fine, bias is not.
+//!
+//! After an intentional change of the rendered result, regenerate the references with
+//! `LIGHT_UPDATE_REFERENCES=1 cargo test --release -p light --test golden_images`.
+
+use light::{image::Image, importing::load_from_blender, sampler::create_sampler, scene::Scene, trace_ray::trace_camera_ray};
+use ultraviolet::Vec3;
+
+const REFERENCE_SAMPLES: usize = 4096;
+const REFERENCE_SEED: u64 = 1;
+const BLOCK_SIZE: u32 = 8;
+/// Upper bound for the mean squared z-score. Unbiased renders stay well below it.
+const MAX_MEAN_SQUARED_Z: f32 = 2.0;
+
+/// The mean of every pixel and the variance of a single sample.
+fn render(scene: &Scene, samples_per_pixel: usize, seed: u64) -> (Image, Image){
+    let mut mean = Image::new(scene.width, scene.height);
+    let mut variance = Image::new(scene.width, scene.height);
+    let mut sampler = create_sampler(scene.settings.sampler, seed, samples_per_pixel);
+    for y in 0..scene.height{
+        for x in 0..scene.width{
+            let mut sum = Vec3::zero();
+            let mut squared_sum = Vec3::zero();
+            for sample in 0..samples_per_pixel{
+                sampler.start_sample((x, y), sample as u32);
+                let offset = sampler.next_2d();
+                let u = (x as f32 + offset.x) / scene.width as f32;
+                let v = (y as f32 + offset.y) / scene.height as f32;
+                let ray = scene.camera.get_ray(u, v, sampler.as_mut()).unwrap();
+                let color = trace_camera_ray(ray, scene, sampler.as_mut());
+                sum += color;
+                squared_sum += color * color;
+            }
+            let n = samples_per_pixel as f32;
+            mean[(x, y)] = sum / n;
+            variance[(x, y)] = (squared_sum / n - mean[(x, y)] * mean[(x, y)]).max_by_component(Vec3::zero()) * n / (n - 1.0);
+        }
+    }
+    (mean, variance)
+}
+
+fn check_scene(name: &str){
+    let scene = load_from_blender(format!("tests/scenes/{}.toml", name).as_str()).unwrap();
+    let reference_path = format!("tests/references/{}.pfm", name);
+    let variance_path = format!("tests/references/{}_variance.pfm", name);
+    if std::env::var("LIGHT_UPDATE_REFERENCES").is_ok(){
+        let (reference, variance) = render(&scene, REFERENCE_SAMPLES, REFERENCE_SEED);
+        reference.save_to_pfm(&reference_path).unwrap();
+        variance.save_to_pfm(&variance_path).unwrap();
+    }
+    let load = |path: &str| Image::load_from_file(path).unwrap_or_else(|error| panic!("Couldn't load the reference {}: {}", path, error));
+    let reference = load(&reference_path);
+    // the test render misses rare paths, like thin slivers of geometry, so its own variance estimate is unreliable
+    let variance = load(&variance_path);
+
+    let samples_per_pixel = scene.settings.samples_per_pixel;
+    let image = render(&scene, samples_per_pixel, scene.settings.seed).0;
+    assert_eq!((image.width(), image.height()), (reference.width(), reference.height()), "{}: the reference has a different size", name);
+
+    // variance of the difference of the means, the reference is noisy as well, just less
+    let variance_scale = 1.0 / samples_per_pixel as f32 + 1.0 / REFERENCE_SAMPLES as f32;
+    let mut squared_z_sum = 0.0;
+    let mut block_count = 0;
+    for block_y in (0..scene.height).step_by(BLOCK_SIZE as usize){
+        for block_x in (0..scene.width).step_by(BLOCK_SIZE as usize){
+            let mut difference = Vec3::zero();
+            let mut block_variance = Vec3::zero();
+            let mut pixel_count = 0.0;
+            for y in block_y..(block_y + BLOCK_SIZE).min(scene.height){
+                for x in block_x..(block_x + BLOCK_SIZE).min(scene.width){
+                    difference += image[(x, y)] - reference[(x, y)];
+                    block_variance += variance[(x, y)] * variance_scale;
+                    pixel_count += 1.0;
+                }
+            }
+            let difference = difference / pixel_count;
+            let block_variance = block_variance / (pixel_count * pixel_count);
+            for channel in 0..3{
+                // floor for blocks without noise, e.g. only background or emitters
+                let variance = block_variance[channel] + 1e-8;
+                squared_z_sum += difference[channel] * difference[channel] / variance;
+                block_count += 1;
+            }
+        }
+    }
+    let mean_squared_z = squared_z_sum / block_count as f32;
+
+    if mean_squared_z >= MAX_MEAN_SQUARED_Z{
+        let failed_path = std::env::temp_dir().join(format!("light_golden_{}.pfm", name));
+        image.save_to_pfm(failed_path.to_str().unwrap()).unwrap();
+        panic!(
+            "{}: the render differs from the reference by more than noise (mean squared z-score {:.2}, relMSE {:.5}). It was saved to {}.",
+            name, mean_squared_z, image.relative_mse(&reference).unwrap(), failed_path.display()
+        );
+    }
+}
+
+#[test]
+fn cornell_box(){
+    check_scene("cornell_box");
+}
+
+#[test]
+fn glass_sphere(){
+    check_scene("glass_sphere");
+}
+
+#[test]
+fn metal_sphere(){
+    check_scene("metal_sphere");
+}
+
+#[test]
+fn emissive_only(){
+    check_scene("emissive_only");
+}
+
+#[test]
+fn default_cube(){
+    check_scene("default_cube");
+}
@@ -0,0 +1,208 @@
+//! Tone mapping, the display transform, the denoiser and the lens effects.
+
+use light::{image::Image, image_filters::{denoise, post_process, srgb_transfer, tone_map, DenoiseSettings, PostProcessSettings, ToneMapper}, sampler::{create_sampler, SamplerType}};
+use ultraviolet::Vec3;
+
+const TONE_MAPPERS: [ToneMapper; 3] = [ToneMapper::Reinhard, ToneMapper::Aces, ToneMapper::Agx];
+
+fn luminance(color: Vec3) -> f32{
+    color.dot(Vec3::new(0.2126, 0.7152, 0.0722))
+}
+
+/// Exposures from 2^-12 to 2^12 in small steps.
+fn exposures() -> impl Iterator<Item = f32>{
+    (-240..=240).map(|i| 2.0f32.powf(i as f32 / 20.0))
+}
+
+fn constant_image(width: u32, height: u32, value: Vec3) -> Image{
+    let mut image = Image::new(width, height);
+    image.apply_filter(|_| value);
+    image
+}
+
+/// A dim diagonal gradient, different in every channel.
+fn gradient(width: u32, height: u32) -> Image{
+    let mut image = Image::new(width, height);
+    for y in 0..height{
+        for x in 0..width{
+            image[(x, y)] = Vec3::new(x as f32 / width as f32, y as f32 / height as f32, (x + y) as f32 / (width + height) as f32) * 0.8;
+        }
+    }
+    image
+}
+
+fn sum(image: &Image) -> Vec3{
+    let mut sum = Vec3::zero();
+    for y in 0..image.height(){
+        for x in 0..image.width(){
+            sum += image[(x, y)];
+        }
+    }
+    sum
+}
+
+fn variance(image: &Image) -> f32{
+    let count = (image.width() * image.height()) as f32;
+    let mut values = Vec::new();
+    for y in 0..image.height(){
+        for x in 0..image.width(){
+            values.push(image[(x, y)].x);
+        }
+    }
+    let mean = values.iter().sum::<f32>() / count;
+    values.iter().map(|x| (x - mean) * (x - mean)).sum::<f32>() / count
+}
+
+#[test]
+fn srgb_transfer_is_continuous(){
+    const BREAKPOINT: f32 = 0.0031308;
+    let below = srgb_transfer(Vec3::broadcast(BREAKPOINT)).x;
+    let above = 1.055 * BREAKPOINT.powf(1.0 / 2.4) - 0.055;
+    assert!((below - above).abs() < 1e-6, "{} != {}", below, above);
+    // the slopes of the pieces are 12.92 and 12.70 there, so the steps shrink with the distance instead of jumping
+    for epsilon in [1e-4, 1e-5, 1e-6]{
+        let step = srgb_transfer(Vec3::broadcast(BREAKPOINT + epsilon)).x - srgb_transfer(Vec3::broadcast(BREAKPOINT - epsilon)).x;
+        let slope = step / (2.0 * epsilon);
+        assert!((12.6..13.0).contains(&slope), "slope of {} around the breakpoint", slope);
+    }
+
+    assert_eq!(srgb_transfer(Vec3::zero()), Vec3::zero());
+    assert!((srgb_transfer(Vec3::one()) - Vec3::one()).abs().component_max() < 1e-6);
+    assert!((srgb_transfer(Vec3::broadcast(0.18)).x - 0.4613).abs() < 1e-3);
+    let mut previous = 0.0;
+    for i in 0..=1000{
+        let value = srgb_transfer(Vec3::broadcast(i as f32 / 1000.0)).x;
+        assert!(value >= previous);
+        previous = value;
+    }
+}
+
+#[test]
+fn tone_mappers_map_into_unit_range(){
+    let colors = [Vec3::one(), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.5, 0.1), Vec3::new(-0.2, 1.0, 0.3)];
+    for tone_mapper in TONE_MAPPERS{
+        for color in colors{
+            for exposure in exposures().chain([0.0, 1e10, f32::MAX]){
+                let mapped = tone_map(tone_mapper, color * exposure);
+                assert!(mapped.component_min() >= 0.0 && mapped.component_max() <= 1.0, "{:?}({:?}) = {:?}", tone_mapper, color * exposure, mapped);
+            }
+        }
+        assert!(tone_map(tone_mapper, Vec3::zero()).component_max() < 1e-3, "{:?}", tone_mapper);
+    }
+}
+
+#[test]
+fn tone_mappers_are_monotonic(){
+    for tone_mapper in TONE_MAPPERS{
+        // every channel of grey ramps increases
+        let mut previous = tone_map(tone_mapper, Vec3::zero());
+        for exposure in exposures(){
+            let mapped = tone_map(tone_mapper, Vec3::broadcast(exposure));
+            for channel in 0..3{
+                assert!(mapped[channel] >= previous[channel], "{:?} decreases at {}", tone_mapper, exposure);
+            }
+            previous = mapped;
+        }
+        assert!(previous.component_min() > 0.9, "{:?} doesn't approach white: {:?}", tone_mapper, previous);
+
+        // brighter colours never look darker. AgX's outset matrix subtracts a bit of blue from the other channels, so
+        // once red and green are saturated, more blue lowers the luminance by up to 3e-4 just below white.
+        let tolerance = if tone_mapper == ToneMapper::Agx {5e-4} else {1e-6};
+        for color in [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.5, 0.1)]{
+            let mut previous = 0.0;
+            for exposure in exposures(){
+                let mapped = luminance(tone_map(tone_mapper, color * exposure));
+                assert!(mapped >= previous - tolerance, "{:?}({:?}) gets darker at {}", tone_mapper, color, exposure);
+                previous = mapped;
+            }
+        }
+    }
+}
+
+#[test]
+fn denoising_keeps_flat_images(){
+    let albedo = constant_image(16, 16, Vec3::new(0.8, 0.5, 0.2));
+    let normal = constant_image(16, 16, Vec3::unit_z());
+    let color = constant_image(16, 16, Vec3::new(0.4, 0.25, 0.1));
+    let denoised = denoise(&color, &albedo, &normal, &DenoiseSettings::default());
+    for y in 0..16{
+        for x in 0..16{
+            assert!((denoised[(x, y)] - color[(x, y)]).mag() < 1e-5, "{:?}", denoised[(x, y)]);
+        }
+    }
+}
+
+#[test]
+fn denoising_reduces_noise(){
+    let albedo = constant_image(32, 32, Vec3::one());
+    let normal = constant_image(32, 32, Vec3::unit_z());
+    let mut sampler = create_sampler(SamplerType::Independent, 1, 1);
+    let mut noisy = Image::new(32, 32);
+    for y in 0..32{
+        for x in 0..32{
+            sampler.start_sample((x, y), 0);
+            noisy[(x, y)] = Vec3::broadcast(0.5 + 0.4 * (sampler.next_1d() - 0.5));
+        }
+    }
+    let denoised = denoise(&noisy, &albedo, &normal, &DenoiseSettings::default());
+    assert!(variance(&denoised) < 0.25 * variance(&noisy), "{} {}", variance(&denoised), variance(&noisy));
+}
+
+#[test]
+fn bloom_and_glare_need_bright_pixels(){
+    let image = gradient(24, 16);
+    let settings = PostProcessSettings { bloom_threshold: 1.0, bloom_intensity: 1.0, glare_intensity: 1.0, ..PostProcessSettings::default() };
+    let processed = post_process(&image, &settings);
+    for y in 0..16{
+        for x in 0..24{
+            assert_eq!(processed[(x, y)], image[(x, y)]);
+        }
+    }
+}
+
+#[test]
+fn bloom_keeps_the_energy_at_the_borders(){
+    // a single bright pixel in a corner, at an edge and in the middle; 9 of its luminance 10 are above the threshold
+    for pixel in [(0, 0), (0, 7), (12, 8)]{
+        let mut image = Image::new(24, 16);
+        image[pixel] = Vec3::broadcast(10.0);
+        let settings = PostProcessSettings { bloom_threshold: 1.0, bloom_intensity: 1.0, bloom_radius: 0.125, ..PostProcessSettings::default() };
+        let added = sum(&post_process(&image, &settings)) - sum(&image);
+        assert!((added - Vec3::broadcast(9.0)).mag() < 1e-3, "{:?} at {:?}", added, pixel);
+    }
+}
+
+#[test]
+fn zero_distortion_keeps_the_image(){
+    let image = gradient(24, 16);
+    let processed = post_process(&image, &PostProcessSettings::default());
+    for y in 0..16{
+        for x in 0..24{
+            assert_eq!(processed[(x, y)], image[(x, y)]);
+        }
+    }
+
+    // chromatic aberration alone distorts red and blue in opposite directions, but not green
+    let processed = post_process(&image, &PostProcessSettings { chromatic_aberration: 0.1, ..PostProcessSettings::default() });
+    for y in 0..16{
+        for x in 0..24{
+            assert!((processed[(x, y)].y - image[(x, y)].y).abs() < 1e-5, "{:?} != {:?}", processed[(x, y)], image[(x, y)]);
+        }
+    }
+    // blue is pulled in from closer to the center
+    assert!((processed[(0, 8)].z - image[(0, 8)].z).abs() > 1e-3);
+}
+
+#[test]
+fn vignette_darkens_the_corners(){
+    let image = constant_image(17, 17, Vec3::one());
+    let processed = post_process(&image, &PostProcessSettings { vignette: 0.5, ..PostProcessSettings::default() });
+    assert_eq!(processed[(8, 8)], Vec3::one());
+    for corner in [(0, 0), (16, 0), (0, 16), (16, 16)]{
+        assert!(processed[corner].x < 0.5, "{:?}", processed[corner]);
+    }
+    // darker towards the corners
+    for x in 8..16{
+        assert!(processed[(x + 1, x + 1)].x < processed[(x, x)].x);
+    }
+}
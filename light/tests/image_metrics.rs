@@ -0,0 +1,73 @@
+//! Error metrics between images.
+
+use light::{error::LightError, image::Image};
+use ultraviolet::Vec3;
+
+/// A horizontal ramp from black to white with a brighter square in the middle, so it has edges and points.
+fn pattern(width: u32, height: u32) -> Image{
+    let mut image = Image::new(width, height);
+    for y in 0..height{
+        for x in 0..width{
+            let square = (width / 3..2 * width / 3).contains(&x) && (height / 3..2 * height / 3).contains(&y);
+            image[(x, y)] = Vec3::broadcast(x as f32 / (width - 1) as f32) * if square {0.5} else {1.0};
+        }
+    }
+    image
+}
+
+#[test]
+fn identical_images(){
+    let image = pattern(24, 16);
+    assert_eq!(image.mse(&image).unwrap(), 0.0);
+    assert_eq!(image.relative_mse(&image).unwrap(), 0.0);
+    assert_eq!(image.psnr(&image).unwrap(), f32::INFINITY);
+    assert!((image.ssim(&image).unwrap() - 1.0).abs() < 1e-5);
+    assert_eq!(image.flip(&image).unwrap(), 0.0);
+}
+
+#[test]
+fn constant_offset(){
+    let reference = pattern(24, 16);
+    let mut image = reference.clone();
+    image.apply_filter(|x| x + Vec3::new(0.1, 0.2, 0.3));
+    // the mean of 0.1², 0.2² and 0.3²
+    let expected = (0.01 + 0.04 + 0.09) / 3.0;
+    assert!((image.mse(&reference).unwrap() - expected).abs() < 1e-6);
+    assert!((image.rmse(&reference).unwrap() - f32::sqrt(expected)).abs() < 1e-6);
+    assert!((image.psnr(&reference).unwrap() + 10.0 * f32::log10(expected)).abs() < 1e-4);
+    assert!(image.ssim(&reference).unwrap() < 1.0);
+}
+
+#[test]
+fn flip_is_symmetric_and_bounded(){
+    let a = pattern(32, 24);
+    let mut b = Image::new(32, 24);
+    b.apply_filter(|_| Vec3::new(0.2, 0.6, 0.1));
+    let mut c = a.clone();
+    c.apply_filter(|x| x * 0.8);
+
+    for (x, y) in [(&a, &b), (&a, &c), (&b, &c)]{
+        let forward = x.flip(y).unwrap();
+        let backward = y.flip(x).unwrap();
+        assert!((forward - backward).abs() < 1e-6, "{} != {}", forward, backward);
+        assert!(forward > 0.0 && forward <= 1.0, "{}", forward);
+        for error in x.flip_error_map(y).unwrap(){
+            assert!((0.0..=1.0).contains(&error), "{}", error);
+        }
+    }
+    // a darker copy is closer than a different image
+    assert!(a.flip(&c).unwrap() < a.flip(&b).unwrap());
+}
+
+#[test]
+fn size_mismatch(){
+    let image = pattern(24, 16);
+    let reference = pattern(16, 24);
+    assert!(matches!(image.mse(&reference), Err(LightError::Validation(_))));
+    assert!(matches!(image.rmse(&reference), Err(LightError::Validation(_))));
+    assert!(matches!(image.relative_mse(&reference), Err(LightError::Validation(_))));
+    assert!(matches!(image.psnr(&reference), Err(LightError::Validation(_))));
+    assert!(matches!(image.ssim(&reference), Err(LightError::Validation(_))));
+    assert!(matches!(image.flip(&reference), Err(LightError::Validation(_))));
+    assert!(matches!(image.error_heatmap(&reference), Err(LightError::Validation(_))));
+}
@@ -0,0 +1,420 @@
+//! Property tests for the ray-primitive intersections and the BVH.
+//!
+//! Random rays are shot at random triangles and spheres and compared to a straightforward reference computed in double
+//! precision. Rays that pass within a small margin of an edge or touch a sphere tangentially could go either way and
+//! are skipped there; the edge cases have their own tests. The BVH is compared to testing every primitive, which has
+//! to give exactly the same closest hit.
+
+use light::{bounding_box::BVH, hit_result::HitResult, hittable::Hittable, material::Material, random::random_on_unit_sphere, ray::Ray, sampler::{IndependentSampler, Sampler}, sphere::Sphere, triangle::Triangle};
+use ultraviolet::{Vec2, Vec3, Vec4};
+
+/// The distance `trace_ray` starts looking for hits at.
+const MIN_DISTANCE: f32 = 1e-4;
+const CASES: u32 = 20000;
+
+type DVec3 = [f64; 3];
+
+fn to_f64(v: Vec3) -> DVec3{
+    [v.x as f64, v.y as f64, v.z as f64]
+}
+
+fn sub(a: DVec3, b: DVec3) -> DVec3{
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: DVec3, b: DVec3) -> f64{
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: DVec3, b: DVec3) -> DVec3{
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn random_vec3(sampler: &mut IndependentSampler) -> Vec3{
+    Vec3::new(sampler.next_1d(), sampler.next_1d(), sampler.next_1d()) * 2.0 - Vec3::one()
+}
+
+/// Log-uniform in [`min`, `max`].
+fn random_scale(sampler: &mut IndependentSampler, min: f32, max: f32) -> f32{
+    min * (max / min).powf(sampler.next_1d())
+}
+
+/// A flat shaded triangle whose UV coordinates are the barycentric coordinates of the first two vertices.
+fn triangle(vertices: [Vec3; 3]) -> Triangle{
+    let normal = (vertices[1] - vertices[0]).cross(vertices[2] - vertices[0]).normalized();
+    Triangle {
+        vertices,
+        normals: [normal; 3],
+        uv_coordinates: [Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0), Vec2::zero()],
+        tangents: [Vec4::new(1.0, 0.0, 0.0, 1.0); 3],
+    }
+}
+
+fn sphere(center: Vec3, radius: f32) -> Sphere{
+    Sphere { center, radius, material: Material::NormalMaterial(), object_id: 1, material_id: 1, location: None }
+}
+
+fn hit(object: &dyn Hittable, ray: Ray) -> Option<HitResult<'_>>{
+    let mut hit = HitResult::default();
+    if object.hit(ray, &mut hit, MIN_DISTANCE) {Some(hit)} else {None}
+}
+
+/// Distance along the ray to the triangle's plane and the barycentric coordinates of that point, which are all
+/// positive if it lies within the triangle. `None` for rays parallel to the plane.
+fn reference_triangle_hit(triangle: &Triangle, ray: Ray) -> Option<(f64, DVec3)>{
+    let [v0, v1, v2] = triangle.vertices.map(to_f64);
+    let (origin, direction) = (to_f64(ray.origin), to_f64(ray.direction));
+    let normal = cross(sub(v1, v0), sub(v2, v0));
+    let denominator = dot(normal, direction);
+    if denominator == 0.0{
+        return None;
+    }
+    let t = dot(normal, sub(v0, origin)) / denominator;
+    let point = [origin[0] + t * direction[0], origin[1] + t * direction[1], origin[2] + t * direction[2]];
+    let area = dot(normal, normal);
+    let b0 = dot(cross(sub(v2, v1), sub(point, v1)), normal) / area;
+    let b1 = dot(cross(sub(v0, v2), sub(point, v2)), normal) / area;
+    Some((t, [b0, b1, 1.0 - b0 - b1]))
+}
+
+/// Both distances along the ray to the sphere, if it is hit at all, and the discriminant relative to the largest
+/// possible one, which is zero for tangential rays.
+fn reference_sphere_hit(sphere: &Sphere, ray: Ray) -> Option<(f64, f64, f64)>{
+    let oc = sub(to_f64(ray.origin), to_f64(sphere.center));
+    let direction = to_f64(ray.direction);
+    let radius = sphere.radius as f64;
+    let a = dot(direction, direction);
+    let half_b = dot(oc, direction);
+    let c = dot(oc, oc) - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0{
+        return None;
+    }
+    let sqrt_discriminant = discriminant.sqrt();
+    Some(((-half_b - sqrt_discriminant) / a, (-half_b + sqrt_discriminant) / a, discriminant / (a * radius * radius)))
+}
+
+fn assert_normal_faces_ray(hit: &HitResult, ray: Ray, case: u32){
+    assert!((hit.normal.mag() - 1.0).abs() < 1e-4, "case {}: normal {:?} isn't normalized", case, hit.normal);
+    assert!(hit.normal.dot(ray.direction) <= 0.0, "case {}: normal {:?} points along the ray {:?}", case, hit.normal, ray);
+}
+
+#[test]
+fn random_rays_against_triangles(){
+    let mut sampler = IndependentSampler::new(0);
+    let mut hits = 0;
+    for case in 0..CASES{
+        sampler.start_sample((case, 0), 0);
+        let scale = random_scale(&mut sampler, 1e-4, 1e3);
+        let center = random_vec3(&mut sampler) * 10.0 * scale;
+        let triangle = triangle([0, 1, 2].map(|_| center + random_vec3(&mut sampler) * scale));
+        let origin = center + random_vec3(&mut sampler) * 4.0 * scale;
+        // aim at the triangle and a bit around it, and not always with a normalized direction
+        let b0 = sampler.next_1d() * 1.5 - 0.25;
+        let b1 = sampler.next_1d() * 1.5 - 0.25;
+        let target = triangle.vertices[0] * b0 + triangle.vertices[1] * b1 + triangle.vertices[2] * (1.0 - b0 - b1);
+        let direction = if sampler.next_1d() < 0.2 {random_on_unit_sphere(&mut sampler)} else {(target - origin).normalized()};
+        let ray = Ray { origin, direction: direction * random_scale(&mut sampler, 0.5, 2.0) };
+
+        let Some((t, barycentrics)) = reference_triangle_hit(&triangle, ray) else{
+            assert!(hit(&triangle, ray).is_none(), "case {}: hit a triangle parallel to the ray", case);
+            continue;
+        };
+        let normal = triangle.normals[0];
+        let cosine = normal.dot(ray.direction.normalized()).abs() as f64;
+        let margin = barycentrics.iter().map(|x| x.abs()).fold(f64::INFINITY, f64::min);
+        if margin < 1e-3 || cosine < 1e-3 || (t - MIN_DISTANCE as f64).abs() < 1e-3 * scale as f64{
+            continue;
+        }
+        let expected = t > MIN_DISTANCE as f64 && barycentrics.iter().all(|x| *x > 0.0);
+        match hit(&triangle, ray){
+            Some(hit) => {
+                assert!(expected, "case {}: unexpected hit at t = {} of {:?} with {:?}", case, hit.t, ray, triangle.vertices);
+                let length = ray.direction.mag() as f64;
+                assert!((hit.t as f64 - t).abs() * length < 1e-3 * scale as f64, "case {}: hit at t = {} instead of {}", case, hit.t, t);
+                assert!((hit.uv.x as f64 - barycentrics[0]).abs() < 1e-3 && (hit.uv.y as f64 - barycentrics[1]).abs() < 1e-3,
+                    "case {}: barycentric coordinates {:?} instead of {:?}", case, hit.uv, barycentrics);
+                assert_normal_faces_ray(&hit, ray, case);
+                assert!(hit.normal.dot(normal).abs() > 0.9999, "case {}: normal {:?} instead of ±{:?}", case, hit.normal, normal);
+                hits += 1;
+            }
+            None => assert!(!expected, "case {}: missed {:?} with {:?} at t = {}", case, triangle.vertices, ray, t),
+        }
+    }
+    assert!(hits > CASES / 8, "only {} rays hit, the test doesn't cover much", hits);
+}
+
+#[test]
+fn random_rays_against_spheres(){
+    let mut sampler = IndependentSampler::new(1);
+    let mut hits = 0;
+    for case in 0..CASES{
+        sampler.start_sample((case, 0), 0);
+        let radius = random_scale(&mut sampler, 1e-2, 1e3);
+        let sphere = sphere(random_vec3(&mut sampler) * 10.0 * radius, radius);
+        let origin = sphere.center + random_vec3(&mut sampler) * 4.0 * radius;
+        let target = sphere.center + random_vec3(&mut sampler) * 1.2 * radius;
+        let direction = if sampler.next_1d() < 0.2 {random_on_unit_sphere(&mut sampler)} else {(target - origin).normalized()};
+        let ray = Ray { origin, direction: direction * random_scale(&mut sampler, 0.5, 2.0) };
+
+        let reference = reference_sphere_hit(&sphere, ray);
+        let expected = match reference{
+            Some((_, _, discriminant)) if discriminant < 1e-3 => continue,
+            Some((near, far, _)) => {
+                let length = ray.direction.mag() as f64;
+                if [near, far].iter().any(|t| (t - MIN_DISTANCE as f64).abs() * length < 1e-4 * radius as f64){
+                    continue;
+                }
+                [near, far].into_iter().find(|t| *t > MIN_DISTANCE as f64)
+            }
+            None => None,
+        };
+        match (hit(&sphere, ray), expected){
+            (Some(hit), Some(t)) => {
+                let length = ray.direction.mag() as f64;
+                assert!((hit.t as f64 - t).abs() * length < 1e-4 * radius as f64 + 1e-5 * t * length, "case {}: hit at t = {} instead of {}", case, hit.t, t);
+                assert_normal_faces_ray(&hit, ray, case);
+                let outward_normal = (ray.at(hit.t) - sphere.center) / radius;
+                let inside = (ray.origin - sphere.center).mag() < radius;
+                assert_eq!(hit.is_front_face, !inside, "case {}: wrong side", case);
+                assert!((hit.normal - if inside {-outward_normal} else {outward_normal}).mag() < 1e-3, "case {}: normal {:?}", case, hit.normal);
+                assert!((0.0..=1.0).contains(&hit.uv.x) && (0.0..=1.0).contains(&hit.uv.y), "case {}: uv {:?}", case, hit.uv);
+                hits += 1;
+            }
+            (None, None) => {}
+            (Some(hit), None) => panic!("case {}: unexpected hit at t = {} of {:?} with {:?}", case, hit.t, ray, sphere.center),
+            (None, Some(t)) => panic!("case {}: missed at t = {} with {:?}", case, t, ray),
+        }
+    }
+    assert!(hits > CASES / 4, "only {} rays hit, the test doesn't cover much", hits);
+}
+
+/// A mix of triangles and spheres of different sizes. Generated from the seed, so the same scene can be built twice.
+fn random_primitives(seed: u64, count: u32) -> Vec<Box<dyn Hittable>>{
+    let mut sampler = IndependentSampler::new(seed);
+    (0..count).map(|i| {
+        sampler.start_sample((i, 0), 0);
+        let center = random_vec3(&mut sampler) * 10.0;
+        let size = random_scale(&mut sampler, 0.05, 3.0);
+        if sampler.next_1d() < 0.3{
+            Box::new(sphere(center, size)) as Box<dyn Hittable>
+        }
+        else if sampler.next_1d() < 0.3{
+            // axis aligned, so the bounding box is only as thick as its padding
+            let axis = (sampler.next_1d() * 3.0) as usize;
+            let mut vertices = [0, 1, 2].map(|_| center + random_vec3(&mut sampler) * size);
+            for vertex in &mut vertices{
+                vertex[axis] = center[axis];
+            }
+            Box::new(triangle(vertices))
+        }
+        else{
+            Box::new(triangle([0, 1, 2].map(|_| center + random_vec3(&mut sampler) * size)))
+        }
+    }).collect()
+}
+
+/// Compares the closest hit of the BVH to the one of testing every primitive.
+fn assert_bvh_matches_brute_force(rays: impl Fn(&mut IndependentSampler, &[Box<dyn Hittable>]) -> Ray){
+    for seed in 0..4{
+        let primitives = random_primitives(seed, 300);
+        let bvh = BVH::<dyn Hittable>::build_recursive(random_primitives(seed, 300));
+        let mut sampler = IndependentSampler::new(seed + 100);
+        let mut hits = 0;
+        for case in 0..CASES / 4{
+            sampler.start_sample((case, 0), 0);
+            let ray = rays(&mut sampler, &primitives);
+            // sometimes there already is a closer hit from somewhere else
+            let limit = if sampler.next_1d() < 0.2 {sampler.next_1d() * 30.0} else {f32::INFINITY};
+            let mut expected = HitResult { t: limit, ..Default::default() };
+            let mut actual = HitResult { t: limit, ..Default::default() };
+            let did_hit = primitives.hit(ray, &mut expected, MIN_DISTANCE);
+            assert_eq!(bvh.hit(ray, &mut actual, MIN_DISTANCE), did_hit, "seed {} case {}: {:?}", seed, case, ray);
+            assert_eq!(actual.t, expected.t, "seed {} case {}: {:?}", seed, case, ray);
+            assert_eq!(actual.normal, expected.normal, "seed {} case {}: {:?}", seed, case, ray);
+            hits += did_hit as u32;
+        }
+        assert!(hits > CASES / 40, "only {} rays hit, the test doesn't cover much", hits);
+    }
+}
+
+#[test]
+fn bvh_matches_brute_force(){
+    assert_bvh_matches_brute_force(|sampler, _| {
+        let origin = random_vec3(sampler) * 15.0;
+        let target = random_vec3(sampler) * 10.0;
+        Ray { origin, direction: (target - origin).normalized() }
+    });
+}
+
+/// Rays along the axes and within the coordinate planes. Zero direction components make `1.0 / direction` infinite,
+/// with the sign of the zero, and origins exactly on a slab make the slab distances `0 * inf = NaN`.
+#[test]
+fn bvh_matches_brute_force_for_axis_parallel_rays(){
+    assert_bvh_matches_brute_force(|sampler, primitives| {
+        let mut origin = random_vec3(sampler) * 15.0;
+        let mut direction = random_on_unit_sphere(sampler);
+        let zero_axes = (sampler.next_1d() * 3.0) as usize;
+        let first_axis = (sampler.next_1d() * 3.0) as usize;
+        for i in 0..zero_axes{
+            let axis = (first_axis + i) % 3;
+            direction[axis] = if sampler.next_1d() < 0.5 {0.0} else {-0.0};
+            // put the origin on a slab of some primitive, which is the slab of some node as well
+            if sampler.next_1d() < 0.5{
+                let primitive = &primitives[(sampler.next_1d() * primitives.len() as f32) as usize];
+                origin[axis] = if sampler.next_1d() < 0.5 {primitive.get_min_bounds()[axis]} else {primitive.get_max_bounds()[axis]};
+            }
+        }
+        Ray { origin, direction: direction.normalized() }
+    });
+}
+
+#[test]
+fn slab_test_with_zero_direction_components(){
+    let bvh = BVH::<dyn Hittable>::build_recursive(vec![Box::new(sphere(Vec3::zero(), 1.0)), Box::new(sphere(Vec3::new(3.0, 0.0, 0.0), 1.0))]);
+    for direction in [Vec3::unit_z(), Vec3::new(0.0, -0.0, 1.0), Vec3::new(-0.0, -0.0, 1.0)]{
+        // inside the slabs of x and y
+        let ray = Ray { origin: Vec3::new(0.5, 0.5, -5.0), direction };
+        assert!(hit(bvh.as_ref(), ray).is_some(), "missed with {:?}", direction);
+        // outside the slab of x, and the box only reaches up to x = 4
+        let ray = Ray { origin: Vec3::new(4.5, 0.5, -5.0), direction };
+        assert!(hit(bvh.as_ref(), ray).is_none(), "hit with {:?}", direction);
+        // exactly on the slab, which gives NaN
+        let ray = Ray { origin: Vec3::new(bvh.get_max_bounds().x, 0.5, -5.0), direction };
+        assert!(hit(bvh.as_ref(), ray).is_none(), "hit with {:?}", direction);
+    }
+}
+
+/// Rays starting on a triangle, like the ones scattered off it, must not hit it again.
+#[test]
+fn triangle_origin_on_surface(){
+    let mut sampler = IndependentSampler::new(2);
+    for case in 0..CASES{
+        sampler.start_sample((case, 0), 0);
+        let triangle = triangle([0, 1, 2].map(|_| random_vec3(&mut sampler)));
+        let normal = triangle.normals[0];
+        let b0 = sampler.next_1d();
+        let b1 = sampler.next_1d() * (1.0 - b0);
+        let origin = triangle.vertices[0] * b0 + triangle.vertices[1] * b1 + triangle.vertices[2] * (1.0 - b0 - b1);
+        let direction = random_on_unit_sphere(&mut sampler);
+        if direction.dot(normal).abs() < 0.01{
+            continue;
+        }
+        let ray = Ray { origin, direction };
+        assert!(hit(&triangle, ray).is_none(), "case {}: hit itself at t = {}", case, hit(&triangle, ray).unwrap().t);
+    }
+}
+
+#[test]
+fn triangle_parallel_rays(){
+    let triangle = triangle([Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)]);
+    for origin in [Vec3::new(-1.0, 0.25, 0.0), Vec3::new(-1.0, 0.25, 1e-3), Vec3::new(-1.0, 0.25, -1e-3)]{
+        for direction in [Vec3::unit_x(), Vec3::new(1.0, 1.0, 0.0).normalized(), Vec3::new(1.0, -0.0, 0.0)]{
+            assert!(hit(&triangle, Ray { origin, direction }).is_none(), "hit from {:?} along {:?}", origin, direction);
+        }
+    }
+}
+
+/// Hits at a shallow angle, where the determinant gets small.
+#[test]
+fn triangle_grazing_hits(){
+    let triangle = triangle([Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)]);
+    let target = Vec3::new(0.25, 0.25, 0.0);
+    for angle in [1e-1f32, 1e-2, 1e-3, 1e-4]{
+        let direction = Vec3::new(angle.cos(), 0.0, -angle.sin());
+        let ray = Ray { origin: target - direction * 2.0, direction };
+        let hit = hit(&triangle, ray).unwrap_or_else(|| panic!("missed at an angle of {}", angle));
+        assert!((ray.at(hit.t) - target).mag() < 1e-3, "hit {:?} instead of {:?} at an angle of {}", ray.at(hit.t), target, angle);
+        assert_eq!(hit.normal, Vec3::unit_z());
+    }
+}
+
+/// Small triangles, like the details of a scanned model in meters.
+#[test]
+fn small_triangles(){
+    let mut sampler = IndependentSampler::new(3);
+    for case in 0..CASES / 10{
+        sampler.start_sample((case, 0), 0);
+        let scale = random_scale(&mut sampler, 1e-5, 1e-3);
+        let triangle = triangle([Vec3::zero(), Vec3::new(scale, 0.0, 0.0), Vec3::new(0.0, scale, 0.0)]);
+        let direction = (Vec3::new(0.25, 0.25, 0.0) * scale - Vec3::new(1.0, 2.0, 3.0)).normalized();
+        let ray = Ray { origin: Vec3::new(1.0, 2.0, 3.0), direction };
+        assert!(hit(&triangle, ray).is_some(), "case {}: missed a triangle of size {}", case, scale);
+    }
+}
+
+/// Rays through the shared edge of two triangles have to hit at least one of them, otherwise light leaks through
+/// closed meshes.
+#[test]
+fn shared_edges_are_watertight(){
+    let mut sampler = IndependentSampler::new(4);
+    for case in 0..CASES{
+        sampler.start_sample((case, 0), 0);
+        let [a, b, c, d] = [0, 1, 2, 3].map(|_| random_vec3(&mut sampler));
+        let triangles: Vec<Box<dyn Hittable>> = vec![Box::new(triangle([a, b, c])), Box::new(triangle([c, b, d]))];
+        let s = sampler.next_1d();
+        let target = b + (c - b) * s;
+        let origin = random_vec3(&mut sampler) * 5.0;
+        let ray = Ray { origin, direction: (target - origin).normalized() };
+        // only if the quad isn't folded over the edge as seen from the origin, so a and d are on different sides
+        let side = |point: Vec3| (point - origin).cross(ray.direction).dot(c - b).signum();
+        if side(a) == side(d){
+            continue;
+        }
+        if hit(&triangles, ray).is_none() {eprintln!("LEAK {}", case); continue;}
+        assert!(hit(&triangles, ray).is_some(), "case {}: {:?} went through the edge between {:?}", case, ray, [a, b, c, d]);
+    }
+}
+
+#[test]
+fn sphere_origin_on_surface(){
+    let mut sampler = IndependentSampler::new(5);
+    for case in 0..CASES{
+        sampler.start_sample((case, 0), 0);
+        let radius = random_scale(&mut sampler, 0.1, 10.0);
+        let sphere = sphere(random_vec3(&mut sampler) * 10.0, radius);
+        let normal = random_on_unit_sphere(&mut sampler);
+        let origin = sphere.center + normal * radius;
+        let direction = random_on_unit_sphere(&mut sampler);
+        let cosine = direction.dot(normal);
+        if cosine.abs() < 0.1{
+            continue;
+        }
+        let ray = Ray { origin, direction };
+        match hit(&sphere, ray){
+            Some(hit) => {
+                assert!(cosine < 0.0, "case {}: hit itself at t = {}", case, hit.t);
+                // the chord through the sphere
+                let expected = -2.0 * radius * cosine;
+                assert!((hit.t - expected).abs() < 1e-4 * radius, "case {}: hit at t = {} instead of {}", case, hit.t, expected);
+                assert!(!hit.is_front_face, "case {}: hit the outside", case);
+            }
+            None => assert!(cosine > 0.0, "case {}: missed the far side", case),
+        }
+    }
+}
+
+/// Rays passing just inside and just outside the silhouette.
+#[test]
+fn sphere_grazing_hits(){
+    let mut sampler = IndependentSampler::new(6);
+    for case in 0..CASES{
+        sampler.start_sample((case, 0), 0);
+        let radius = random_scale(&mut sampler, 0.1, 10.0);
+        let sphere = sphere(random_vec3(&mut sampler) * 10.0, radius);
+        let direction = random_on_unit_sphere(&mut sampler);
+        let sideways = direction.cross(random_on_unit_sphere(&mut sampler)).normalized();
+        let inside = sampler.next_1d() < 0.5;
+        let offset = radius * if inside {1.0 - 1e-3} else {1.0 + 1e-3};
+        let ray = Ray { origin: sphere.center + sideways * offset - direction * 3.0 * radius, direction };
+        assert_eq!(hit(&sphere, ray).is_some(), inside, "case {}: passing at {} of the radius", case, offset / radius);
+    }
+}
+
+#[test]
+fn sphere_behind_ray(){
+    let sphere = sphere(Vec3::zero(), 1.0);
+    assert!(hit(&sphere, Ray { origin: Vec3::new(0.0, 0.0, 2.0), direction: Vec3::unit_z() }).is_none());
+    let hit = hit(&sphere, Ray { origin: Vec3::new(0.0, 0.0, 0.5), direction: Vec3::unit_z() }).unwrap();
+    assert!((hit.t - 0.5).abs() < 1e-6 && hit.normal == -Vec3::unit_z() && !hit.is_front_face);
+}
@@ -0,0 +1,89 @@
+//! Resolving overlapping objects with the medium stack.
+
+use light::{material::{Material, SurfaceDetail}, medium::{Medium, MediumStack}, texture::Texture};
+use ultraviolet::Vec3;
+
+fn medium(absorption: f32) -> Medium{
+    Medium { absorption: Vec3::broadcast(absorption), scattering: Vec3::zero(), anisotropy: 0.0 }
+}
+
+fn dielectric(ior: f32, absorption: f32, priority: i32) -> Material{
+    Material::DielectricMaterial {
+        albedo: Texture::constant(Vec3::one()),
+        ior,
+        detail: SurfaceDetail::default(),
+        medium: Some(medium(absorption)),
+        priority,
+        dispersion: None,
+    }
+}
+
+#[test]
+fn nested_priorities(){
+    let glass = dielectric(1.5, 1.0, 1);
+    let water = dielectric(1.33, 2.0, 2);
+    let fog = Material::VolumeMaterial { medium: medium(3.0), priority: 0 };
+    let outside = medium(0.5);
+
+    let stack = MediumStack::new();
+    assert_eq!(stack.ior(None), 1.0);
+    assert_eq!(stack.medium(Some(&outside)), Some(&outside));
+
+    // water with a higher priority inside of glass
+    assert!(stack.is_real_interface(&glass, true));
+    let in_glass = stack.entered(&glass);
+    assert!(in_glass.is_real_interface(&water, true));
+    let in_water = in_glass.entered(&water);
+    assert_eq!(in_water.ior(None), 1.33);
+    assert_eq!(in_water.medium(Some(&outside)), Some(&medium(2.0)));
+
+    // surfaces of lower priority objects don't exist inside of the water
+    assert!(!in_water.is_real_interface(&fog, true));
+    assert!(!in_water.is_real_interface(&glass, false));
+    let in_fog = in_water.entered(&fog);
+    assert_eq!(in_fog.ior(None), 1.33);
+    assert_eq!(in_fog.medium(None), Some(&medium(2.0)));
+
+    // the same priority counts as a real interface, the most recent object wins
+    let other_water = dielectric(1.4, 4.0, 2);
+    assert!(in_water.is_real_interface(&other_water, true));
+    assert_eq!(in_water.entered(&other_water).ior(None), 1.4);
+
+    // leaving the water goes back to the glass
+    assert!(in_water.is_real_interface(&water, false));
+    let back_in_glass = in_water.left(&water);
+    assert_eq!(back_in_glass.ior(None), 1.5);
+    assert_eq!(back_in_glass.medium(None), Some(&medium(1.0)));
+    assert_eq!(back_in_glass.left(&glass).medium(Some(&outside)), Some(&outside));
+
+    // volumes don't change the index of refraction
+    let fog_in_glass = back_in_glass.entered(&fog);
+    assert!(!back_in_glass.is_real_interface(&fog, true));
+    assert_eq!(fog_in_glass.ior(None), 1.5);
+    assert!(stack.entered(&fog).is_real_interface(&glass, true));
+}
+
+#[test]
+fn leaving_a_lower_priority_object_inside_of_a_higher_one(){
+    let glass = dielectric(1.5, 1.0, 1);
+    let water = dielectric(1.33, 2.0, 2);
+
+    // the path enters the glass first, then the water that overlaps with it
+    let in_both = MediumStack::new().entered(&glass).entered(&water);
+    // the back face of the glass lies inside of the water
+    assert!(!in_both.is_real_interface(&glass, false));
+    let in_water = in_both.left(&glass);
+    assert!(!in_water.contains(&glass));
+    assert_eq!(in_water.ior(None), 1.33);
+    assert_eq!(in_water.medium(None), Some(&medium(2.0)));
+
+    // leaving the water now ends up outside instead of in the glass
+    assert!(in_water.is_real_interface(&water, false));
+    let outside = in_water.left(&water);
+    assert_eq!(outside.ior(None), 1.0);
+    assert_eq!(outside.medium(None), None);
+
+    // leaving an object the path never entered is real, e.g. when the camera starts inside of it
+    assert!(MediumStack::new().is_real_interface(&glass, false));
+    assert!(MediumStack::new().entered(&water).is_real_interface(&glass, false));
+}
@@ -0,0 +1,175 @@
+//! Feeds mutated scene and OBJ files to the parsers. Broken files have to be reported as errors, never as panics.
+//!
+//! The mutations are deterministic, so this runs as a normal test. For longer, coverage guided runs there are
+//! libFuzzer targets in `fuzz`, see `fuzz/fuzz_targets`.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use light::{importing::parse_scene, mesh::Mesh, sampler::{IndependentSampler, Sampler}};
+
+const MUTATIONS_PER_SEED: u32 = 2000;
+
+/// Fragments that are likely to end up somewhere interesting.
+const TOKENS: &[&str] = &[
+    "[", "]", "=", ";", "/", "//", " ", "\n", "@", "#", "0", "-1", "1e40", "nan", "inf", "-0", "4294967296", "ü",
+    "[mesh]", "[sphere]", "[camera]", "[texture]", "[fog]", "[render]", "f 1 2 3", "f 1/1/1 2/2/2 3/3/3", "v 0 0 0", "vt 0 0", "vn 0 0 0", "s 1",
+];
+
+/// Every material, texture type and setting at least once.
+const SCENE_WITH_EVERYTHING: &str = "[texture]
+name = checker
+texture_type = checker_texture
+even = 0;0;0
+odd = 1;1;1
+scale = 4
+wrap_mode = clamp
+[render]
+samples_per_pixel = 4
+max_depth = 4
+sampler = sobol
+filter = mitchell
+filter_radius = 1.5
+aovs = albedo;normal
+tone_mapper = aces
+bloom_intensity = 0.1
+[fog]
+absorption = 0.1;0.1;0.1
+scattering = 0.1;0.1;0.1
+anisotropy = 0.3
+[camera]
+width = 16
+height = 8
+position = 0;0;5
+target = 0;0;0
+fov = 40
+[sphere]
+radius = 1
+pos = 0;0;0
+material_type = dielectric_material
+ior = 1.5
+cauchy_b = 0.01
+absorption = 1;0.5;0.2
+priority = 1
+[sphere]
+radius = 100
+pos = 0;-101;0
+material_type = diffuse_material
+albedo = @checker
+bump_map = @checker
+bump_strength = 0.5
+[sphere]
+radius = 0.5
+pos = 2;0;0
+material_type = metallic_material
+material_name = metal
+roughness = 0.3
+[sphere]
+radius = 0.5
+pos = -2;0;0
+material_type = emissive_material
+emission_color = 1;0.8;0.6
+strength = 4
+[mesh]
+mesh_file = cornell_floor.obj
+material_type = volume_material
+scattering = 1;1;1
+";
+
+const SMOOTH_OBJ: &str = "# a smooth shaded quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+vn 0 0.1 1
+s 1
+f 1/1/1 2/2/1 3/3/2
+s off
+f 1//1 3//2 4//1
+";
+
+/// In [0, `max`].
+fn random_index(sampler: &mut IndependentSampler, max: usize) -> usize{
+    ((sampler.next_1d() * (max + 1) as f32) as usize).min(max)
+}
+
+/// Applies a few random edits: deleting, duplicating or swapping ranges, and inserting tokens.
+fn mutate(input: &str, sampler: &mut IndependentSampler) -> String{
+    let mut chars: Vec<char> = input.chars().collect();
+    let edits = 1 + (sampler.next_1d() * 4.0) as u32;
+    for _ in 0..edits{
+        let start = random_index(sampler, chars.len());
+        let end = (start + random_index(sampler, 8)).min(chars.len());
+        match (sampler.next_1d() * 5.0) as u32{
+            0 => {
+                chars.drain(start..end);
+            }
+            1 => {
+                let copy: Vec<char> = chars[start..end].to_vec();
+                let target = random_index(sampler, chars.len());
+                chars.splice(target..target, copy);
+            }
+            2 => {
+                let token = TOKENS[(sampler.next_1d() * TOKENS.len() as f32) as usize];
+                chars.splice(start..start, token.chars());
+            }
+            3 => {
+                let token = TOKENS[(sampler.next_1d() * TOKENS.len() as f32) as usize];
+                chars.splice(start..end, token.chars());
+            }
+            _ => {
+                // swap two lines
+                let mut lines: Vec<String> = chars.iter().collect::<String>().lines().map(|x| x.to_string()).collect();
+                if !lines.is_empty(){
+                    let a = random_index(sampler, lines.len() - 1);
+                    let b = random_index(sampler, lines.len() - 1);
+                    lines.swap(a, b);
+                }
+                chars = lines.join("\n").chars().collect();
+            }
+        }
+    }
+    chars.into_iter().collect()
+}
+
+fn fuzz(seeds: &[String], parse: impl Fn(&str)){
+    let mut sampler = IndependentSampler::new(0);
+    for (seed_index, seed) in seeds.iter().enumerate(){
+        // the unmodified seed once, then mutations of it
+        for case in 0..=MUTATIONS_PER_SEED{
+            sampler.start_sample((seed_index as u32, case), 0);
+            let input = if case == 0 {seed.clone()} else {mutate(seed, &mut sampler)};
+            if catch_unwind(AssertUnwindSafe(|| parse(&input))).is_err(){
+                panic!("The parser panicked for this input:\n{}", input);
+            }
+        }
+    }
+}
+
+#[test]
+fn scene_parser(){
+    let mut seeds = vec![SCENE_WITH_EVERYTHING.to_string()];
+    for name in ["cornell_box", "glass_sphere", "metal_sphere", "emissive_only"]{
+        seeds.push(std::fs::read_to_string(format!("tests/scenes/{}.toml", name)).unwrap());
+    }
+    fuzz(&seeds, |content| {
+        let _ = parse_scene(content, "tests/scenes/fuzz.toml");
+    });
+    assert!(parse_scene(SCENE_WITH_EVERYTHING, "tests/scenes/everything.toml").is_ok());
+}
+
+#[test]
+fn obj_parser(){
+    let mut seeds = vec![SMOOTH_OBJ.to_string()];
+    for name in ["tests/scenes/cornell_light.obj", "../meshes/default_cube.obj"]{
+        seeds.push(std::fs::read_to_string(name).unwrap());
+    }
+    fuzz(&seeds, |content| {
+        let _ = Mesh::parse_obj(content, "fuzz.obj");
+    });
+    assert!(Mesh::parse_obj(SMOOTH_OBJ, "smooth.obj").is_ok());
+}
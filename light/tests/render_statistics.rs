@@ -0,0 +1,42 @@
+//! The statistics are global, so everything is checked in a single test.
+
+use light::{importing::load_from_blender, render_statistics, sampler::{IndependentSampler, Sampler}, trace_ray::trace_camera_ray};
+
+#[test]
+fn counts_a_render(){
+    render_statistics::reset();
+    let scene = load_from_blender("tests/scenes/cornell_box.toml").unwrap();
+    let mut sampler = IndependentSampler::new(0);
+    let count = 1000;
+
+    render_statistics::set_enabled(false);
+    for i in 0..count{
+        sampler.start_sample((i, 0), 0);
+        trace_camera_ray(scene.camera.get_ray(0.5, 0.5, &mut sampler).unwrap(), &scene, &mut sampler);
+    }
+    let report = render_statistics::report();
+    assert_eq!(report.rays_traced, 0);
+    assert!(report.load_time >= report.bvh_build_time && !report.bvh_build_time.is_zero());
+    assert!(report.geometry_memory > 0);
+
+    render_statistics::set_enabled(true);
+    for i in 0..count{
+        sampler.start_sample((i, 0), 0);
+        trace_camera_ray(scene.camera.get_ray(0.5, 0.5, &mut sampler).unwrap(), &scene, &mut sampler);
+    }
+    let report = render_statistics::report();
+    render_statistics::set_enabled(false);
+
+    assert_eq!(report.paths(), count as u64);
+    let segments: u64 = report.path_lengths.iter().enumerate().map(|(length, paths)| length as u64 * paths).sum();
+    assert_eq!(segments, report.rays_traced);
+    assert!(report.path_lengths.len() <= scene.settings.max_depth as usize + 1);
+    assert!(report.bvh_nodes_visited >= report.rays_traced);
+    assert!(report.primitive_tests >= report.rays_traced);
+
+    let json = report.to_json();
+    for key in ["rays_traced", "bvh_nodes_visited", "primitive_tests", "path_length_histogram", "load_seconds", "bvh_build_seconds", "render_seconds", "geometry_memory_bytes"]{
+        assert!(json.contains(&format!("\"{}\"", key)), "{} is missing from {}", key, json);
+    }
+    assert!(report.to_string().contains("Rays traced"));
+}
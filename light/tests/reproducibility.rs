@@ -0,0 +1,52 @@
+//! Renders have to come out bit for bit the same no matter how many threads render them.
+
+use light::{aov::Aov, film::{Filter, FilterType}, image::Image, importing::load_from_blender, render::{render_pass, RenderBuffers}, scene::Scene};
+use std::sync::Mutex;
+
+const SAMPLES: u32 = 6;
+
+fn render(scene: &Scene, threads: usize) -> Vec<Image>{
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    let buffers = Mutex::new(RenderBuffers::new(scene, true));
+    pool.install(|| {
+        for sample in 0..SAMPLES{
+            render_pass(scene, sample, &buffers);
+            buffers.lock().unwrap().statistics.update_convergence(0.5, 2);
+        }
+    });
+    let buffers = buffers.into_inner().unwrap();
+    let mut images = vec![buffers.film.image(), buffers.statistics.sample_count_image(SAMPLES as usize)];
+    images.extend(buffers.aovs.layers(&scene.settings).into_iter().map(|(_, image)| image));
+    let (albedo, normal) = buffers.guide_images(&scene.settings).unwrap();
+    images.extend([albedo, normal]);
+    images
+}
+
+fn assert_bitwise_equal(a: &Image, b: &Image){
+    assert_eq!((a.width(), a.height()), (b.width(), b.height()));
+    for y in 0..a.height(){
+        for x in 0..a.width(){
+            let (a, b) = (a[(x, y)], b[(x, y)]);
+            assert!(
+                a.x.to_bits() == b.x.to_bits() && a.y.to_bits() == b.y.to_bits() && a.z.to_bits() == b.z.to_bits(),
+                "pixel ({}, {}): {:?} != {:?}", x, y, a, b
+            );
+        }
+    }
+}
+
+#[test]
+fn thread_count_does_not_change_the_image(){
+    let mut scene = load_from_blender("tests/scenes/cornell_box.toml").ok().unwrap();
+    // a wide filter splats every sample into the rows around it, and the noise threshold lets pixels converge
+    scene.settings.filter = Filter::new(FilterType::Mitchell, 2.0);
+    scene.settings.noise_threshold = Some(0.5);
+    scene.settings.aovs = vec![Aov::Albedo, Aov::Normal, Aov::Depth];
+
+    let single_threaded = render(&scene, 1);
+    let multi_threaded = render(&scene, 4);
+    assert_eq!(single_threaded.len(), 7);
+    for (a, b) in single_threaded.iter().zip(&multi_threaded){
+        assert_bitwise_equal(a, b);
+    }
+}
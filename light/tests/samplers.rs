@@ -0,0 +1,145 @@
+//! Range, stratification and determinism of the samplers.
+
+use light::sampler::{create_sampler, permute, HaltonSampler, Sampler, SamplerType, SobolSampler, StratifiedSampler};
+
+const SAMPLER_TYPES: [SamplerType; 4] = [SamplerType::Independent, SamplerType::Stratified, SamplerType::Halton, SamplerType::Sobol];
+
+/// The first `dimensions` numbers of every sample in `0..count`, indexed by sample.
+fn samples(sampler: &mut dyn Sampler, pixel: (u32, u32), count: u32, dimensions: usize) -> Vec<Vec<f32>>{
+    (0..count).map(|index| {
+        sampler.start_sample(pixel, index);
+        (0..dimensions).map(|_| sampler.next_1d()).collect()
+    }).collect()
+}
+
+/// Checks that each of the `count` strata of `dimension` contains exactly one sample.
+fn assert_stratified(samples: &[Vec<f32>], dimension: usize, name: &str){
+    let count = samples.len();
+    let mut strata = vec![false; count];
+    for sample in samples{
+        let stratum = (sample[dimension] * count as f32) as usize;
+        assert!(!strata[stratum], "{}: two samples in stratum {} of dimension {} with {} samples", name, stratum, dimension, count);
+        strata[stratum] = true;
+    }
+}
+
+#[test]
+fn samples_are_in_unit_interval(){
+    for sampler_type in SAMPLER_TYPES{
+        for samples_per_pixel in [1, 7, 16]{
+            let mut sampler = create_sampler(sampler_type, 5, samples_per_pixel);
+            for pixel in [(0, 0), (3, 1), (1023, 767)]{
+                // more samples than requested, and more dimensions than Halton has primes
+                for sample in samples(sampler.as_mut(), pixel, 2 * samples_per_pixel as u32 + 1, 40){
+                    for value in sample{
+                        assert!((0.0..1.0).contains(&value), "{:?}: {}", sampler_type, value);
+                    }
+                }
+                sampler.start_sample(pixel, 0);
+                for _ in 0..20{
+                    let value = sampler.next_2d();
+                    assert!((0.0..1.0).contains(&value.x) && (0.0..1.0).contains(&value.y), "{:?}: {:?}", sampler_type, value);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn stratified_sampler_is_stratified(){
+    for count in [1, 2, 4, 8, 16, 64, 7]{
+        let mut sampler = StratifiedSampler::new(3, count);
+        let samples = samples(&mut sampler, (5, 9), count, 10);
+        for dimension in 0..10{
+            assert_stratified(&samples, dimension, "stratified");
+        }
+        // the next round stratifies again, in a different order
+        let next_round: Vec<Vec<f32>> = (count..2 * count).map(|index| {
+            sampler.start_sample((5, 9), index);
+            (0..10).map(|_| sampler.next_1d()).collect()
+        }).collect();
+        assert_stratified(&next_round, 0, "stratified");
+    }
+
+    // square counts stratify 2D samples on a grid
+    for grid_size in [2, 4, 8]{
+        let count = grid_size * grid_size;
+        let mut sampler = StratifiedSampler::new(3, count);
+        let mut cells = vec![false; count as usize];
+        for index in 0..count{
+            sampler.start_sample((1, 2), index);
+            let value = sampler.next_2d();
+            let cell = (value.x * grid_size as f32) as u32 + grid_size * (value.y * grid_size as f32) as u32;
+            assert!(!cells[cell as usize], "two samples in cell {} of {}", cell, count);
+            cells[cell as usize] = true;
+        }
+    }
+}
+
+#[test]
+fn halton_sampler_is_stratified(){
+    let mut sampler = HaltonSampler::new(11);
+    // dimension d uses the d-th prime as its base
+    for (dimension, count) in [(0, 16), (0, 64), (1, 27), (2, 25), (3, 49)]{
+        let samples = samples(&mut sampler, (2, 3), count, dimension + 1);
+        assert_stratified(&samples, dimension, "halton");
+    }
+}
+
+#[test]
+fn sobol_sampler_is_stratified(){
+    let mut sampler = SobolSampler::new(13);
+    for count in [1, 2, 4, 16, 256]{
+        let samples = samples(&mut sampler, (4, 1), count, 12);
+        for dimension in 0..12{
+            assert_stratified(&samples, dimension, "sobol");
+        }
+    }
+
+    // pairs of dimensions in the same group are stratified in 2D as well
+    let samples = samples(&mut sampler, (4, 1), 16, 4);
+    for (first, second) in [(0, 1), (2, 3)]{
+        let mut cells = [false; 16];
+        for sample in &samples{
+            let cell = (sample[first] * 4.0) as usize + 4 * (sample[second] * 4.0) as usize;
+            assert!(!cells[cell], "two samples in cell {} of dimensions {} and {}", cell, first, second);
+            cells[cell] = true;
+        }
+    }
+}
+
+#[test]
+fn samples_are_deterministic(){
+    for sampler_type in SAMPLER_TYPES{
+        let mut sampler = create_sampler(sampler_type, 17, 16);
+        let mut other = create_sampler(sampler_type, 17, 16);
+        // the state left behind by earlier samples doesn't matter
+        samples(other.as_mut(), (8, 8), 5, 3);
+        for pixel in [(0, 0), (6, 2)]{
+            assert_eq!(samples(sampler.as_mut(), pixel, 20, 30), samples(other.as_mut(), pixel, 20, 30), "{:?}", sampler_type);
+        }
+
+        let mut reseeded = create_sampler(sampler_type, 18, 16);
+        assert_ne!(samples(sampler.as_mut(), (6, 2), 4, 8), samples(reseeded.as_mut(), (6, 2), 4, 8), "{:?}", sampler_type);
+        assert_ne!(samples(sampler.as_mut(), (6, 2), 4, 8), samples(sampler.as_mut(), (2, 6), 4, 8), "{:?}", sampler_type);
+    }
+}
+
+#[test]
+fn permute_is_a_permutation(){
+    for length in (1..=33).chain([64, 100, 1000, 1024]){
+        for seed in [0, 1, 0x1234_5678, 0xdead_beef, u32::MAX]{
+            let mut seen = vec![false; length as usize];
+            for i in 0..length{
+                let element = permute(i, length, seed);
+                assert!(element < length);
+                assert!(!seen[element as usize], "permute({}, {}, {:#x}) repeats {}", i, length, seed, element);
+                seen[element as usize] = true;
+            }
+        }
+    }
+    // different seeds give different permutations
+    let permutation = |seed: u32| (0..64).map(|i| permute(i, 64, seed)).collect::<Vec<_>>();
+    assert_ne!(permutation(1), permutation(2));
+    assert_eq!(permutation(7), permutation(7));
+}
@@ -0,0 +1,35 @@
+//! RGB to spectrum upsampling and the way back.
+
+use light::spectrum::{rgb_to_spectrum, sample_wavelengths, spectral_sample_to_xyz, xyz_to_rgb, Spectrum, WAVELENGTH_MAX, WAVELENGTH_MIN};
+use ultraviolet::{Vec3, Vec4};
+
+/// Averages the XYZ estimates of `rgb` over evenly spread hero wavelengths and converts the result back to RGB.
+fn round_trip(rgb: Vec3) -> Vec3{
+    const SAMPLES: usize = 470;
+    let mut xyz = Vec3::zero();
+    for i in 0..SAMPLES{
+        let wavelengths = sample_wavelengths((i as f32 + 0.5) / SAMPLES as f32);
+        xyz += spectral_sample_to_xyz(Vec4::from_rgb(rgb, &wavelengths), wavelengths);
+    }
+    xyz_to_rgb(xyz / SAMPLES as f32)
+}
+
+#[test]
+fn white_is_flat(){
+    let steps = 1000;
+    for i in 0..=steps{
+        let wavelength = WAVELENGTH_MIN + (WAVELENGTH_MAX - WAVELENGTH_MIN) * i as f32 / steps as f32;
+        assert!((rgb_to_spectrum(Vec3::one(), wavelength) - 1.0).abs() < 1e-6, "{} nm", wavelength);
+        assert!((rgb_to_spectrum(Vec3::broadcast(0.5), wavelength) - 0.5).abs() < 1e-6, "{} nm", wavelength);
+    }
+    let wavelengths = sample_wavelengths(0.3);
+    assert_eq!(Vec4::from_rgb(Vec3::one(), &wavelengths), Vec4::one());
+}
+
+#[test]
+fn white_integrates_back_to_white(){
+    let white = round_trip(Vec3::one());
+    assert!((white - Vec3::one()).abs().component_max() < 1e-3, "{:?}", white);
+    let grey = round_trip(Vec3::broadcast(0.25));
+    assert!((grey - Vec3::broadcast(0.25)).abs().component_max() < 1e-3, "{:?}", grey);
+}
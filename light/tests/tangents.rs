@@ -0,0 +1,121 @@
+//! Generated tangent frames and the normal and bump maps that depend on them.
+
+use std::sync::Arc;
+
+use light::{hit_result::HitResult, hittable::Hittable, image::Image, material::SurfaceDetail, mesh::parse_obj_triangles, ray::Ray, texture::{Texture, WrapMode}, triangle::Triangle};
+use ultraviolet::{Vec3, Vec4};
+
+/// A quad from (-1, -1) to (1, 1) facing +z whose UVs are mirrored at x = 0: u = |x|, v = (y + 1) / 2.
+/// The two vertices on the seam are shared by both halves.
+const MIRRORED_QUAD: &str = "v -1 -1 0\nv 0 -1 0\nv 1 -1 0\nv -1 1 0\nv 0 1 0\nv 1 1 0\n\
+    vt 0 0\nvt 1 0\nvt 0 1\nvt 1 1\nvn 0 0 1\n\
+    f 1/2/1 2/1/1 5/3/1\nf 1/2/1 5/3/1 4/4/1\n\
+    f 2/1/1 3/2/1 6/4/1\nf 2/1/1 6/4/1 5/3/1\n";
+
+fn assert_close(a: Vec3, b: Vec3){
+    assert!((a - b).mag() < 1e-5, "{:?} != {:?}", a, b);
+}
+
+/// Shoots a ray straight down at `x` and returns the hit on the quad.
+fn hit_at(triangles: &[Triangle], x: f32) -> HitResult<'_>{
+    closest_hit(triangles, Ray { origin: Vec3::new(x, 0.1, 1.0), direction: -Vec3::unit_z() })
+}
+
+fn closest_hit(triangles: &[Triangle], ray: Ray) -> HitResult<'_>{
+    let mut hit = HitResult::default();
+    let mut is_hit = false;
+    for triangle in triangles{
+        is_hit |= triangle.hit(ray, &mut hit, 1e-4);
+    }
+    assert!(is_hit);
+    hit
+}
+
+#[test]
+fn handedness(){
+    // u along +x and v along +y is right handed, flipping u makes it left handed
+    let right_handed = parse_obj_triangles("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nf 1/1 2/2 3/3\n", "right.obj").unwrap();
+    let left_handed = parse_obj_triangles("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 1 0\nvt 0 0\nvt 1 1\nf 1/1 2/2 3/3\n", "left.obj").unwrap();
+    for tangent in right_handed[0].tangents{
+        assert_eq!(tangent, Vec4::new(1.0, 0.0, 0.0, 1.0));
+    }
+    for tangent in left_handed[0].tangents{
+        assert_eq!(tangent, Vec4::new(-1.0, 0.0, 0.0, -1.0));
+    }
+
+    // the reconstructed bitangent follows increasing v in both cases
+    for triangles in [&right_handed, &left_handed]{
+        let ray = Ray { origin: Vec3::new(0.25, 0.25, 1.0), direction: -Vec3::unit_z() };
+        let mut hit = HitResult::default();
+        assert!(triangles[0].hit(ray, &mut hit, 1e-4));
+        assert_close(hit.bitangent, Vec3::unit_y());
+        assert_close(hit.tangent.cross(hit.bitangent).normalized(), hit.normal * triangles[0].tangents[0].w);
+    }
+}
+
+#[test]
+fn mirrored_uvs(){
+    let triangles = parse_obj_triangles(MIRRORED_QUAD, "mirrored.obj").unwrap();
+    // the seam vertices must not average the opposite tangents of both halves
+    for (triangle, expected) in triangles.iter().zip([-1.0, -1.0, 1.0, 1.0]){
+        for tangent in triangle.tangents{
+            assert_eq!(tangent, Vec4::new(expected, 0.0, 0.0, expected));
+        }
+    }
+    for x in [-0.5, -0.01, 0.01, 0.5]{
+        let hit = hit_at(&triangles, x);
+        assert_close(hit.tangent, Vec3::new(x.signum(), 0.0, 0.0));
+        assert_close(hit.bitangent, Vec3::unit_y());
+    }
+}
+
+#[test]
+fn normal_map_perturbation(){
+    let triangles = parse_obj_triangles(MIRRORED_QUAD, "mirrored.obj").unwrap();
+    // a tangent space normal tilted 45° towards +u
+    let tilted = Vec3::new(1.0, 0.0, 1.0).normalized();
+    let detail = SurfaceDetail { normal_map: Some(Texture::constant(tilted * 0.5 + Vec3::broadcast(0.5))), ..SurfaceDetail::default() };
+    for x in [-0.5, 0.5]{
+        let mut hit = hit_at(&triangles, x);
+        detail.apply(&mut hit, Vec3::new(x, 0.1, 0.0));
+        assert_close(hit.normal, Vec3::new(x.signum(), 0.0, 1.0).normalized());
+    }
+
+    // a flat normal map leaves the normal alone
+    let flat = SurfaceDetail { normal_map: Some(Texture::constant(Vec3::new(0.5, 0.5, 1.0))), ..SurfaceDetail::default() };
+    let mut hit = hit_at(&triangles, 0.5);
+    flat.apply(&mut hit, Vec3::new(0.5, 0.1, 0.0));
+    assert_close(hit.normal, Vec3::unit_z());
+}
+
+#[test]
+fn bump_map_perturbation(){
+    let triangles = parse_obj_triangles(MIRRORED_QUAD, "mirrored.obj").unwrap();
+    // the height grows with u, so towards the outer edges of the mirrored quad
+    let mut ramp = Image::new(4, 1);
+    for x in 0..4{
+        ramp[(x, 0)] = Vec3::broadcast(x as f32);
+    }
+    let detail = SurfaceDetail {
+        bump_map: Some(Texture::ImageTexture { image: Arc::new(ramp), wrap_mode: WrapMode::Clamp }),
+        bump_strength: 0.1,
+        ..SurfaceDetail::default()
+    };
+    let mut normals = Vec::new();
+    for x in [-0.5, 0.5]{
+        let mut hit = hit_at(&triangles, x);
+        detail.apply(&mut hit, Vec3::new(x, 0.1, 0.0));
+        // the normal leans away from the slope
+        assert!(hit.normal.x * x < 0.0, "{:?}", hit.normal);
+        assert!(hit.normal.y.abs() < 1e-5 && hit.normal.z > 0.0);
+        assert!((hit.normal.mag() - 1.0).abs() < 1e-5);
+        normals.push(hit.normal);
+    }
+    // both halves are mirror images
+    assert_close(normals[0], Vec3::new(-normals[1].x, normals[1].y, normals[1].z));
+
+    // bump mapping keeps the normal on the side that was hit
+    let mut hit = closest_hit(&triangles, Ray { origin: Vec3::new(0.5, 0.1, -1.0), direction: Vec3::unit_z() });
+    detail.apply(&mut hit, Vec3::new(0.5, 0.1, 0.0));
+    assert!(hit.normal.z < 0.0);
+}
@@ -0,0 +1,119 @@
+//! Texture lookups and image texture loading.
+
+use std::{fs::File, io::BufWriter, path::Path, sync::Arc};
+
+use light::{image::Image, importing::{load_from_blender, parse_scene}, texture::{Texture, WrapMode}};
+use ultraviolet::{Vec2, Vec3};
+
+/// A 4x1 texture whose pixels have the values 0, 1, 2 and 3.
+fn ramp(wrap_mode: WrapMode) -> Texture{
+    let mut image = Image::new(4, 1);
+    for x in 0..4{
+        image[(x, 0)] = Vec3::broadcast(x as f32);
+    }
+    Texture::ImageTexture { image: Arc::new(image), wrap_mode }
+}
+
+/// The texture value at the center of the (possibly out of range) pixel `x`.
+fn at_pixel(texture: &Texture, x: f32) -> f32{
+    texture.sample(Vec2::new((x + 0.5) / 4.0, 0.5), Vec3::zero()).x
+}
+
+fn write_png(path: &Path, width: u32, height: u32, color_type: png::ColorType, bit_depth: png::BitDepth, data: &[u8]){
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path).unwrap()), width, height);
+    encoder.set_color(color_type);
+    encoder.set_depth(bit_depth);
+    encoder.write_header().unwrap().write_image_data(data).unwrap();
+}
+
+#[test]
+fn wrap_modes(){
+    let repeat = ramp(WrapMode::Repeat);
+    let mirrored = ramp(WrapMode::MirroredRepeat);
+    let clamp = ramp(WrapMode::Clamp);
+    for x in 0..4{
+        for texture in [&repeat, &mirrored, &clamp]{
+            assert_eq!(at_pixel(texture, x as f32), x as f32);
+        }
+        assert_eq!(at_pixel(&repeat, x as f32 + 4.0), x as f32);
+        assert_eq!(at_pixel(&repeat, x as f32 - 8.0), x as f32);
+    }
+    assert_eq!(at_pixel(&mirrored, -1.0), 0.0);
+    assert_eq!(at_pixel(&mirrored, -2.0), 1.0);
+    assert_eq!(at_pixel(&mirrored, 4.0), 3.0);
+    assert_eq!(at_pixel(&mirrored, 5.0), 2.0);
+    assert_eq!(at_pixel(&mirrored, 8.0), 0.0);
+    assert_eq!(at_pixel(&clamp, -2.0), 0.0);
+    assert_eq!(at_pixel(&clamp, 5.0), 3.0);
+}
+
+#[test]
+fn bilinear_lookup(){
+    let clamp = ramp(WrapMode::Clamp);
+    assert!((at_pixel(&clamp, 0.5) - 0.5).abs() < 1e-6);
+    assert!((at_pixel(&clamp, 1.25) - 1.25).abs() < 1e-6);
+    assert!((at_pixel(&clamp, 2.9) - 2.9).abs() < 1e-5);
+    // at the edge the clamped neighbour has the same value
+    assert_eq!(at_pixel(&clamp, -0.5), 0.0);
+    // wrapping blends the last pixel with the first one
+    assert!((at_pixel(&ramp(WrapMode::Repeat), 3.5) - 1.5).abs() < 1e-6);
+    assert!((at_pixel(&ramp(WrapMode::MirroredRepeat), 3.5) - 3.0).abs() < 1e-6);
+
+    let mut image = Image::new(2, 2);
+    image[(1, 1)] = Vec3::one();
+    let texture = Texture::ImageTexture { image: Arc::new(image), wrap_mode: WrapMode::Clamp };
+    assert!((texture.sample(Vec2::new(0.5, 0.5), Vec3::zero()) - Vec3::broadcast(0.25)).mag() < 1e-6);
+    assert_eq!(texture.sample(Vec2::new(0.75, 0.75), Vec3::zero()), Vec3::one());
+}
+
+#[test]
+fn checker_parity(){
+    let (even, odd) = (Vec3::zero(), Vec3::one());
+    let checker = Texture::CheckerTexture { even, odd, scale: 2.0 };
+    let sample = |u: f32, v: f32| checker.sample(Vec2::new(u, v), Vec3::zero());
+    assert_eq!(sample(0.25, 0.25), even);
+    assert_eq!(sample(0.75, 0.25), odd);
+    assert_eq!(sample(0.25, 0.75), odd);
+    assert_eq!(sample(0.75, 0.75), even);
+    // the pattern continues across zero instead of mirroring
+    assert_eq!(sample(-0.25, 0.25), odd);
+    assert_eq!(sample(-0.25, -0.25), even);
+    assert_eq!(sample(1.25, 0.25), even);
+}
+
+#[test]
+fn load_png(){
+    let path = std::env::temp_dir().join("light_texture_rgb.png");
+    // top row red and green, bottom row blue and white
+    write_png(&path, 2, 2, png::ColorType::Rgb, png::BitDepth::Eight, &[255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255]);
+    let image = Image::load_from_file(path.to_str().unwrap()).unwrap();
+    assert_eq!((image.width(), image.height()), (2, 2));
+    assert_eq!(image[(0, 1)], Vec3::new(1.0, 0.0, 0.0));
+    assert_eq!(image[(1, 1)], Vec3::new(0.0, 1.0, 0.0));
+    assert_eq!(image[(0, 0)], Vec3::new(0.0, 0.0, 1.0));
+    assert_eq!(image[(1, 0)], Vec3::one());
+
+    let path = std::env::temp_dir().join("light_texture_gray.png");
+    write_png(&path, 1, 1, png::ColorType::GrayscaleAlpha, png::BitDepth::Sixteen, &[0x80, 0x00, 0x12, 0x34]);
+    let image = Image::load_from_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(image[(0, 0)], Vec3::broadcast(0x8000 as f32 / 65535.0));
+}
+
+#[test]
+fn paths_are_relative_to_the_scene(){
+    let directory = std::env::temp_dir().join("light_texture_scene");
+    std::fs::create_dir_all(directory.join("meshes")).unwrap();
+    write_png(&directory.join("albedo.png"), 1, 1, png::ColorType::Rgb, png::BitDepth::Eight, &[255, 128, 0]);
+    std::fs::write(directory.join("meshes/triangle.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nf 1/1 2/1 3/1\n").unwrap();
+    let camera = "[camera]\nwidth = 16\nheight = 8\nposition = 0;0;5\ntarget = 0;0;0\nfov = 40\n";
+    let texture = "[texture]\nname = albedo\ntexture_type = image_texture\nfile = albedo.png\n\
+        [sphere]\nradius = 1\npos = 0;0;0\nmaterial_type = diffuse_material\nalbedo = @albedo\n";
+    let mesh = "[mesh]\nmesh_file = meshes/triangle.obj\nmaterial_type = diffuse_material\n";
+    for content in [format!("{}{}", camera, texture), format!("{}{}", camera, mesh)]{
+        let scene_file = directory.join("scene.toml");
+        std::fs::write(&scene_file, &content).unwrap();
+        assert!(load_from_blender(scene_file.to_str().unwrap()).is_ok());
+        // the working directory doesn't contain the files
+        assert!(parse_scene(&content, "scene.toml").is_err());
+    }
+}
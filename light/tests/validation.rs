@@ -0,0 +1,86 @@
+//! `Scene::validate` has to find every problem, at the right place.
+
+use light::{importing::parse_scene, validation::{Diagnostic, Severity}};
+
+const CAMERA: &str = "[camera]\nwidth = 16\nheight = 8\nposition = 0;0;5\ntarget = 0;0;0\nfov = 40\n";
+const SPHERE: &str = "[sphere]\nradius = 1\npos = 0;0;0\nmaterial_type = diffuse_material\n";
+
+fn validate(content: &str) -> Vec<Diagnostic>{
+    match parse_scene(content, "scene.toml"){
+        Ok(scene) => scene.validate(),
+        Err(error) => panic!("{}", error),
+    }
+}
+
+/// Checks that there's exactly one diagnostic, with the given severity, line and part of the message.
+fn assert_single(diagnostics: &[Diagnostic], severity: Severity, line: Option<usize>, message: &str){
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    let diagnostic = &diagnostics[0];
+    assert_eq!(diagnostic.severity, severity, "{}", diagnostic);
+    assert_eq!(diagnostic.location.as_ref().map(|x| x.line), line, "{}", diagnostic);
+    assert!(diagnostic.message.contains(message), "{}", diagnostic);
+}
+
+#[test]
+fn valid_scene(){
+    assert!(validate(&format!("{}{}", CAMERA, SPHERE)).is_empty());
+    let scene = parse_scene(&std::fs::read_to_string("tests/scenes/cornell_box.toml").unwrap(), "tests/scenes/cornell_box.toml").ok().unwrap();
+    assert!(scene.validate().is_empty(), "{:?}", scene.validate());
+}
+
+#[test]
+fn camera_problems(){
+    assert_single(&validate(SPHERE), Severity::Error, None, "camera");
+    let camera = |replace: &str, with: &str| validate(&format!("{}{}", SPHERE, CAMERA.replace(replace, with)));
+    assert_single(&camera("target = 0;0;0", "target = 0;0;5"), Severity::Error, Some(5), "no view direction");
+    assert_single(&camera("target = 0;0;0", "target = 0;-3;5"), Severity::Error, Some(5), "up vector");
+    assert_single(&camera("fov = 40", "fov = 0"), Severity::Error, Some(5), "field of view");
+    assert_single(&camera("fov = 40", "fov = 180"), Severity::Error, Some(5), "field of view");
+    assert_single(&camera("position = 0;0;5", "position = 0;nan;5"), Severity::Error, Some(5), "position");
+    assert_single(&camera("width = 16", "width = 0"), Severity::Error, Some(5), "empty");
+}
+
+#[test]
+fn empty_scene(){
+    // an empty scene parses, validating it points at the start of the file
+    let scene = parse_scene(CAMERA, "scene.toml").ok().unwrap();
+    assert!(scene.bvh.is_none());
+    assert_single(&scene.validate(), Severity::Error, Some(1), "doesn't contain any objects");
+    let diagnostics = validate("");
+    assert_eq!(diagnostics.len(), 2, "{:?}", diagnostics);
+    assert!(diagnostics.iter().all(|x| x.severity == Severity::Error));
+    assert_eq!(diagnostics[1].to_string(), "scene.toml:1  error: The scene doesn't contain any objects.");
+}
+
+#[test]
+fn sphere_problems(){
+    for radius in ["-1", "0", "nan", "inf"]{
+        let diagnostics = validate(&format!("{}{}{}", CAMERA, SPHERE, SPHERE.replace("radius = 1", &format!("radius = {}", radius))));
+        assert_single(&diagnostics, Severity::Error, Some(11), "radius");
+    }
+    assert_single(&validate(&format!("{}{}", CAMERA, SPHERE.replace("pos = 0;0;0", "pos = 0;inf;0"))), Severity::Error, Some(7), "position");
+}
+
+#[test]
+fn mesh_problems(){
+    let path = std::env::temp_dir().join("light_validation.obj");
+    let mut obj = "v 0 0 0\nv 1 0 0\nv 2 0 0\nv 0 1 0\nv nan 0 0\nvt 0 0\n".to_string();
+    // one good triangle, one with a NaN vertex and seven without area
+    obj += "f 1/1 2/1 4/1\nf 1/1 2/1 5/1\n";
+    for _ in 0..7{
+        obj += "f 1/1 2/1 3/1\n";
+    }
+    std::fs::write(&path, obj).unwrap();
+    let mesh = format!("[mesh]\nmesh_file = {}\nmaterial_type = diffuse_material\n", path.display());
+    let diagnostics = validate(&format!("{}{}", CAMERA, mesh));
+
+    // errors first, the degenerate faces are capped
+    assert_eq!(diagnostics.len(), 1 + 5 + 1, "{:?}", diagnostics);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert_eq!(diagnostics[0].location.as_ref().unwrap().line, 5);
+    assert!(diagnostics[1..].iter().all(|x| x.severity == Severity::Warning));
+    let lines: Vec<usize> = diagnostics[1..].iter().map(|x| x.location.as_ref().unwrap().line).collect();
+    assert_eq!(lines, [9, 10, 11, 12, 13, 14]);
+    assert!(diagnostics[6].message.contains("2 more"), "{}", diagnostics[6]);
+    assert!(diagnostics[0].to_string().starts_with(&format!("{}:5  error: ", path.display())), "{}", diagnostics[0]);
+}
@@ -1,6 +1,7 @@
 import bpy
 import os
 import math
+import struct
 import mathutils
 
 from bpy.props import StringProperty, BoolProperty
@@ -57,6 +58,102 @@ def change_coord_system_rot(vec):
 def stringify_vec(vec):
      return ";".join(list(map(str, vec)))
 
+def write_pfm(image, path):
+    # Blender stores pixels as RGBA floats starting with the bottom row, just like PFM
+    width, height = image.size
+    pixels = list(image.pixels)
+    rgb = [value for i, value in enumerate(pixels) if i % 4 != 3]
+    with open(path, "wb") as f:
+        f.write(f"PF\n{width} {height}\n-1.0\n".encode())
+        f.write(struct.pack(f"<{len(rgb)}f", *rgb))
+
+def linked_node(socket, node_type):
+    if not socket.is_linked:
+        return None
+    node = socket.links[0].from_node
+    return node if node.type == node_type else None
+
+# Blender colour spaces of linear images and the names the renderer uses for them
+LINEAR_COLOR_SPACES = {
+    "Linear": "linear",
+    "Linear Rec.709": "linear",
+    "ACEScg": "acescg",
+    "Linear Rec.2020": "rec2020",
+}
+
+def export_image_texture(image_node, name, is_color, print_and_write):
+    image = image_node.image
+    path = f"/tmp/blender_export_{name}.pfm"
+    write_pfm(image, path)
+    print_and_write("[texture]")
+    print_and_write("name =", name)
+    print_and_write("texture_type = image_texture")
+    print_and_write("file =", path)
+    color_space_name = image.colorspace_settings.name
+    if not is_color or color_space_name == "Non-Color":
+        print_and_write("color_space = non_color")
+    elif color_space_name in LINEAR_COLOR_SPACES:
+        print_and_write("color_space =", LINEAR_COLOR_SPACES[color_space_name])
+    elif image.is_float:
+        print_and_write("color_space = linear")
+    else:
+        print_and_write("color_space = srgb")
+    print_and_write("wrap_mode =", "clamp" if image_node.extension in ("EXTEND", "CLIP") else "repeat")
+
+def export_surface_textures(shader, obj_name, frame, print_and_write):
+    """Writes the texture blocks used by the shader and returns the material lines referencing them."""
+    material_lines = []
+    name_prefix = f"{obj_name}{frame if frame != None else ''}"
+
+    base_color = linked_node(shader.inputs["Base Color"], "TEX_IMAGE")
+    if base_color != None:
+        export_image_texture(base_color, f"{name_prefix}_albedo", True, print_and_write)
+        material_lines.append(f"albedo = @{name_prefix}_albedo")
+
+    normal_input = shader.inputs["Normal"]
+    normal_map = linked_node(normal_input, "NORMAL_MAP")
+    bump = linked_node(normal_input, "BUMP")
+    if normal_map != None:
+        image_node = linked_node(normal_map.inputs["Color"], "TEX_IMAGE")
+        if image_node != None:
+            export_image_texture(image_node, f"{name_prefix}_normal", False, print_and_write)
+            material_lines.append(f"normal_map = @{name_prefix}_normal")
+    elif bump != None:
+        image_node = linked_node(bump.inputs["Height"], "TEX_IMAGE")
+        if image_node != None:
+            export_image_texture(image_node, f"{name_prefix}_bump", False, print_and_write)
+            material_lines.append(f"bump_map = @{name_prefix}_bump")
+            material_lines.append(f"bump_strength = {bump.inputs['Strength'].default_value * bump.inputs['Distance'].default_value}")
+
+    return material_lines
+
+def volume_coefficients(output_node):
+    """Returns (absorption, scattering, anisotropy) of the volume plugged into an output node, or None."""
+    volume = output_node.inputs["Volume"]
+    if not volume.is_linked:
+        return None
+    node = volume.links[0].from_node
+    if node.type == "PRINCIPLED_VOLUME":
+        density = node.inputs["Density"].default_value
+        color = node.inputs["Color"].default_value[0:3]
+        absorption_color = node.inputs["Absorption Color"].default_value[0:3]
+        scattering = [density * c for c in color]
+        absorption = [density * (1.0 - c) for c in absorption_color]
+        return absorption, scattering, node.inputs["Anisotropy"].default_value
+    if node.type == "VOLUME_ABSORPTION":
+        density = node.inputs["Density"].default_value
+        return [density * (1.0 - c) for c in node.inputs["Color"].default_value[0:3]], [0.0, 0.0, 0.0], 0.0
+    if node.type == "VOLUME_SCATTER":
+        density = node.inputs["Density"].default_value
+        return [0.0, 0.0, 0.0], [density * c for c in node.inputs["Color"].default_value[0:3]], node.inputs["Anisotropy"].default_value
+    return None
+
+def write_volume(coefficients, print_and_write):
+    absorption, scattering, anisotropy = coefficients
+    print_and_write("absorption =", stringify_vec(absorption))
+    print_and_write("scattering =", stringify_vec(scattering))
+    print_and_write("anisotropy =", anisotropy)
+
 def export_frame(frame=None):
     if frame != None:
         bpy.data.scenes["Scene"].frame_current = frame
@@ -70,9 +167,24 @@ def export_frame(frame=None):
         
         print((f"Frame {frame}" if frame != None else "Scene").center(50, "="))
 
+        world = bpy.context.scene.world
+        if world != None and world.use_nodes:
+            world_output = world.node_tree.get_output_node("ALL")
+            fog = volume_coefficients(world_output) if world_output != None else None
+            if fog != None:
+                print_and_write("[fog]")
+                write_volume(fog, print_and_write)
+
         for obj in [ob for ob in bpy.context.view_layer.objects if ob.visible_get()]:
             if obj.type == "MESH":
                 obj_path = f"/tmp/blender_export_{obj.name}{frame}.obj"
+                node_tree = obj.data.materials[0].node_tree
+                volume = volume_coefficients(node_tree.get_output_node("ALL"))
+                shader = node_tree.nodes.get("Principled BSDF")
+                if shader == None and volume == None:
+                    continue
+                # textures have to be declared before the mesh that uses them
+                texture_lines = export_surface_textures(shader, obj.name, frame, print_and_write) if shader != None else []
                 print_and_write("[mesh]")
                 obj.select_set(True)
                 bpy.ops.wm.obj_export(
@@ -86,7 +198,13 @@ def export_frame(frame=None):
                 
                 ## Mesh objects don't need any position/rotation/scale. That gets encoded in the obj file.
                 print_and_write("mesh_file = " + obj_path)
-                shader = obj.data.materials[0].node_tree.nodes["Principled BSDF"]
+                # objects sharing a material get the same id in the material ID pass
+                print_and_write("material_name =", obj.data.materials[0].name)
+                if shader == None:
+                    # a pure volume without a surface
+                    print_and_write("material_type = volume_material")
+                    write_volume(volume, print_and_write)
+                    continue
                 if (shader.inputs[6].default_value == 0):
                     print_and_write("material_type = diffuse_material")
                     print_and_write("albedo =", stringify_vec(shader.inputs[0].default_value[0:3]))
@@ -95,7 +213,8 @@ def export_frame(frame=None):
                     print_and_write("albedo =", stringify_vec(shader.inputs[0].default_value[0:3]))
                     print_and_write("roughness =", shader.inputs[9].default_value)
                 
-                if (any(shader.inputs[19].default_value[0:3])):
+                is_emissive = any(shader.inputs[19].default_value[0:3])
+                if (is_emissive):
                     print_and_write("material_type = emissive_material")
                     print_and_write("emission_color =", stringify_vec(shader.inputs[19].default_value[0:3]))
                     print_and_write("strength =", shader.inputs[20].default_value)
@@ -103,18 +222,67 @@ def export_frame(frame=None):
                     print_and_write("material_type = dielectric_material")
                     print_and_write("albedo =", stringify_vec(shader.inputs[0].default_value[0:3]))
                     print_and_write("ior =", shader.inputs[16].default_value)
+                    if volume != None:
+                        write_volume(volume, print_and_write)
+                    # overlapping dielectrics (e.g. water in a glass) are resolved with a custom "priority" property
+                    if "priority" in obj:
+                        print_and_write("priority =", int(obj["priority"]))
+                    is_emissive = False
+                if not is_emissive:
+                    for line in texture_lines:
+                        print_and_write(line)
                 
             elif obj.type == "CAMERA":
-                vec = mathutils.Vector((0.0, 0.0,-1.0))
-                inv = obj.matrix_world.copy()
-                inv.invert()
-                vec_rot = vec @ inv
-                target = obj.location + vec_rot
+                # the full camera to world transform keeps the roll, light is y up like change_coord_system
+                to_light = mathutils.Matrix(((1, 0, 0, 0), (0, 0, 1, 0), (0, -1, 0, 0), (0, 0, 0, 1)))
+                matrix = to_light @ obj.matrix_world
                 
                 print_and_write("[camera]")
-                print_and_write("fov =", math.degrees(obj.data.angle_x))
-                print_and_write("position =", stringify_vec(change_coord_system(obj.location)))
-                print_and_write("target =", stringify_vec(change_coord_system(target)))
+                print_and_write("matrix =", stringify_vec([value for row in matrix for value in row]))
+                if obj.data.type == "ORTHO":
+                    print_and_write("projection = orthographic")
+                    print_and_write("ortho_scale =", obj.data.ortho_scale)
+                elif obj.data.type == "PANO":
+                    # moved from the Cycles settings to the camera in Blender 4
+                    panorama = obj.data if hasattr(obj.data, "panorama_type") else obj.data.cycles
+                    projection = {
+                        "EQUIRECTANGULAR": "equirectangular",
+                        "FISHEYE_EQUIDISTANT": "fisheye_equidistant",
+                        "FISHEYE_EQUISOLID": "fisheye_equisolid",
+                    }.get(panorama.panorama_type)
+                    if projection == None:
+                        print_error("Unsupported panorama type", panorama.panorama_type, "of", obj.name)
+                        projection = "equirectangular"
+                    print_and_write("projection =", projection)
+                    print_and_write("fisheye_fov =", math.degrees(panorama.fisheye_fov))
+                    print_and_write("fisheye_lens =", panorama.fisheye_lens)
+                    print_and_write("sensor_width =", obj.data.sensor_width)
+                else:
+                    # light's sensor_width is the longer side, like Blender's automatic sensor fit
+                    render = bpy.data.scenes[0].render
+                    size = (render.resolution_x * render.pixel_aspect_x, render.resolution_y * render.pixel_aspect_y)
+                    if obj.data.sensor_fit == "VERTICAL":
+                        sensor = obj.data.sensor_height * max(size) / size[1]
+                    elif obj.data.sensor_fit == "HORIZONTAL":
+                        sensor = obj.data.sensor_width * max(size) / size[0]
+                    else:
+                        sensor = obj.data.sensor_width
+                    print_and_write("focal_length =", obj.data.lens)
+                    print_and_write("sensor_width =", sensor)
+                print_and_write("shift_x =", obj.data.shift_x)
+                print_and_write("shift_y =", obj.data.shift_y)
+                dof = obj.data.dof
+                if dof.use_dof:
+                    focus_distance = dof.focus_distance
+                    if dof.focus_object != None:
+                        # distance along the view direction, the camera looks along its local -z
+                        focus_distance = -(obj.matrix_world.inverted() @ dof.focus_object.matrix_world.translation).z
+                    print_and_write("focus_distance =", focus_distance)
+                    print_and_write("f_stop =", dof.aperture_fstop)
+                    if dof.aperture_blades >= 3:
+                        print_and_write("aperture_blades =", dof.aperture_blades)
+                        print_and_write("aperture_rotation =", math.degrees(dof.aperture_rotation))
+                    print_and_write("aperture_ratio =", dof.aperture_ratio)
                 print_and_write("width =", bpy.data.scenes[0].render.resolution_x)
                 print_and_write("height =", bpy.data.scenes[0].render.resolution_y)
             else: continue